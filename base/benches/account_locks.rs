@@ -0,0 +1,143 @@
+//! Concurrent deposits against a storage with simulated round-trip latency.
+//! With per-account locks, deposits on distinct accounts overlap their I/O,
+//! while deposits on a single account are serialized.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use base::{
+    dto::{
+        search::{SearchCursor, TransactionSearchDTO},
+        transaction::DepositTransactionDTO,
+    },
+    lock::AccountLocks,
+    model::{Account, AccountUpdate, Details, Transaction},
+    storage::{AccountCreation, InMemoryStorage, Storage},
+    use_case::{DepositUseCase, UseCase},
+};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::future::join_all;
+use uuid::Uuid;
+
+const DEPOSITS: usize = 32;
+const LATENCY: Duration = Duration::from_millis(1);
+
+/// Delays every call as a remote database would.
+struct SlowStorage(InMemoryStorage);
+
+#[async_trait]
+impl Storage for SlowStorage {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        tokio::time::sleep(LATENCY).await;
+        self.0.save_account(account).await
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        tokio::time::sleep(LATENCY).await;
+        self.0.get_account(uuid).await
+    }
+
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        tokio::time::sleep(LATENCY).await;
+        self.0
+            .save_transactions(created_transactions, updated_accounts)
+            .await
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.0.get_transactions(account_id, from, to, limit).await
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        self.0.get_transaction(id).await
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.0.get_operation_transactions(operation_id).await
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        self.0
+            .get_idempotency_key(account_id, idempotency_key)
+            .await
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.0.search_transactions(query, after, limit).await
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        self.0.health_check().await
+    }
+
+    async fn close(&self) -> Result<(), String> {
+        self.0.close().await
+    }
+}
+
+async fn run_deposits(use_case: &DepositUseCase<SlowStorage>, accounts: &[Uuid]) {
+    let deposits = (0..DEPOSITS).map(|i| {
+        use_case.execute(DepositTransactionDTO {
+            idempotency_key: Uuid::new_v4().to_string(),
+            account_id: accounts[i % accounts.len()],
+            amount: BigDecimal::from(1),
+            details: Details::default(),
+            expected_version: None,
+        })
+    });
+    for result in join_all(deposits).await {
+        result.unwrap();
+    }
+}
+
+fn bench_deposits(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let storage = Arc::new(SlowStorage(InMemoryStorage::new()));
+    let use_case = DepositUseCase::new(&storage, &Arc::new(AccountLocks::default()));
+    let accounts: Vec<Uuid> = (0..DEPOSITS).map(|_| Uuid::new_v4()).collect();
+    runtime.block_on(async {
+        for uuid in &accounts {
+            let account = Account::new(*uuid, &"BRL".to_string());
+            storage.save_account(account).await.unwrap();
+        }
+    });
+
+    let mut group = c.benchmark_group("deposits");
+    group.sample_size(10);
+    group.bench_function("distinct_accounts", |b| {
+        b.to_async(&runtime)
+            .iter(|| run_deposits(&use_case, &accounts))
+    });
+    group.bench_function("same_account", |b| {
+        b.to_async(&runtime)
+            .iter(|| run_deposits(&use_case, &accounts[..1]))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_deposits);
+criterion_main!(benches);
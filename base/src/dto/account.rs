@@ -1,16 +1,29 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::model::Account;
+use crate::model::{Account, Details};
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct AccountCreationDTO {
     pub uuid: Uuid,
     pub currency: String,
+    #[serde(flatten)]
+    pub details: Details,
+}
+
+/// The account a creation request resolved to. `created` is false when an
+/// identical earlier request had already created it.
+#[derive(Debug, Clone)]
+pub struct AccountCreationResultDTO {
+    pub account: Account,
+    pub created: bool,
 }
 
 impl Into<Account> for AccountCreationDTO {
     fn into(self) -> Account {
-        Account::new(self.uuid, &self.currency)
+        Account {
+            details: self.details,
+            ..Account::new(self.uuid, &self.currency)
+        }
     }
 }
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use super::{
+    account::AccountCreationDTO,
+    transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+/// One line of an import file. JSONL lines carry the same fields as the HTTP
+/// request bodies plus an `operation` tag; CSV files use those names as
+/// column headers and leave the columns an operation doesn't need empty.
+/// Metadata entries go in `metadata.<key>` columns.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum ImportOperation {
+    CreateAccount(AccountCreationDTO),
+    Deposit(DepositTransactionDTO),
+    Withdrawal(WithdrawalTransactionDTO),
+    Transfer(TransferTransactionDTO),
+}
+
+impl ImportOperation {
+    pub fn account_ids(&self) -> Vec<Uuid> {
+        match self {
+            ImportOperation::CreateAccount(input) => vec![input.uuid],
+            ImportOperation::Deposit(input) => vec![input.account_id],
+            ImportOperation::Withdrawal(input) => vec![input.account_id],
+            ImportOperation::Transfer(input) => {
+                vec![input.from_account_id, input.to_account_id]
+            }
+        }
+    }
+}
+
+pub struct BulkImportDTO {
+    pub content: String,
+    pub format: ImportFormat,
+    /// 1-based line number to start from. Earlier lines are skipped, so an
+    /// interrupted import can be resumed; overlapping lines are expected to be
+    /// deduplicated through their idempotency keys.
+    pub resume_from: usize,
+}
+
+const METADATA_COLUMN_PREFIX: &str = "metadata.";
+
+pub type ParsedLine = (usize, Result<ImportOperation, String>);
+
+fn parse_jsonl(content: &str) -> Vec<ParsedLine> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let operation = serde_json::from_str(line).map_err(|e| e.to_string());
+            (index + 1, operation)
+        })
+        .collect()
+}
+
+fn parse_csv(content: &str) -> Vec<ParsedLine> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return vec![(1, Err(e.to_string()))],
+    };
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string());
+            let line = match &record {
+                Ok(record) => record.position().map(|p| p.line() as usize),
+                Err(_) => None,
+            };
+            let operation = record.and_then(|record| {
+                let mut fields = Map::new();
+                let mut metadata = Map::new();
+                for (name, value) in headers.iter().zip(record.iter()) {
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let value = Value::String(value.to_string());
+                    match name.strip_prefix(METADATA_COLUMN_PREFIX) {
+                        Some(key) => metadata.insert(key.to_string(), value),
+                        None => fields.insert(name.to_string(), value),
+                    };
+                }
+                fields.insert("metadata".to_string(), Value::Object(metadata));
+                serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())
+            });
+            (line.unwrap_or_default(), operation)
+        })
+        .collect()
+}
+
+impl BulkImportDTO {
+    pub fn parse(&self) -> Vec<ParsedLine> {
+        let lines = match self.format {
+            ImportFormat::Jsonl => parse_jsonl(&self.content),
+            ImportFormat::Csv => parse_csv(&self.content),
+        };
+        lines
+            .into_iter()
+            .filter(|(line, _)| *line >= self.resume_from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jsonl() {
+        let input = BulkImportDTO {
+            content: [
+                r#"{"operation":"create_account","uuid":"0d6134fa-af04-421d-bb84-06bf3ab7c74c","currency":"BRL"}"#,
+                "",
+                r#"{"operation":"deposit","idempotency_key":"k1","account_id":"0d6134fa-af04-421d-bb84-06bf3ab7c74c","amount":"10.00"}"#,
+                r#"{"operation":"refund"}"#,
+            ]
+            .join("\n"),
+            format: ImportFormat::Jsonl,
+            resume_from: 0,
+        };
+        let lines = input.parse();
+        assert_eq!(lines.len(), 3);
+        assert!(matches!(
+            lines[0],
+            (1, Ok(ImportOperation::CreateAccount(_)))
+        ));
+        assert!(matches!(lines[1], (3, Ok(ImportOperation::Deposit(_)))));
+        assert!(matches!(lines[2], (4, Err(_))));
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let input = BulkImportDTO {
+            content: [
+                "operation,idempotency_key,uuid,currency,account_id,from_account_id,to_account_id,amount,description,metadata.order_id",
+                "create_account,,0d6134fa-af04-421d-bb84-06bf3ab7c74c,BRL,,,,,,",
+                "transfer,k2,,,,0d6134fa-af04-421d-bb84-06bf3ab7c74c,2348a36a-8444-45a3-a130-669144a29793,9.99,Order 42,42",
+                "withdrawal,k3,,,not-a-uuid,,,1.00,,",
+            ]
+            .join("\n"),
+            format: ImportFormat::Csv,
+            resume_from: 3,
+        };
+        let lines = input.parse();
+        assert_eq!(lines.len(), 2);
+        match &lines[0] {
+            (3, Ok(ImportOperation::Transfer(transfer))) => {
+                assert_eq!(transfer.amount.to_string(), "9.99");
+                assert_eq!(transfer.idempotency_key, "k2");
+                assert_eq!(transfer.details.description.as_deref(), Some("Order 42"));
+                assert_eq!(transfer.details.metadata["order_id"], "42");
+            }
+            _ => panic!("expected a transfer on line 3"),
+        }
+        assert!(matches!(lines[1], (4, Err(_))));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod account;
+pub mod import;
+pub mod search;
+pub mod statement;
 pub mod transaction;
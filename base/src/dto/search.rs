@@ -0,0 +1,156 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::model::{OperationKind, Transaction, TransactionStatus};
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 50;
+pub const MAX_SEARCH_LIMIT: usize = 500;
+pub const SEARCH_PARTITION_REQUIRED: &str =
+    "Search requires account_id, external_reference or metadata_key and metadata_value";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionDirection {
+    Credit,
+    Debit,
+}
+
+/// Position of the last transaction of a page. Results are ordered by
+/// creation time, then id, so the pair identifies where the next page starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchCursor {
+    pub created_at_in_nanos: i64,
+    pub id: Uuid,
+}
+
+impl SearchCursor {
+    pub fn of(transaction: &Transaction) -> Self {
+        SearchCursor {
+            created_at_in_nanos: transaction.created_at.timestamp_nanos_opt().unwrap(),
+            id: transaction.id,
+        }
+    }
+
+    pub fn parse(cursor: &str) -> Result<Self, String> {
+        let invalid = || "Invalid cursor".to_string();
+        let (nanos, id) = cursor.split_once(':').ok_or_else(invalid)?;
+        Ok(SearchCursor {
+            created_at_in_nanos: nanos.parse().map_err(|_| invalid())?,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+
+    /// Whether `transaction` sorts strictly after this cursor.
+    pub fn precedes(&self, transaction: &Transaction) -> bool {
+        let nanos = transaction.created_at.timestamp_nanos_opt().unwrap();
+        (nanos, transaction.id) > (self.created_at_in_nanos, self.id)
+    }
+}
+
+impl std::fmt::Display for SearchCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.created_at_in_nanos, self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TransactionSearchDTO {
+    pub account_id: Option<Uuid>,
+    pub external_reference: Option<String>,
+    pub metadata_key: Option<String>,
+    pub metadata_value: Option<String>,
+    pub min_amount: Option<BigDecimal>,
+    pub max_amount: Option<BigDecimal>,
+    pub currency: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub direction: Option<TransactionDirection>,
+    pub kind: Option<OperationKind>,
+    pub status: Option<TransactionStatus>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+impl TransactionSearchDTO {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT)
+    }
+
+    pub fn cursor(&self) -> Result<Option<SearchCursor>, String> {
+        self.cursor.as_deref().map(SearchCursor::parse).transpose()
+    }
+
+    pub fn metadata(&self) -> Option<(&str, &str)> {
+        match (&self.metadata_key, &self.metadata_value) {
+            (Some(key), Some(value)) => Some((key.as_str(), value.as_str())),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_SEARCH_LIMIT).contains(&self.limit()) {
+            return Err(format!("limit must be between 1 and {}", MAX_SEARCH_LIMIT));
+        }
+        if self.metadata_key.is_some() != self.metadata_value.is_some() {
+            return Err("metadata_key and metadata_value must be given together".to_string());
+        }
+        // Cassandra and DynamoDB read one lookup partition per search, so
+        // every backend requires a field naming one.
+        if self.account_id.is_none()
+            && self.external_reference.is_none()
+            && self.metadata_key.is_none()
+        {
+            return Err(SEARCH_PARTITION_REQUIRED.to_string());
+        }
+        if let (Some(min), Some(max)) = (&self.min_amount, &self.max_amount) {
+            if min > max {
+                return Err("min_amount must not exceed max_amount".to_string());
+            }
+        }
+        if let (Some(from), Some(to)) = (&self.from, &self.to) {
+            if from >= to {
+                return Err("from must be before to".to_string());
+            }
+        }
+        self.cursor()?;
+        Ok(())
+    }
+
+    /// Applies every filter to a single transaction. Backends that can only
+    /// narrow results down by one indexed field use this for the rest.
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        let amount = &transaction.amount;
+        self.account_id
+            .is_none_or(|id| transaction.account_id == id)
+            && self
+                .external_reference
+                .as_ref()
+                .is_none_or(|r| transaction.details.external_reference.as_ref() == Some(r))
+            && self.metadata().is_none_or(|(k, v)| {
+                transaction.details.metadata.get(k).map(String::as_str) == Some(v)
+            })
+            && self
+                .min_amount
+                .as_ref()
+                .is_none_or(|min| amount.abs() >= *min)
+            && self
+                .max_amount
+                .as_ref()
+                .is_none_or(|max| amount.abs() <= *max)
+            && self
+                .currency
+                .as_ref()
+                .is_none_or(|c| &transaction.currency == c)
+            && self.from.is_none_or(|from| transaction.created_at >= from)
+            && self.to.is_none_or(|to| transaction.created_at < to)
+            && self.direction.is_none_or(|direction| {
+                (direction == TransactionDirection::Debit) == (*amount < BigDecimal::zero())
+            })
+            && self.kind.is_none_or(|kind| transaction.kind == kind)
+            && self
+                .status
+                .is_none_or(|status| transaction.status == status)
+    }
+}
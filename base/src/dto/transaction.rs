@@ -2,18 +2,31 @@ use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::model::{Account, Transaction};
+use crate::model::{Account, Details, OperationKind, Transaction};
 
 #[derive(Serialize, Deserialize)]
 pub struct DepositTransactionDTO {
     pub idempotency_key: String,
     pub account_id: Uuid,
     pub amount: BigDecimal,
+    #[serde(flatten)]
+    pub details: Details,
+    /// Only apply while the account is at this version; set from `If-Match`.
+    #[serde(skip)]
+    pub expected_version: Option<Uuid>,
 }
 
 impl DepositTransactionDTO {
     pub fn to_transaction(&self, account: &Account) -> Transaction {
-        Transaction::new(account, &self.idempotency_key, &self.amount)
+        Transaction {
+            details: self.details.clone(),
+            ..Transaction::new(
+                account,
+                &self.idempotency_key,
+                &self.amount,
+                OperationKind::Deposit,
+            )
+        }
     }
 }
 
@@ -22,12 +35,25 @@ pub struct WithdrawalTransactionDTO {
     pub idempotency_key: String,
     pub account_id: Uuid,
     pub amount: BigDecimal,
+    #[serde(flatten)]
+    pub details: Details,
+    /// Only apply while the account is at this version; set from `If-Match`.
+    #[serde(skip)]
+    pub expected_version: Option<Uuid>,
 }
 
 impl WithdrawalTransactionDTO {
     pub fn to_transaction(&self, account: &Account) -> Transaction {
         let negative_amount = -&self.amount;
-        Transaction::new(account, &self.idempotency_key, &negative_amount)
+        Transaction {
+            details: self.details.clone(),
+            ..Transaction::new(
+                account,
+                &self.idempotency_key,
+                &negative_amount,
+                OperationKind::Withdrawal,
+            )
+        }
     }
 }
 
@@ -37,14 +63,41 @@ pub struct TransferTransactionDTO {
     pub from_account_id: Uuid,
     pub to_account_id: Uuid,
     pub amount: BigDecimal,
+    #[serde(flatten)]
+    pub details: Details,
+    /// Only apply while the source account is at this version; set from
+    /// `If-Match`.
+    #[serde(skip)]
+    pub expected_version: Option<Uuid>,
 }
 
 impl TransferTransactionDTO {
     pub fn to_transactions(&self, from: &Account, to: &Account) -> (Transaction, Transaction) {
         let negative_amount = -&self.amount;
+        let operation_id = Uuid::new_v4();
         (
-            Transaction::new(from, &self.idempotency_key, &negative_amount),
-            Transaction::new(to, &self.idempotency_key, &self.amount),
+            Transaction {
+                operation_id,
+                counterparty_account_id: Some(to.uuid),
+                details: self.details.clone(),
+                ..Transaction::new(
+                    from,
+                    &self.idempotency_key,
+                    &negative_amount,
+                    OperationKind::TransferOut,
+                )
+            },
+            Transaction {
+                operation_id,
+                counterparty_account_id: Some(from.uuid),
+                details: self.details.clone(),
+                ..Transaction::new(
+                    to,
+                    &self.idempotency_key,
+                    &self.amount,
+                    OperationKind::TransferIn,
+                )
+            },
         )
     }
 }
@@ -1,5 +1,8 @@
 pub mod dto;
+pub mod lock;
+pub mod metrics;
 pub mod model;
+pub mod shutdown;
 pub mod storage;
 pub mod use_case;
 
@@ -0,0 +1,113 @@
+use tokio::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+use crate::utils::shard_of;
+
+pub const DEFAULT_LOCK_STRIPES: usize = 1024;
+
+/// Serializes operations on the same account while letting unrelated
+/// accounts proceed in parallel. Accounts are hashed onto a fixed set of
+/// stripes, so two accounts occasionally share a lock but memory stays
+/// bounded no matter how many accounts exist.
+#[derive(Debug)]
+pub struct AccountLocks {
+    stripes: Vec<Mutex<()>>,
+}
+
+/// Holds the stripes of every locked account until dropped.
+pub struct AccountGuard<'a> {
+    _guards: Vec<MutexGuard<'a, ()>>,
+}
+
+impl AccountLocks {
+    pub fn new(stripes: usize) -> Self {
+        AccountLocks {
+            stripes: (0..stripes.max(1)).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    fn stripe(&self, account_id: &Uuid) -> usize {
+        shard_of(account_id, self.stripes.len())
+    }
+
+    /// Locks every given account. Stripes are always taken in ascending
+    /// order, so operations locking overlapping accounts (e.g. transfers in
+    /// opposite directions) cannot deadlock.
+    pub async fn lock(&self, account_ids: &[Uuid]) -> AccountGuard<'_> {
+        let mut stripes: Vec<usize> = account_ids.iter().map(|id| self.stripe(id)).collect();
+        stripes.sort_unstable();
+        stripes.dedup();
+
+        let mut guards = Vec::with_capacity(stripes.len());
+        for stripe in stripes {
+            guards.push(self.stripes[stripe].lock().await);
+        }
+        AccountGuard { _guards: guards }
+    }
+}
+
+impl Default for AccountLocks {
+    fn default() -> Self {
+        AccountLocks::new(DEFAULT_LOCK_STRIPES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_same_account_is_exclusive() {
+        let locks = AccountLocks::default();
+        let account = Uuid::new_v4();
+
+        let _guard = locks.lock(&[account]).await;
+        let second = tokio::time::timeout(Duration::from_millis(20), locks.lock(&[account])).await;
+        assert!(second.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unrelated_accounts_do_not_block() {
+        let locks = AccountLocks::new(2);
+        let first = Uuid::new_v4();
+        let second = std::iter::repeat_with(Uuid::new_v4)
+            .find(|id| locks.stripe(id) != locks.stripe(&first))
+            .unwrap();
+
+        let _guard = locks.lock(&[first]).await;
+        let other = tokio::time::timeout(Duration::from_millis(20), locks.lock(&[second])).await;
+        assert!(other.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_opposite_transfers_do_not_deadlock() {
+        let locks = Arc::new(AccountLocks::default());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut handles = vec![];
+        for i in 0..100 {
+            let locks = locks.clone();
+            let pair = if i % 2 == 0 { [a, b] } else { [b, a] };
+            handles.push(tokio::spawn(async move {
+                let _guard = locks.lock(&pair).await;
+                tokio::task::yield_now().await;
+            }));
+        }
+        let all = futures::future::join_all(handles);
+        assert!(tokio::time::timeout(Duration::from_secs(5), all)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_accounts_lock_once() {
+        let locks = AccountLocks::default();
+        let account = Uuid::new_v4();
+
+        let guard =
+            tokio::time::timeout(Duration::from_millis(20), locks.lock(&[account, account])).await;
+        assert!(guard.is_ok());
+    }
+}
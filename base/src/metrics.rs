@@ -0,0 +1,98 @@
+//! Prometheus metrics recorded by the use cases and storage decorators. They
+//! live in the default registry, which the server renders on `/metrics`.
+
+use std::sync::LazyLock;
+
+use bigdecimal::ToPrimitive;
+use prometheus::{
+    register_counter_vec, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    CounterVec, HistogramVec, IntCounter, IntCounterVec,
+};
+
+use crate::{
+    model::{Transaction, ACCOUNT_ALREADY_EXISTS, ACCOUNT_VERSION_MISMATCH},
+    storage::{IDEMPOTENCY_KEY_REUSED, STORAGE_TIMEOUT, VERSION_CONFLICT},
+};
+
+pub static USE_CASE_EXECUTIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "ledger_use_case_executions_total",
+        "Use case executions by outcome: ok or the kind of error",
+        &["use_case", "outcome"]
+    )
+    .unwrap()
+});
+
+pub static STORAGE_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "ledger_storage_operation_duration_seconds",
+        "Latency of storage calls",
+        &["backend", "operation", "outcome"]
+    )
+    .unwrap()
+});
+
+pub static VERSION_CONFLICT_RETRIES: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "ledger_version_conflict_retries_total",
+        "Operations retried after a version conflict"
+    )
+    .unwrap()
+});
+
+pub static ACCOUNT_CACHE_LOOKUPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "ledger_account_cache_lookups_total",
+        "Account cache lookups by result: hit or miss",
+        &["result"]
+    )
+    .unwrap()
+});
+
+pub static VOLUME: LazyLock<CounterVec> = LazyLock::new(|| {
+    register_counter_vec!(
+        "ledger_volume_total",
+        "Amount moved by posted operations, replays excluded",
+        &["kind", "currency"]
+    )
+    .unwrap()
+});
+
+/// A bounded label for an error, so outcomes can be counted by kind.
+pub fn error_kind(error: &str) -> &'static str {
+    match error {
+        VERSION_CONFLICT => "version_conflict",
+        IDEMPOTENCY_KEY_REUSED => "idempotency_key_reused",
+        STORAGE_TIMEOUT => "storage_timeout",
+        ACCOUNT_VERSION_MISMATCH => "version_mismatch",
+        ACCOUNT_ALREADY_EXISTS => "already_exists",
+        "Insufficient balance" => "insufficient_balance",
+        _ if error.ends_with("not found") => "not_found",
+        _ => "other",
+    }
+}
+
+/// Adds `attempted` to the volume of `kind` if it is what was saved, and not
+/// a replay that returned an earlier operation instead.
+pub(crate) fn record_volume(kind: &str, attempted: &Transaction, saved: &[Transaction]) {
+    if saved.first().map(|tx| tx.id) != Some(attempted.id) {
+        return;
+    }
+    let amount = attempted.amount.abs().to_f64().unwrap_or_default();
+    VOLUME
+        .with_label_values(&[kind, attempted.currency.as_str()])
+        .inc_by(amount);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(error_kind(VERSION_CONFLICT), "version_conflict");
+        assert_eq!(error_kind("Source account not found"), "not_found");
+        assert_eq!(error_kind("Insufficient balance"), "insufficient_balance");
+        assert_eq!(error_kind("metadata keys must not be blank"), "other");
+    }
+}
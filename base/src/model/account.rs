@@ -3,6 +3,8 @@ use chrono::{self, DateTime, TimeZone, Utc};
 use serde::{self, Serialize};
 use uuid::Uuid;
 
+use super::{details::Details, transaction::Transaction};
+
 use crate::utils::serialize_datetime;
 
 #[derive(Debug, Serialize, Clone, PartialEq)]
@@ -15,6 +17,22 @@ pub struct Account {
     #[serde(serialize_with = "serialize_datetime")]
     pub last_updated_at: DateTime<Utc>,
     pub version: Uuid,
+    #[serde(flatten)]
+    pub details: Details,
+}
+
+pub const ACCOUNT_VERSION_MISMATCH: &str = "Account version mismatch";
+
+/// Returned when creating an account whose id is already taken by an account
+/// with another currency or other details.
+pub const ACCOUNT_ALREADY_EXISTS: &str = "Account already exists with different attributes";
+
+/// New state of an account, to be written only while the stored account is
+/// still at `expected_version`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountUpdate {
+    pub account: Account,
+    pub expected_version: Uuid,
 }
 
 impl Account {
@@ -26,6 +44,7 @@ impl Account {
             created_at: chrono::Utc::now(),
             last_updated_at: chrono::Utc::now(),
             version: Uuid::new_v4(),
+            details: Details::default(),
         }
     }
 
@@ -44,6 +63,29 @@ impl Account {
             created_at: chrono::Utc.timestamp_nanos(created_at_in_nanos),
             last_updated_at: chrono::Utc.timestamp_nanos(last_updated_at_in_nanos),
             version,
+            details: Details::default(),
+        }
+    }
+
+    /// Fails when the caller expected the account at another version, e.g.
+    /// because it changed since the caller read it.
+    pub fn check_version(&self, expected_version: Option<Uuid>) -> Result<(), String> {
+        match expected_version {
+            Some(expected) if expected != self.version => Err(ACCOUNT_VERSION_MISMATCH.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the state `transaction` moves this account to.
+    pub fn apply(&self, transaction: &Transaction) -> AccountUpdate {
+        AccountUpdate {
+            account: Account {
+                balance: transaction.balance_after.clone(),
+                last_updated_at: transaction.created_at,
+                version: transaction.account_version,
+                ..self.clone()
+            },
+            expected_version: self.version,
         }
     }
 }
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+pub type Metadata = BTreeMap<String, String>;
+
+pub const MAX_DESCRIPTION_LENGTH: usize = 256;
+pub const MAX_EXTERNAL_REFERENCE_LENGTH: usize = 128;
+pub const MAX_METADATA_ENTRIES: usize = 16;
+pub const MAX_METADATA_KEY_LENGTH: usize = 64;
+pub const MAX_METADATA_VALUE_LENGTH: usize = 256;
+
+/// Free-form fields callers can attach to accounts and transactions.
+/// Lengths are counted in characters.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Details {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub external_reference: Option<String>,
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+fn check_length(field: &str, value: &str, max: usize) -> Result<(), String> {
+    if value.chars().count() > max {
+        return Err(format!("{} exceeds {} characters", field, max));
+    }
+    Ok(())
+}
+
+impl Details {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(description) = &self.description {
+            check_length("description", description, MAX_DESCRIPTION_LENGTH)?;
+        }
+        if let Some(reference) = &self.external_reference {
+            if reference.trim().is_empty() {
+                return Err("external_reference must not be blank".to_string());
+            }
+            check_length(
+                "external_reference",
+                reference,
+                MAX_EXTERNAL_REFERENCE_LENGTH,
+            )?;
+        }
+        if self.metadata.len() > MAX_METADATA_ENTRIES {
+            return Err(format!("metadata exceeds {} entries", MAX_METADATA_ENTRIES));
+        }
+        for (key, value) in &self.metadata {
+            if key.trim().is_empty() {
+                return Err("metadata keys must not be blank".to_string());
+            }
+            if key.contains('=') {
+                return Err("metadata keys must not contain '='".to_string());
+            }
+            check_length("metadata key", key, MAX_METADATA_KEY_LENGTH)?;
+            check_length("metadata value", value, MAX_METADATA_VALUE_LENGTH)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_successful() {
+        let details = Details {
+            description: Some("Order payment".to_string()),
+            external_reference: Some("order-42".to_string()),
+            metadata: vec![("order_id".to_string(), "42".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        assert!(details.validate().is_ok());
+        assert!(Details::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_limits() {
+        let too_long = Details {
+            description: Some("x".repeat(MAX_DESCRIPTION_LENGTH + 1)),
+            ..Details::default()
+        };
+        assert_eq!(
+            too_long.validate().unwrap_err(),
+            "description exceeds 256 characters"
+        );
+
+        let blank_reference = Details {
+            external_reference: Some(" ".to_string()),
+            ..Details::default()
+        };
+        assert!(blank_reference.validate().is_err());
+
+        let too_many = Details {
+            metadata: (0..=MAX_METADATA_ENTRIES)
+                .map(|i| (format!("key_{}", i), "value".to_string()))
+                .collect(),
+            ..Details::default()
+        };
+        assert_eq!(
+            too_many.validate().unwrap_err(),
+            "metadata exceeds 16 entries"
+        );
+
+        let long_value = Details {
+            metadata: vec![("key".to_string(), "v".repeat(MAX_METADATA_VALUE_LENGTH + 1))]
+                .into_iter()
+                .collect(),
+            ..Details::default()
+        };
+        assert!(long_value.validate().is_err());
+
+        let separator_in_key = Details {
+            metadata: vec![("a=b".to_string(), "c".to_string())]
+                .into_iter()
+                .collect(),
+            ..Details::default()
+        };
+        assert!(separator_in_key.validate().is_err());
+    }
+}
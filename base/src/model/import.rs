@@ -0,0 +1,47 @@
+use futures::stream::BoxStream;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Ok,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportLineResult {
+    pub line: usize,
+    pub status: ImportStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transaction_ids: Vec<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ImportLineResult {
+    pub fn ok(line: usize, account_id: Option<Uuid>, transaction_ids: Vec<Uuid>) -> Self {
+        ImportLineResult {
+            line,
+            status: ImportStatus::Ok,
+            account_id,
+            transaction_ids,
+            error: None,
+        }
+    }
+
+    pub fn error(line: usize, error: String) -> Self {
+        ImportLineResult {
+            line,
+            status: ImportStatus::Error,
+            account_id: None,
+            transaction_ids: vec![],
+            error: Some(error),
+        }
+    }
+}
+
+/// Per-line results, yielded in input order as each line completes.
+pub type ImportReport = BoxStream<'static, ImportLineResult>;
@@ -1,5 +1,20 @@
 mod account;
+mod details;
+mod import;
+mod operation;
+mod search;
+mod statement;
 mod transaction;
+mod transfer;
 
-pub use account::Account;
+pub use account::{Account, AccountUpdate, ACCOUNT_ALREADY_EXISTS, ACCOUNT_VERSION_MISMATCH};
+pub use details::{
+    Details, Metadata, MAX_DESCRIPTION_LENGTH, MAX_EXTERNAL_REFERENCE_LENGTH, MAX_METADATA_ENTRIES,
+    MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
+};
+pub use import::{ImportLineResult, ImportReport, ImportStatus};
+pub use operation::{OperationKind, TransactionStatus};
+pub use search::TransactionPage;
+pub use statement::{Statement, StatementEntry, StatementHeader, StatementLine, StatementSummary};
 pub use transaction::Transaction;
+pub use transfer::Transfer;
@@ -0,0 +1,119 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Deposit,
+    Withdrawal,
+    TransferOut,
+    TransferIn,
+    Fee,
+    Interest,
+    Reversal,
+    Adjustment,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Pending,
+    Posted,
+    Failed,
+    Reversed,
+}
+
+impl OperationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperationKind::Deposit => "deposit",
+            OperationKind::Withdrawal => "withdrawal",
+            OperationKind::TransferOut => "transfer_out",
+            OperationKind::TransferIn => "transfer_in",
+            OperationKind::Fee => "fee",
+            OperationKind::Interest => "interest",
+            OperationKind::Reversal => "reversal",
+            OperationKind::Adjustment => "adjustment",
+        }
+    }
+}
+
+impl TransactionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionStatus::Pending => "pending",
+            TransactionStatus::Posted => "posted",
+            TransactionStatus::Failed => "failed",
+            TransactionStatus::Reversed => "reversed",
+        }
+    }
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OperationKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(OperationKind::Deposit),
+            "withdrawal" => Ok(OperationKind::Withdrawal),
+            "transfer_out" => Ok(OperationKind::TransferOut),
+            "transfer_in" => Ok(OperationKind::TransferIn),
+            "fee" => Ok(OperationKind::Fee),
+            "interest" => Ok(OperationKind::Interest),
+            "reversal" => Ok(OperationKind::Reversal),
+            "adjustment" => Ok(OperationKind::Adjustment),
+            _ => Err(format!("Unknown operation kind: {}", s)),
+        }
+    }
+}
+
+impl FromStr for TransactionStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(TransactionStatus::Pending),
+            "posted" => Ok(TransactionStatus::Posted),
+            "failed" => Ok(TransactionStatus::Failed),
+            "reversed" => Ok(TransactionStatus::Reversed),
+            _ => Err(format!("Unknown transaction status: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_round_trip() {
+        for kind in [
+            OperationKind::Deposit,
+            OperationKind::TransferOut,
+            OperationKind::Adjustment,
+        ] {
+            assert_eq!(kind.as_str().parse::<OperationKind>(), Ok(kind));
+            assert_eq!(
+                serde_json::to_string(&kind).unwrap(),
+                format!("\"{}\"", kind)
+            );
+        }
+        for status in [TransactionStatus::Pending, TransactionStatus::Reversed] {
+            assert_eq!(status.as_str().parse::<TransactionStatus>(), Ok(status));
+        }
+        assert!("refund".parse::<OperationKind>().is_err());
+    }
+}
@@ -0,0 +1,10 @@
+use serde::Serialize;
+
+use super::transaction::Transaction;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    /// Pass back as `cursor` to fetch the next page; absent on the last page.
+    pub next_cursor: Option<String>,
+}
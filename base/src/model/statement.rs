@@ -0,0 +1,47 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::transaction::Transaction;
+
+use crate::utils::serialize_datetime;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementHeader {
+    pub account_id: Uuid,
+    pub currency: String,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub from: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub to: DateTime<Utc>,
+    pub opening_balance: BigDecimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementLine {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    pub running_balance: BigDecimal,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct StatementSummary {
+    pub total_in: BigDecimal,
+    pub total_out: BigDecimal,
+    pub closing_balance: BigDecimal,
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementEntry {
+    Line(Box<StatementLine>),
+    Summary(StatementSummary),
+}
+
+/// A statement is produced lazily: `entries` yields every transaction of the
+/// period in time order and ends with a single `StatementEntry::Summary`.
+pub struct Statement {
+    pub header: StatementHeader,
+    pub entries: BoxStream<'static, Result<StatementEntry, String>>,
+}
@@ -3,32 +3,61 @@ use chrono::{DateTime, Utc};
 use serde::Serialize;
 use uuid::Uuid;
 
-use super::account::Account;
+use super::{
+    account::Account,
+    details::Details,
+    operation::{OperationKind, TransactionStatus},
+};
 
 use crate::utils::serialize_datetime;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Transaction {
     pub id: Uuid,
+    /// Shared by every leg written by the same operation, e.g. both sides of
+    /// a transfer.
+    pub operation_id: Uuid,
+    /// The other account of a transfer leg.
+    pub counterparty_account_id: Option<Uuid>,
+    pub kind: OperationKind,
+    pub status: TransactionStatus,
     pub idempotency_key: String,
+    /// Version the account moves to with this transaction.
     pub account_version: Uuid,
     pub account_id: Uuid,
     pub amount: BigDecimal,
+    /// Account balance right after this transaction.
+    pub balance_after: BigDecimal,
     #[serde(serialize_with = "serialize_datetime")]
     pub created_at: DateTime<Utc>,
     pub currency: String,
+    #[serde(flatten)]
+    pub details: Details,
 }
 
 impl Transaction {
-    pub fn new(account: &Account, idempotency_key: &str, amount: &BigDecimal) -> Self {
+    /// Transactions are posted as soon as they are saved, so `balance_after`
+    /// is `account`'s balance plus `amount` and the account gets a new version.
+    pub fn new(
+        account: &Account,
+        idempotency_key: &str,
+        amount: &BigDecimal,
+        kind: OperationKind,
+    ) -> Self {
         Self {
             id: Uuid::new_v4(),
+            operation_id: Uuid::new_v4(),
+            counterparty_account_id: None,
+            kind,
+            status: TransactionStatus::Posted,
             idempotency_key: idempotency_key.to_owned(),
-            account_version: account.version,
+            account_version: Uuid::new_v4(),
             account_id: account.uuid,
             currency: account.currency.clone(),
             amount: amount.clone(),
+            balance_after: &account.balance + amount,
             created_at: Utc::now(),
+            details: Details::default(),
         }
     }
 }
@@ -0,0 +1,53 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{operation::OperationKind, transaction::Transaction};
+
+use crate::utils::serialize_datetime;
+
+/// Both legs of a transfer, identified by their shared operation id.
+#[derive(Debug, Clone, Serialize)]
+pub struct Transfer {
+    pub id: Uuid,
+    pub from_account_id: Uuid,
+    pub to_account_id: Uuid,
+    pub amount: BigDecimal,
+    pub currency: String,
+    #[serde(serialize_with = "serialize_datetime")]
+    pub created_at: DateTime<Utc>,
+    pub debit: Transaction,
+    pub credit: Transaction,
+}
+
+impl Transfer {
+    /// Returns `None` unless `legs` are exactly one transfer_out and one
+    /// transfer_in leg pointing at each other.
+    pub fn from_legs(legs: Vec<Transaction>) -> Option<Self> {
+        let [first, second]: [Transaction; 2] = legs.try_into().ok()?;
+        let (debit, credit) = if first.kind == OperationKind::TransferOut {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        if debit.kind != OperationKind::TransferOut
+            || credit.kind != OperationKind::TransferIn
+            || debit.operation_id != credit.operation_id
+            || debit.counterparty_account_id != Some(credit.account_id)
+            || credit.counterparty_account_id != Some(debit.account_id)
+        {
+            return None;
+        }
+        Some(Transfer {
+            id: debit.operation_id,
+            from_account_id: debit.account_id,
+            to_account_id: credit.account_id,
+            amount: credit.amount.clone(),
+            currency: debit.currency.clone(),
+            created_at: debit.created_at,
+            debit,
+            credit,
+        })
+    }
+}
@@ -0,0 +1,113 @@
+use std::{future::Future, time::Duration};
+
+use tokio::{sync::watch, task::JoinSet};
+use tracing::Instrument;
+
+/// Handed to each worker; flips once shutdown starts. Workers check it
+/// between units of work, so a write in progress is finished rather than cut
+/// off.
+#[derive(Debug, Clone)]
+pub struct StopSignal(watch::Receiver<bool>);
+
+impl StopSignal {
+    pub fn is_stopped(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once shutdown starts, e.g. to `select!` against a sleep.
+    pub async fn stopped(&mut self) {
+        // The sender lives in `Workers`, which signals before it is dropped.
+        let _ = self.0.wait_for(|stopped| *stopped).await;
+    }
+}
+
+/// Background workers, such as WAL compaction or Cassandra recovery, that stop
+/// with the server instead of being killed mid-write when the runtime exits.
+pub struct Workers {
+    stop: watch::Sender<bool>,
+    tasks: JoinSet<()>,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        Workers::new()
+    }
+}
+
+impl Workers {
+    pub fn new() -> Self {
+        Workers {
+            stop: watch::Sender::new(false),
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Runs `worker` in the background until it returns, which it should do
+    /// soon after its `StopSignal` flips.
+    pub fn spawn<F>(&mut self, name: &'static str, worker: impl FnOnce(StopSignal) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let task = worker(StopSignal(self.stop.subscribe()));
+        self.tasks
+            .spawn(task.instrument(tracing::info_span!("worker", name)));
+    }
+
+    /// Signals every worker to stop and waits up to `deadline` for them to
+    /// return. Workers still running after that are aborted; returns how many.
+    pub async fn shutdown(mut self, deadline: Duration) -> usize {
+        self.stop.send_replace(true);
+        let drained = tokio::time::timeout(deadline, async {
+            while self.tasks.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_ok() {
+            return 0;
+        }
+        let aborted = self.tasks.len();
+        tracing::warn!(aborted, "Workers did not stop within {:?}", deadline);
+        self.tasks.shutdown().await;
+        aborted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_workers_finish_their_unit_of_work() {
+        let completed = Arc::new(AtomicUsize::new(0));
+        let mut workers = Workers::new();
+        for name in ["wal_compactor", "cassandra_recovery"] {
+            let completed = Arc::clone(&completed);
+            workers.spawn(name, |stop| async move {
+                while !stop.is_stopped() {
+                    // A unit of work that must not be interrupted.
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    completed.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+
+        assert_eq!(workers.shutdown(Duration::from_secs(1)).await, 0);
+        assert_eq!(completed.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stragglers_are_aborted_after_the_deadline() {
+        let mut workers = Workers::new();
+        workers.spawn("idle", |mut stop| async move { stop.stopped().await });
+        workers.spawn("stuck", |_| async {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        });
+
+        assert_eq!(workers.shutdown(Duration::from_secs(5)).await, 1);
+    }
+}
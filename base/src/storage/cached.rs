@@ -0,0 +1,505 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    metrics::ACCOUNT_CACHE_LOOKUPS,
+    model::{Account, AccountUpdate, Transaction},
+};
+
+use super::{AccountCreation, Storage};
+
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Accounts kept at most; the least recently used is evicted first. Zero
+    /// disables the cache.
+    pub capacity: usize,
+    pub ttl: Duration,
+    /// How long a lookup of a missing account is remembered, if at all.
+    pub negative_ttl: Option<Duration>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            capacity: DEFAULT_CACHE_CAPACITY,
+            ttl: DEFAULT_CACHE_TTL,
+            negative_ttl: None,
+        }
+    }
+}
+
+/// Lookups by this cache alone; `/metrics` counts those of every cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry {
+    /// `None` for an account known not to exist.
+    account: Option<Account>,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+/// Reads of one account from the inner storage in progress.
+#[derive(Default)]
+struct Load {
+    readers: usize,
+    /// Bumped whenever the account is invalidated, so a read that started
+    /// before does not cache what it read.
+    generation: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<Uuid, Entry>,
+    /// Ids by the tick of their last use, oldest first.
+    recency: BTreeMap<u64, Uuid>,
+    tick: u64,
+    /// Only accounts being read are tracked, so this stays small.
+    loads: HashMap<Uuid, Load>,
+}
+
+impl Lru {
+    fn get(&mut self, uuid: &Uuid, now: Instant) -> Option<Option<Account>> {
+        let entry = self.entries.get_mut(uuid)?;
+        if entry.expires_at <= now {
+            self.remove(uuid);
+            return None;
+        }
+        self.tick += 1;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.tick, *uuid);
+        entry.last_used = self.tick;
+        Some(entry.account.clone())
+    }
+
+    fn insert(
+        &mut self,
+        uuid: Uuid,
+        account: Option<Account>,
+        expires_at: Instant,
+        capacity: usize,
+    ) {
+        self.remove(&uuid);
+        while self.entries.len() >= capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, uuid);
+        self.entries.insert(
+            uuid,
+            Entry {
+                account,
+                expires_at,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, uuid: &Uuid) {
+        if let Some(entry) = self.entries.remove(uuid) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    fn invalidate(&mut self, uuids: &[Uuid]) {
+        for uuid in uuids {
+            self.remove(uuid);
+            if let Some(load) = self.loads.get_mut(uuid) {
+                load.generation += 1;
+            }
+        }
+    }
+
+    fn begin_load(&mut self, uuid: Uuid) -> u64 {
+        let load = self.loads.entry(uuid).or_default();
+        load.readers += 1;
+        load.generation
+    }
+
+    /// Whether `uuid` was not invalidated since a read began at `generation`.
+    fn is_current(&self, uuid: &Uuid, generation: u64) -> bool {
+        self.loads
+            .get(uuid)
+            .is_some_and(|load| load.generation == generation)
+    }
+
+    fn end_load(&mut self, uuid: &Uuid) {
+        if let Some(load) = self.loads.get_mut(uuid) {
+            load.readers -= 1;
+            if load.readers == 0 {
+                self.loads.remove(uuid);
+            }
+        }
+    }
+}
+
+/// A read of `uuid` from the inner storage, registered until dropped, even
+/// when the read is cancelled.
+struct PendingLoad<'a> {
+    lru: &'a Mutex<Lru>,
+    uuid: Uuid,
+    generation: u64,
+}
+
+impl Drop for PendingLoad<'_> {
+    fn drop(&mut self) {
+        lock(self.lru).end_load(&self.uuid);
+    }
+}
+
+fn lock(lru: &Mutex<Lru>) -> std::sync::MutexGuard<'_, Lru> {
+    lru.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Read-through cache of accounts in front of another storage. Accounts
+/// touched by `save_account` or `save_transactions` are dropped from the
+/// cache whatever the outcome, so a version conflict caused by a stale
+/// entry is resolved by the retry that follows it. Writes made through other
+/// instances are seen once the entry expires.
+pub struct CachedStorage<S: Storage> {
+    inner: Arc<S>,
+    config: CacheConfig,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S: Storage> CachedStorage<S> {
+    pub fn new(inner: &Arc<S>, config: CacheConfig) -> Self {
+        CachedStorage {
+            inner: Arc::clone(inner),
+            config,
+            lru: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn lru(&self) -> std::sync::MutexGuard<'_, Lru> {
+        lock(&self.lru)
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for CachedStorage<S> {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        let uuid = account.uuid;
+        let result = self.inner.save_account(account).await;
+        self.lru().invalidate(&[uuid]);
+        result
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        if self.config.capacity == 0 {
+            return self.inner.get_account(uuid).await;
+        }
+        let load = {
+            let mut lru = self.lru();
+            if let Some(account) = lru.get(&uuid, Instant::now()) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                ACCOUNT_CACHE_LOOKUPS.with_label_values(&["hit"]).inc();
+                return Ok(account);
+            }
+            PendingLoad {
+                lru: &self.lru,
+                uuid,
+                generation: lru.begin_load(uuid),
+            }
+        };
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        ACCOUNT_CACHE_LOOKUPS.with_label_values(&["miss"]).inc();
+
+        let account = self.inner.get_account(uuid).await?;
+        let ttl = match account {
+            Some(_) => Some(self.config.ttl),
+            None => self.config.negative_ttl,
+        };
+        let mut lru = self.lru();
+        if let Some(ttl) = ttl.filter(|_| lru.is_current(&uuid, load.generation)) {
+            lru.insert(
+                uuid,
+                account.clone(),
+                Instant::now() + ttl,
+                self.config.capacity,
+            );
+        }
+        // Ending the load locks the cache again.
+        drop(lru);
+        Ok(account)
+    }
+
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        let uuids: Vec<Uuid> = updated_accounts
+            .iter()
+            .map(|update| update.account.uuid)
+            .collect();
+        let result = self
+            .inner
+            .save_transactions(created_transactions, updated_accounts)
+            .await;
+        self.lru().invalidate(&uuids);
+        result
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner
+            .get_transactions(account_id, from, to, limit)
+            .await
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        self.inner.get_transaction(id).await
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner.get_operation_transactions(operation_id).await
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        self.inner
+            .get_idempotency_key(account_id, idempotency_key)
+            .await
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner.search_transactions(query, after, limit).await
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        self.inner.health_check().await
+    }
+
+    async fn close(&self) -> Result<(), String> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+
+    use crate::{
+        model::OperationKind,
+        storage::{Fault, FaultyStorage, InMemoryStorage, StorageOperation, Trigger},
+    };
+
+    use super::*;
+
+    fn setup(config: CacheConfig) -> CachedStorage<InMemoryStorage> {
+        CachedStorage::new(&Arc::new(InMemoryStorage::new()), config)
+    }
+
+    async fn create_account(storage: &impl Storage) -> Account {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        account
+    }
+
+    fn stats(hits: u64, misses: u64) -> CacheStats {
+        CacheStats { hits, misses }
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let storage = setup(CacheConfig {
+            negative_ttl: Some(Duration::from_secs(5)),
+            ..CacheConfig::default()
+        });
+        crate::storage::conformance::run(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn test_second_read_is_a_hit() {
+        let storage = setup(CacheConfig::default());
+        let account = create_account(&storage).await;
+        let hits = ACCOUNT_CACHE_LOOKUPS.with_label_values(&["hit"]).get();
+
+        storage.get_account(account.uuid).await.unwrap().unwrap();
+        let cached = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(cached, account);
+        assert_eq!(storage.stats(), stats(1, 1));
+        // Other tests' caches count into the same metric concurrently.
+        assert!(ACCOUNT_CACHE_LOOKUPS.with_label_values(&["hit"]).get() > hits);
+    }
+
+    #[tokio::test]
+    async fn test_save_transactions_invalidates() {
+        let storage = setup(CacheConfig::default());
+        let account = create_account(&storage).await;
+        storage.get_account(account.uuid).await.unwrap();
+
+        let tx = Transaction::new(
+            &account,
+            "idemp_1",
+            &BigDecimal::from(10),
+            OperationKind::Deposit,
+        );
+        let update = account.apply(&tx);
+        storage
+            .save_transactions(vec![tx], vec![update])
+            .await
+            .unwrap();
+
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+        assert_eq!(storage.stats(), stats(0, 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_entries_expire() {
+        let storage = setup(CacheConfig {
+            ttl: Duration::from_secs(5),
+            ..CacheConfig::default()
+        });
+        let account = create_account(&storage).await;
+        storage.get_account(account.uuid).await.unwrap();
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        storage.get_account(account.uuid).await.unwrap();
+        tokio::time::advance(Duration::from_secs(2)).await;
+        storage.get_account(account.uuid).await.unwrap();
+        assert_eq!(storage.stats(), stats(1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_is_evicted() {
+        let storage = setup(CacheConfig {
+            capacity: 2,
+            ..CacheConfig::default()
+        });
+        let first = create_account(&storage).await;
+        let second = create_account(&storage).await;
+        let third = create_account(&storage).await;
+
+        storage.get_account(first.uuid).await.unwrap();
+        storage.get_account(second.uuid).await.unwrap();
+        storage.get_account(first.uuid).await.unwrap();
+        storage.get_account(third.uuid).await.unwrap();
+        assert_eq!(storage.stats(), stats(1, 3));
+
+        storage.get_account(first.uuid).await.unwrap();
+        storage.get_account(second.uuid).await.unwrap();
+        assert_eq!(storage.stats(), stats(2, 4));
+    }
+
+    /// Only an invalidation of the account being read keeps it out of the
+    /// cache; writes to other accounts meanwhile do not.
+    #[tokio::test(start_paused = true)]
+    async fn test_reads_overlapping_invalidations() {
+        let faulty = Arc::new(FaultyStorage::new(&Arc::new(InMemoryStorage::new())));
+        let storage = CachedStorage::new(&faulty, CacheConfig::default());
+        let account = create_account(&storage).await;
+        let other = create_account(&storage).await;
+        let deposit = |account: &Account| {
+            let tx = Transaction::new(
+                account,
+                "idemp_1",
+                &BigDecimal::from(10),
+                OperationKind::Deposit,
+            );
+            let update = account.apply(&tx);
+            storage.save_transactions(vec![tx], vec![update])
+        };
+        let slow_read = || {
+            faulty.inject(
+                StorageOperation::GetAccount,
+                Trigger::Probability(1.0),
+                Fault::Delay(Duration::from_millis(100)),
+            );
+            storage.get_account(account.uuid)
+        };
+
+        let (read, saved) = tokio::join!(slow_read(), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            deposit(&other).await
+        });
+        faulty.clear();
+        read.unwrap();
+        saved.unwrap();
+        storage.get_account(account.uuid).await.unwrap();
+        assert_eq!(storage.stats(), stats(1, 1));
+
+        storage.save_account(account.clone()).await.unwrap();
+        let (read, saved) = tokio::join!(slow_read(), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            deposit(&account).await
+        });
+        faulty.clear();
+        read.unwrap();
+        saved.unwrap();
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+        assert_eq!(storage.stats(), stats(1, 3));
+    }
+
+    #[tokio::test]
+    async fn test_negative_cache() {
+        let storage = setup(CacheConfig::default());
+        let missing = Uuid::new_v4();
+        storage.get_account(missing).await.unwrap();
+        storage.get_account(missing).await.unwrap();
+        assert_eq!(storage.stats(), stats(0, 2));
+
+        let storage = setup(CacheConfig {
+            negative_ttl: Some(Duration::from_secs(5)),
+            ..CacheConfig::default()
+        });
+        assert!(storage.get_account(missing).await.unwrap().is_none());
+        assert!(storage.get_account(missing).await.unwrap().is_none());
+        assert_eq!(storage.stats(), stats(1, 1));
+
+        let account = Account::new(missing, &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        assert_eq!(storage.get_account(missing).await.unwrap(), Some(account));
+    }
+}
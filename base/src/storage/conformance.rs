@@ -0,0 +1,311 @@
+//! Behaviour every `Storage` backend must share, as async checks that panic
+//! on the first difference. Each check creates accounts with fresh ids, so
+//! the suite can run against a shared database:
+//!
+//! ```ignore
+//! base::storage::conformance::run(&storage).await;
+//! ```
+
+use bigdecimal::BigDecimal;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    dto::{search::TransactionSearchDTO, transaction::TransferTransactionDTO},
+    model::{Account, AccountUpdate, Details, OperationKind, Transaction},
+};
+
+use super::{AccountCreation, Storage, IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT};
+
+pub async fn run<S: Storage>(storage: &S) {
+    healthy(storage).await;
+    account_round_trip(storage).await;
+    account_create_only(storage).await;
+    missing_records(storage).await;
+    transaction_round_trip(storage).await;
+    version_conflict(storage).await;
+    multi_account_atomicity(storage).await;
+    duplicate_operation(storage).await;
+    retry_after_failed_write(storage).await;
+}
+
+fn details(label: &str) -> Details {
+    Details {
+        description: Some(format!("{} description", label)),
+        external_reference: Some(format!("{}-{}", label, Uuid::new_v4())),
+        metadata: [("conformance".to_string(), Uuid::new_v4().to_string())].into(),
+    }
+}
+
+async fn create_account<S: Storage>(storage: &S) -> Account {
+    let mut account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+    account.details = details("account");
+    let creation = storage.save_account(account.clone()).await.unwrap();
+    assert_eq!(creation, AccountCreation::Created);
+    account
+}
+
+async fn reload<S: Storage>(storage: &S, account: &Account) -> Account {
+    storage.get_account(account.uuid).await.unwrap().unwrap()
+}
+
+fn deposit(account: &Account, key: &str) -> (Transaction, AccountUpdate) {
+    let tx = Transaction {
+        details: details("deposit"),
+        ..Transaction::new(account, key, &BigDecimal::from(10), OperationKind::Deposit)
+    };
+    let update = account.apply(&tx);
+    (tx, update)
+}
+
+fn transfer(from: &Account, to: &Account, key: &str) -> (Vec<Transaction>, Vec<AccountUpdate>) {
+    let dto = TransferTransactionDTO {
+        idempotency_key: key.to_string(),
+        from_account_id: from.uuid,
+        to_account_id: to.uuid,
+        amount: BigDecimal::from(3),
+        details: details("transfer"),
+        expected_version: None,
+    };
+    let (from_tx, to_tx) = dto.to_transactions(from, to);
+    let updates = vec![from.apply(&from_tx), to.apply(&to_tx)];
+    (vec![from_tx, to_tx], updates)
+}
+
+fn assert_same_transaction(stored: &Transaction, expected: &Transaction) {
+    assert_eq!(stored.id, expected.id);
+    assert_eq!(stored.operation_id, expected.operation_id);
+    assert_eq!(
+        stored.counterparty_account_id,
+        expected.counterparty_account_id
+    );
+    assert_eq!(stored.kind, expected.kind);
+    assert_eq!(stored.status, expected.status);
+    assert_eq!(stored.idempotency_key, expected.idempotency_key);
+    assert_eq!(stored.account_version, expected.account_version);
+    assert_eq!(stored.account_id, expected.account_id);
+    assert_eq!(stored.amount, expected.amount);
+    assert_eq!(stored.balance_after, expected.balance_after);
+    assert_eq!(
+        stored.created_at, expected.created_at,
+        "created_at must keep nanoseconds"
+    );
+    assert_eq!(stored.currency, expected.currency);
+    assert_eq!(stored.details, expected.details);
+}
+
+/// A migrated, reachable backend reports itself healthy.
+pub async fn healthy<S: Storage>(storage: &S) {
+    storage.health_check().await.unwrap();
+}
+
+/// A saved account reads back field for field, timestamps to the nanosecond.
+pub async fn account_round_trip<S: Storage>(storage: &S) {
+    let account = create_account(storage).await;
+    assert_eq!(reload(storage, &account).await, account);
+}
+
+/// Saving an account under a taken id leaves the stored one untouched and
+/// reports it as `AlreadyExists`.
+pub async fn account_create_only<S: Storage>(storage: &S) {
+    let account = create_account(storage).await;
+    let duplicate = Account::new(account.uuid, &"USD".to_string());
+    let creation = storage.save_account(duplicate).await.unwrap();
+    assert_eq!(creation, AccountCreation::AlreadyExists(account.clone()));
+    assert_eq!(reload(storage, &account).await, account);
+}
+
+/// Unknown ids read as `None`, not as errors.
+pub async fn missing_records<S: Storage>(storage: &S) {
+    let id = Uuid::new_v4();
+    assert!(storage.get_account(id).await.unwrap().is_none());
+    assert!(storage.get_transaction(id).await.unwrap().is_none());
+    assert!(storage
+        .get_operation_transactions(id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// A committed deposit reads back through every lookup, and moves the
+/// account to the transaction's version and balance.
+pub async fn transaction_round_trip<S: Storage>(storage: &S) {
+    let account = create_account(storage).await;
+    let (tx, update) = deposit(&account, "conformance_1");
+    let saved = storage
+        .save_transactions(vec![tx.clone()], vec![update.clone()])
+        .await
+        .unwrap();
+    assert_same_transaction(&saved[0], &tx);
+
+    assert_eq!(reload(storage, &account).await, update.account);
+    let by_id = storage.get_transaction(tx.id).await.unwrap().unwrap();
+    assert_same_transaction(&by_id, &tx);
+    let by_operation = storage
+        .get_operation_transactions(tx.operation_id)
+        .await
+        .unwrap();
+    assert_eq!(by_operation.len(), 1);
+    assert_same_transaction(&by_operation[0], &tx);
+
+    let from = tx.created_at - Duration::seconds(1);
+    let history = storage
+        .get_transactions(account.uuid, from, Utc::now() + Duration::seconds(1), 10)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_same_transaction(&history[0], &tx);
+
+    let (key, value) = tx.details.metadata.iter().next().unwrap();
+    let query = TransactionSearchDTO {
+        metadata_key: Some(key.clone()),
+        metadata_value: Some(value.clone()),
+        ..TransactionSearchDTO::default()
+    };
+    let found = storage.search_transactions(&query, None, 10).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_same_transaction(&found[0], &tx);
+}
+
+/// A commit against a stale or missing account fails with
+/// `VERSION_CONFLICT` and writes nothing.
+pub async fn version_conflict<S: Storage>(storage: &S) {
+    let account = create_account(storage).await;
+    let (first, update) = deposit(&account, "conformance_1");
+    storage
+        .save_transactions(vec![first], vec![update.clone()])
+        .await
+        .unwrap();
+
+    let (stale, stale_update) = deposit(&account, "conformance_2");
+    let result = storage
+        .save_transactions(vec![stale.clone()], vec![stale_update])
+        .await;
+    assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+    assert!(storage.get_transaction(stale.id).await.unwrap().is_none());
+    assert_eq!(reload(storage, &account).await, update.account);
+
+    let unsaved = Account::new(Uuid::new_v4(), &"BRL".to_string());
+    let (orphan, orphan_update) = deposit(&unsaved, "conformance_1");
+    let result = storage
+        .save_transactions(vec![orphan.clone()], vec![orphan_update])
+        .await;
+    assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+    assert!(storage.get_transaction(orphan.id).await.unwrap().is_none());
+    assert!(storage.get_account(unsaved.uuid).await.unwrap().is_none());
+}
+
+/// A commit touching several accounts is written whole or not at all: one
+/// stale account keeps both legs and both accounts unchanged.
+pub async fn multi_account_atomicity<S: Storage>(storage: &S) {
+    let from = create_account(storage).await;
+    let to = create_account(storage).await;
+    let (moved, update) = deposit(&to, "conformance_1");
+    storage
+        .save_transactions(vec![moved], vec![update.clone()])
+        .await
+        .unwrap();
+
+    // `to` is read before the deposit above, so its leg is stale.
+    let (legs, updates) = transfer(&from, &to, "conformance_2");
+    let result = storage.save_transactions(legs.clone(), updates).await;
+    assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+    for leg in &legs {
+        assert!(storage.get_transaction(leg.id).await.unwrap().is_none());
+    }
+    assert_eq!(reload(storage, &from).await, from);
+    assert_eq!(reload(storage, &to).await, update.account);
+
+    let (legs, updates) = transfer(&from, &update.account, "conformance_2");
+    storage
+        .save_transactions(legs.clone(), updates.clone())
+        .await
+        .unwrap();
+    let operation = storage
+        .get_operation_transactions(legs[0].operation_id)
+        .await
+        .unwrap();
+    assert_eq!(operation.len(), 2);
+    assert_eq!(reload(storage, &from).await, updates[0].account);
+    assert_eq!(reload(storage, &to).await, updates[1].account);
+}
+
+/// Replaying an operation's idempotency key returns the original
+/// transactions without writing again; reusing it for a different operation
+/// fails with `IDEMPOTENCY_KEY_REUSED`. The key is found per account.
+pub async fn duplicate_operation<S: Storage>(storage: &S) {
+    let account = create_account(storage).await;
+    let other = create_account(storage).await;
+    let (tx, update) = deposit(&account, "conformance_1");
+    storage
+        .save_transactions(vec![tx.clone()], vec![update.clone()])
+        .await
+        .unwrap();
+    let recorded = storage
+        .get_idempotency_key(account.uuid, "conformance_1")
+        .await
+        .unwrap();
+    assert_eq!(recorded, Some(tx.operation_id));
+    let elsewhere = storage
+        .get_idempotency_key(other.uuid, "conformance_1")
+        .await
+        .unwrap();
+    assert!(elsewhere.is_none());
+
+    let (replay, replay_update) = deposit(&update.account, "conformance_1");
+    let saved = storage
+        .save_transactions(vec![replay.clone()], vec![replay_update])
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_same_transaction(&saved[0], &tx);
+    assert!(storage.get_transaction(replay.id).await.unwrap().is_none());
+    assert_eq!(reload(storage, &account).await, update.account);
+
+    let (legs, updates) = transfer(&update.account, &other, "conformance_1");
+    let result = storage.save_transactions(legs.clone(), updates).await;
+    assert_eq!(result.unwrap_err(), IDEMPOTENCY_KEY_REUSED);
+    for leg in &legs {
+        assert!(storage.get_transaction(leg.id).await.unwrap().is_none());
+    }
+    assert_eq!(reload(storage, &account).await, update.account);
+    assert_eq!(reload(storage, &other).await, other);
+}
+
+/// An operation that claimed its idempotency key and then failed to write
+/// leaves the key free: retrying it against the current account commits, and
+/// replaying it after that returns the retry.
+pub async fn retry_after_failed_write<S: Storage>(storage: &S) {
+    let account = create_account(storage).await;
+    let (moved, update) = deposit(&account, "conformance_1");
+    storage
+        .save_transactions(vec![moved], vec![update.clone()])
+        .await
+        .unwrap();
+
+    // Read before the deposit above, so its write fails.
+    let (stale, stale_update) = deposit(&account, "conformance_2");
+    let result = storage
+        .save_transactions(vec![stale.clone()], vec![stale_update])
+        .await;
+    assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+
+    let (retry, retry_update) = deposit(&update.account, "conformance_2");
+    let saved = storage
+        .save_transactions(vec![retry.clone()], vec![retry_update.clone()])
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_same_transaction(&saved[0], &retry);
+    assert!(storage.get_transaction(stale.id).await.unwrap().is_none());
+    assert_eq!(reload(storage, &account).await, retry_update.account);
+
+    let (replay, replay_update) = deposit(&retry_update.account, "conformance_2");
+    let saved = storage
+        .save_transactions(vec![replay.clone()], vec![replay_update])
+        .await
+        .unwrap();
+    assert_same_transaction(&saved[0], &retry);
+    assert!(storage.get_transaction(replay.id).await.unwrap().is_none());
+    assert_eq!(reload(storage, &account).await, retry_update.account);
+}
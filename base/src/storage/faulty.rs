@@ -0,0 +1,459 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use uuid::Uuid;
+
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Transaction},
+};
+
+use super::{AccountCreation, Storage};
+
+/// Returned by a `Fault::Timeout` or `Fault::LoseResponse`: the caller cannot
+/// tell whether the operation took effect.
+pub const STORAGE_TIMEOUT: &str = "Storage operation timed out";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageOperation {
+    SaveAccount,
+    GetAccount,
+    SaveTransactions,
+    GetTransactions,
+    GetTransaction,
+    GetOperationTransactions,
+    GetIdempotencyKey,
+    SearchTransactions,
+    HealthCheck,
+}
+
+const OPERATIONS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Fails with the error without reaching the inner storage.
+    Fail(String),
+    /// Waits before calling the inner storage.
+    Delay(Duration),
+    /// Waits, then fails with `STORAGE_TIMEOUT` without reaching the inner
+    /// storage.
+    Timeout(Duration),
+    /// Calls the inner storage, then fails with `STORAGE_TIMEOUT` whatever it
+    /// returned, as if the reply was lost after a commit.
+    LoseResponse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// Only the Nth call of the operation, counting from 1.
+    OnCall(u64),
+    /// Each call, with the given probability between 0 and 1.
+    Probability(f64),
+}
+
+struct Rule {
+    operation: StorageOperation,
+    trigger: Trigger,
+    fault: Fault,
+}
+
+/// Storage decorator that injects faults into chosen operations, to test how
+/// callers cope with a storage that fails halfway. Faults are added with
+/// `inject` at any time; when several match a call, the first one added wins.
+pub struct FaultyStorage<S: Storage> {
+    inner: Arc<S>,
+    rules: Mutex<Vec<Rule>>,
+    calls: [AtomicU64; OPERATIONS],
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    pub fn new(inner: &Arc<S>) -> Self {
+        FaultyStorage {
+            inner: Arc::clone(inner),
+            rules: Mutex::default(),
+            calls: Default::default(),
+        }
+    }
+
+    pub fn inject(&self, operation: StorageOperation, trigger: Trigger, fault: Fault) {
+        self.rules().push(Rule {
+            operation,
+            trigger,
+            fault,
+        });
+    }
+
+    /// Drops every injected fault; call counts are kept.
+    pub fn clear(&self) {
+        self.rules().clear();
+    }
+
+    /// How many times the operation was called, faulted or not.
+    pub fn calls(&self, operation: StorageOperation) -> u64 {
+        self.calls[operation as usize].load(Ordering::SeqCst)
+    }
+
+    fn rules(&self) -> std::sync::MutexGuard<'_, Vec<Rule>> {
+        self.rules.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn fault(&self, operation: StorageOperation) -> Option<Fault> {
+        let call = self.calls[operation as usize].fetch_add(1, Ordering::SeqCst) + 1;
+        let rules = self.rules();
+        rules
+            .iter()
+            .filter(|rule| rule.operation == operation)
+            .find(|rule| match rule.trigger {
+                Trigger::OnCall(n) => n == call,
+                Trigger::Probability(p) => rand::thread_rng().gen_bool(p.clamp(0.0, 1.0)),
+            })
+            .map(|rule| rule.fault.clone())
+    }
+
+    async fn call<T, F>(&self, operation: StorageOperation, inner: F) -> Result<T, String>
+    where
+        F: std::future::Future<Output = Result<T, String>>,
+    {
+        match self.fault(operation) {
+            None => inner.await,
+            Some(Fault::Fail(error)) => Err(error),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                inner.await
+            }
+            Some(Fault::Timeout(after)) => {
+                tokio::time::sleep(after).await;
+                Err(STORAGE_TIMEOUT.to_string())
+            }
+            Some(Fault::LoseResponse) => {
+                let _ = inner.await;
+                Err(STORAGE_TIMEOUT.to_string())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for FaultyStorage<S> {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        self.call(
+            StorageOperation::SaveAccount,
+            self.inner.save_account(account),
+        )
+        .await
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        self.call(StorageOperation::GetAccount, self.inner.get_account(uuid))
+            .await
+    }
+
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        self.call(
+            StorageOperation::SaveTransactions,
+            self.inner
+                .save_transactions(created_transactions, updated_accounts),
+        )
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.call(
+            StorageOperation::GetTransactions,
+            self.inner.get_transactions(account_id, from, to, limit),
+        )
+        .await
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        self.call(
+            StorageOperation::GetTransaction,
+            self.inner.get_transaction(id),
+        )
+        .await
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.call(
+            StorageOperation::GetOperationTransactions,
+            self.inner.get_operation_transactions(operation_id),
+        )
+        .await
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        self.call(
+            StorageOperation::GetIdempotencyKey,
+            self.inner.get_idempotency_key(account_id, idempotency_key),
+        )
+        .await
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.call(
+            StorageOperation::SearchTransactions,
+            self.inner.search_transactions(query, after, limit),
+        )
+        .await
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        self.call(StorageOperation::HealthCheck, self.inner.health_check())
+            .await
+    }
+
+    async fn close(&self) -> Result<(), String> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+
+    use crate::{
+        dto::transaction::{DepositTransactionDTO, TransferTransactionDTO},
+        model::Details,
+        storage::{InMemoryStorage, VERSION_CONFLICT},
+        use_case::{DepositUseCase, TransferUseCase, UseCase},
+    };
+
+    use super::*;
+
+    async fn setup(balances: &[i64]) -> (Arc<FaultyStorage<InMemoryStorage>>, Vec<Account>) {
+        let inner = Arc::new(InMemoryStorage::new());
+        let mut accounts = vec![];
+        for balance in balances {
+            let mut account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+            account.balance = BigDecimal::from(*balance);
+            inner.save_account(account.clone()).await.unwrap();
+            accounts.push(account);
+        }
+        (Arc::new(FaultyStorage::new(&inner)), accounts)
+    }
+
+    fn deposit(account: &Account, key: &str) -> DepositTransactionDTO {
+        DepositTransactionDTO {
+            idempotency_key: key.to_string(),
+            account_id: account.uuid,
+            amount: BigDecimal::from(10),
+            details: Details::default(),
+            expected_version: None,
+        }
+    }
+
+    fn transfer(from: &Account, to: &Account, key: &str) -> TransferTransactionDTO {
+        TransferTransactionDTO {
+            idempotency_key: key.to_string(),
+            from_account_id: from.uuid,
+            to_account_id: to.uuid,
+            amount: BigDecimal::from(10),
+            details: Details::default(),
+            expected_version: None,
+        }
+    }
+
+    async fn balance<S: Storage>(storage: &S, account: &Account) -> BigDecimal {
+        storage
+            .get_account(account.uuid)
+            .await
+            .unwrap()
+            .unwrap()
+            .balance
+    }
+
+    /// Executes the request until it succeeds, the way a client retries
+    /// after a storage error, and returns how many attempts it took.
+    async fn execute_until_ok<I, O>(
+        use_case: &impl UseCase<I, O>,
+        input: impl Fn() -> I,
+    ) -> (O, u32) {
+        let mut attempts = 1;
+        loop {
+            match use_case.execute(input()).await {
+                Ok(output) => return (output, attempts),
+                Err(error) => {
+                    assert!(
+                        [STORAGE_TIMEOUT, VERSION_CONFLICT, "Injected failure"].contains(&&*error),
+                        "unexpected error: {}",
+                        error
+                    );
+                    attempts += 1;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fails_on_nth_call() {
+        let (storage, accounts) = setup(&[0]).await;
+        let error = "Injected failure".to_string();
+        storage.inject(
+            StorageOperation::GetAccount,
+            Trigger::OnCall(2),
+            Fault::Fail(error.clone()),
+        );
+
+        assert!(storage.get_account(accounts[0].uuid).await.is_ok());
+        assert_eq!(storage.get_account(accounts[0].uuid).await, Err(error));
+        assert!(storage.get_account(accounts[0].uuid).await.is_ok());
+        assert_eq!(storage.calls(StorageOperation::GetAccount), 3);
+
+        storage.inject(
+            StorageOperation::GetAccount,
+            Trigger::Probability(1.0),
+            Fault::Fail("Injected failure".to_string()),
+        );
+        assert!(storage.get_account(accounts[0].uuid).await.is_err());
+        storage.clear();
+        assert!(storage.get_account(accounts[0].uuid).await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delay_and_timeout() {
+        let (storage, accounts) = setup(&[0]).await;
+        storage.inject(
+            StorageOperation::GetAccount,
+            Trigger::OnCall(1),
+            Fault::Delay(Duration::from_secs(3)),
+        );
+        storage.inject(
+            StorageOperation::GetAccount,
+            Trigger::OnCall(2),
+            Fault::Timeout(Duration::from_secs(5)),
+        );
+
+        let start = tokio::time::Instant::now();
+        assert!(storage.get_account(accounts[0].uuid).await.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+        let result = storage.get_account(accounts[0].uuid).await;
+        assert_eq!(result.unwrap_err(), STORAGE_TIMEOUT);
+        assert_eq!(start.elapsed(), Duration::from_secs(8));
+    }
+
+    #[tokio::test]
+    async fn test_lost_response_is_committed() {
+        let (storage, accounts) = setup(&[0]).await;
+        storage.inject(
+            StorageOperation::SaveTransactions,
+            Trigger::OnCall(1),
+            Fault::LoseResponse,
+        );
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
+
+        let result = use_case.execute(deposit(&accounts[0], "idemp_1")).await;
+        assert_eq!(result.unwrap_err(), STORAGE_TIMEOUT);
+        assert_eq!(balance(&*storage, &accounts[0]).await, BigDecimal::from(10));
+
+        let replayed = use_case
+            .execute(deposit(&accounts[0], "idemp_1"))
+            .await
+            .unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(balance(&*storage, &accounts[0]).await, BigDecimal::from(10));
+    }
+
+    #[tokio::test]
+    async fn test_conflict_retry_after_lost_response_does_not_double_apply() {
+        let (storage, accounts) = setup(&[100, 0]).await;
+        // The first write lands but reports a conflict, so the use case's own
+        // retry replays it against the already updated accounts.
+        storage.inject(
+            StorageOperation::SaveTransactions,
+            Trigger::OnCall(1),
+            Fault::LoseResponse,
+        );
+        storage.inject(
+            StorageOperation::SaveTransactions,
+            Trigger::OnCall(2),
+            Fault::Fail(VERSION_CONFLICT.to_string()),
+        );
+        let use_case = TransferUseCase::new(&storage, &Arc::default());
+        let input = || transfer(&accounts[0], &accounts[1], "idemp_1");
+
+        let (first, attempts) = execute_until_ok(&use_case, input).await;
+        assert_eq!(attempts, 2);
+        let (replayed, _) = execute_until_ok(&use_case, input).await;
+        assert_eq!(
+            first.iter().map(|tx| tx.id).collect::<Vec<_>>(),
+            replayed.iter().map(|tx| tx.id).collect::<Vec<_>>()
+        );
+        assert_eq!(balance(&*storage, &accounts[0]).await, BigDecimal::from(90));
+        assert_eq!(balance(&*storage, &accounts[1]).await, BigDecimal::from(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_random_faults_never_double_apply() {
+        let (storage, accounts) = setup(&[1_000, 0]).await;
+        for operation in [
+            StorageOperation::GetAccount,
+            StorageOperation::SaveTransactions,
+        ] {
+            storage.inject(operation, Trigger::Probability(0.1), Fault::LoseResponse);
+            storage.inject(
+                operation,
+                Trigger::Probability(0.1),
+                Fault::Fail(VERSION_CONFLICT.to_string()),
+            );
+            storage.inject(
+                operation,
+                Trigger::Probability(0.1),
+                Fault::Fail("Injected failure".to_string()),
+            );
+            storage.inject(
+                operation,
+                Trigger::Probability(0.1),
+                Fault::Timeout(Duration::from_secs(1)),
+            );
+        }
+        let deposits = DepositUseCase::new(&storage, &Arc::default());
+        let transfers = TransferUseCase::new(&storage, &Arc::default());
+
+        for i in 0..20 {
+            let key = format!("deposit_{}", i);
+            execute_until_ok(&deposits, || deposit(&accounts[0], &key)).await;
+            let key = format!("transfer_{}", i);
+            execute_until_ok(&transfers, || transfer(&accounts[0], &accounts[1], &key)).await;
+        }
+
+        storage.clear();
+        assert_eq!(
+            balance(&*storage, &accounts[0]).await,
+            BigDecimal::from(1_000)
+        );
+        assert_eq!(
+            balance(&*storage, &accounts[1]).await,
+            BigDecimal::from(200)
+        );
+    }
+}
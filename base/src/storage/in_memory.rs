@@ -1,54 +1,466 @@
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    ops::Deref,
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::model::{Account, Transaction};
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Transaction},
+    utils::shard_of,
+};
 
-use super::Storage;
+use super::{AccountCreation, Storage, IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT};
 
+const SHARDS: usize = 64;
+
+/// Everything stored for the accounts hashed onto one shard. An account's
+/// balance, history and idempotency keys live together, so a commit only
+/// locks the shards of the accounts it touches.
+#[derive(Default)]
+struct AccountShard {
+    accounts: HashMap<Uuid, Account>,
+    /// Each account's transactions, oldest first.
+    transactions: HashMap<Uuid, Vec<Transaction>>,
+    /// Operation that recorded each (account, idempotency key).
+    idempotency_keys: HashMap<(Uuid, String), Uuid>,
+}
+
+/// A map split into independently locked shards.
+struct Sharded<T> {
+    shards: Vec<RwLock<T>>,
+}
+
+impl<T: Default> Sharded<T> {
+    fn new() -> Self {
+        Sharded {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    fn read(&self, key: &Uuid) -> RwLockReadGuard<'_, T> {
+        let shard = &self.shards[shard_of(key, SHARDS)];
+        shard.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self, key: &Uuid) -> RwLockWriteGuard<'_, T> {
+        let shard = &self.shards[shard_of(key, SHARDS)];
+        shard.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Write-locks the shards of every key, in ascending shard order so that
+    /// concurrent commits cannot deadlock. Guards are keyed by shard index.
+    fn write_many<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a Uuid>,
+    ) -> HashMap<usize, RwLockWriteGuard<'_, T>> {
+        let indexes: BTreeSet<usize> = keys.map(|key| shard_of(key, SHARDS)).collect();
+        indexes
+            .into_iter()
+            .map(|index| {
+                let guard = self.shards[index]
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner);
+                (index, guard)
+            })
+            .collect()
+    }
+
+    /// Read-locks the shards of every key, in the same order as `write_many`.
+    fn read_many<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a Uuid>,
+    ) -> HashMap<usize, RwLockReadGuard<'_, T>> {
+        let indexes: BTreeSet<usize> = keys.map(|key| shard_of(key, SHARDS)).collect();
+        indexes
+            .into_iter()
+            .map(|index| {
+                let guard = self.shards[index]
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner);
+                (index, guard)
+            })
+            .collect()
+    }
+
+    fn read_all(&self) -> impl Iterator<Item = RwLockReadGuard<'_, T>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// Every account a commit touches.
+fn commit_accounts<'a>(
+    created_transactions: &'a [Transaction],
+    updated_accounts: &'a [AccountUpdate],
+) -> impl Iterator<Item = &'a Uuid> {
+    created_transactions
+        .iter()
+        .map(|tx| &tx.account_id)
+        .chain(updated_accounts.iter().map(|update| &update.account.uuid))
+}
+
+/// Storage for tests, load tests and demos. Accounts are sharded so that
+/// operations on unrelated accounts rarely contend, and commits follow the
+/// same conditional-update rules as the database backends: nothing is
+/// written unless every updated account exists at its expected version.
 pub struct InMemoryStorage {
-    accounts: Mutex<HashMap<Uuid, Account>>,
+    accounts: Sharded<AccountShard>,
+    /// Transactions by id.
+    transactions: Sharded<HashMap<Uuid, Transaction>>,
+    /// Transaction ids by operation id.
+    operations: Sharded<HashMap<Uuid, Vec<Uuid>>>,
 }
 
 impl InMemoryStorage {
     pub fn new() -> Self {
         InMemoryStorage {
-            accounts: Mutex::new(HashMap::new()),
+            accounts: Sharded::new(),
+            transactions: Sharded::new(),
+            operations: Sharded::new(),
         }
     }
 
     #[cfg(test)]
     pub async fn set_accounts(&self, accounts: HashMap<Uuid, Account>) {
-        let mut storage = self.accounts.lock().await;
-        *storage = accounts;
+        for shard in &self.accounts.shards {
+            shard.write().unwrap().accounts.clear();
+        }
+        for (uuid, account) in accounts {
+            self.accounts.write(&uuid).accounts.insert(uuid, account);
+        }
+    }
+
+    /// Every stored account, in no particular order.
+    pub(crate) fn accounts(&self) -> Vec<Account> {
+        self.accounts
+            .read_all()
+            .flat_map(|shard| shard.accounts.values().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Every stored transaction, ordered by creation time then id.
+    pub(crate) fn transactions(&self) -> Vec<Transaction> {
+        let mut transactions: Vec<Transaction> = self
+            .transactions
+            .read_all()
+            .flat_map(|shard| shard.values().cloned().collect::<Vec<_>>())
+            .collect();
+        transactions.sort_by_key(|tx| (tx.created_at, tx.id));
+        transactions
+    }
+
+    /// What `save_transactions` would do with this commit, without doing it:
+    /// `Some` with the operation its idempotency keys already recorded,
+    /// `None` if it would be written, or the error it would fail with. Holds
+    /// only while nothing else writes in between.
+    pub(crate) fn check_commit(
+        &self,
+        created_transactions: &[Transaction],
+        updated_accounts: &[AccountUpdate],
+    ) -> Result<Option<Vec<Transaction>>, String> {
+        let shards = self
+            .accounts
+            .read_many(commit_accounts(created_transactions, updated_accounts));
+        self.commit_outcome(&shards, created_transactions, updated_accounts)
+    }
+
+    fn commit_outcome<G: Deref<Target = AccountShard>>(
+        &self,
+        shards: &HashMap<usize, G>,
+        created_transactions: &[Transaction],
+        updated_accounts: &[AccountUpdate],
+    ) -> Result<Option<Vec<Transaction>>, String> {
+        let shard = |id: &Uuid| shard_of(id, SHARDS);
+        let recorded: BTreeSet<Uuid> = created_transactions
+            .iter()
+            .filter_map(|tx| {
+                shards[&shard(&tx.account_id)]
+                    .idempotency_keys
+                    .get(&(tx.account_id, tx.idempotency_key.clone()))
+                    .copied()
+            })
+            .collect();
+        if let Some(operation_id) = recorded.first() {
+            let operation = self.operation_transactions(operation_id);
+            if recorded.len() == 1 && operation.len() == created_transactions.len() {
+                return Ok(Some(operation));
+            }
+            return Err(IDEMPOTENCY_KEY_REUSED.to_string());
+        }
+
+        let stale = updated_accounts.iter().any(|update| {
+            let uuid = &update.account.uuid;
+            shards[&shard(uuid)]
+                .accounts
+                .get(uuid)
+                .is_none_or(|stored| stored.version != update.expected_version)
+        });
+        if stale {
+            return Err(VERSION_CONFLICT.to_string());
+        }
+        Ok(None)
+    }
+
+    fn operation_transactions(&self, operation_id: &Uuid) -> Vec<Transaction> {
+        let ids = self
+            .operations
+            .read(operation_id)
+            .get(operation_id)
+            .cloned()
+            .unwrap_or_default();
+        let mut legs: Vec<Transaction> = ids
+            .iter()
+            .filter_map(|id| self.transactions.read(id).get(id).cloned())
+            .collect();
+        legs.sort_by_key(|tx| (tx.created_at, tx.id));
+        legs
     }
 }
 
 #[async_trait]
 impl Storage for InMemoryStorage {
-    async fn save_account(&self, account: Account) -> Result<(), String> {
-        let mut accounts = self.accounts.lock().await;
-        accounts.insert(account.uuid, account.clone());
-        Ok(())
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        let mut shard = self.accounts.write(&account.uuid);
+        match shard.accounts.entry(account.uuid) {
+            Entry::Occupied(stored) => Ok(AccountCreation::AlreadyExists(stored.get().clone())),
+            Entry::Vacant(entry) => {
+                entry.insert(account);
+                Ok(AccountCreation::Created)
+            }
+        }
     }
 
     async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
-        let accounts = self.accounts.lock().await;
-        Ok(accounts.get(&uuid).cloned())
+        Ok(self.accounts.read(&uuid).accounts.get(&uuid).cloned())
     }
 
+    /// Replaying transactions whose idempotency keys were already recorded
+    /// returns the original operation's transactions and writes nothing.
     async fn save_transactions(
         &self,
         created_transactions: Vec<Transaction>,
-        updated_accounts: Vec<Account>,
+        updated_accounts: Vec<AccountUpdate>,
     ) -> Result<Vec<Transaction>, String> {
-        let mut accounts = self.accounts.lock().await;
-        for account in updated_accounts {
-            accounts.insert(account.uuid, account.clone());
+        let mut shards = self
+            .accounts
+            .write_many(commit_accounts(&created_transactions, &updated_accounts));
+        let shard = |id: &Uuid| shard_of(id, SHARDS);
+        let outcome = self.commit_outcome(&shards, &created_transactions, &updated_accounts)?;
+        if let Some(operation) = outcome {
+            return Ok(operation);
+        }
+
+        for update in updated_accounts {
+            let uuid = update.account.uuid;
+            shards
+                .get_mut(&shard(&uuid))
+                .unwrap()
+                .accounts
+                .insert(uuid, update.account);
+        }
+        for tx in &created_transactions {
+            let account_shard = shards.get_mut(&shard(&tx.account_id)).unwrap();
+            account_shard
+                .idempotency_keys
+                .insert((tx.account_id, tx.idempotency_key.clone()), tx.operation_id);
+            let history = account_shard.transactions.entry(tx.account_id).or_default();
+            let position = history.partition_point(|t| t.created_at <= tx.created_at);
+            history.insert(position, tx.clone());
+
+            // Indexes are filled while the accounts are still locked, so a
+            // committed transaction is immediately visible by id.
+            self.transactions.write(&tx.id).insert(tx.id, tx.clone());
+            self.operations
+                .write(&tx.operation_id)
+                .entry(tx.operation_id)
+                .or_default()
+                .push(tx.id);
         }
 
         Ok(created_transactions)
     }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let shard = self.accounts.read(&account_id);
+        let page = shard
+            .transactions
+            .get(&account_id)
+            .map(|history| {
+                let start = history.partition_point(|tx| tx.created_at < from);
+                history[start..]
+                    .iter()
+                    .take_while(|tx| tx.created_at < to)
+                    .take(limit)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(page)
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        Ok(self.transactions.read(&id).get(&id).cloned())
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        Ok(self.operation_transactions(&operation_id))
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        Ok(self
+            .accounts
+            .read(&account_id)
+            .idempotency_keys
+            .get(&(account_id, idempotency_key.to_string()))
+            .copied())
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let is_match =
+            |tx: &&Transaction| after.is_none_or(|cursor| cursor.precedes(tx)) && query.matches(tx);
+        let mut matches: Vec<Transaction> = match query.account_id {
+            Some(account_id) => self
+                .accounts
+                .read(&account_id)
+                .transactions
+                .get(&account_id)
+                .map(|history| history.iter().filter(is_match).cloned().collect())
+                .unwrap_or_default(),
+            None => self
+                .transactions
+                .read_all()
+                .flat_map(|shard| shard.values().filter(is_match).cloned().collect::<Vec<_>>())
+                .collect(),
+        };
+        matches.sort_by_key(|tx| (tx.created_at, tx.id));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+
+    use crate::model::OperationKind;
+
+    use super::*;
+
+    async fn setup() -> (InMemoryStorage, Account) {
+        let storage = InMemoryStorage::new();
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        (storage, account)
+    }
+
+    fn deposit(account: &Account, key: &str) -> (Transaction, AccountUpdate) {
+        let tx = Transaction::new(account, key, &BigDecimal::from(10), OperationKind::Deposit);
+        let update = account.apply(&tx);
+        (tx, update)
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        crate::storage::conformance::run(&InMemoryStorage::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_replay_returns_original_operation() {
+        let (storage, account) = setup().await;
+        let (tx, update) = deposit(&account, "idemp_1");
+        storage
+            .save_transactions(vec![tx.clone()], vec![update])
+            .await
+            .unwrap();
+
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        let (replay, update) = deposit(&stored, "idemp_1");
+        let result = storage
+            .save_transactions(vec![replay], vec![update])
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].id, tx.id);
+        let account = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(account.balance, BigDecimal::from(10));
+    }
+
+    #[tokio::test]
+    async fn test_key_reused_by_another_operation() {
+        let (storage, from) = setup().await;
+        let to = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(to.clone()).await.unwrap();
+        let (tx, update) = deposit(&from, "idemp_1");
+        storage
+            .save_transactions(vec![tx], vec![update])
+            .await
+            .unwrap();
+
+        let from = storage.get_account(from.uuid).await.unwrap().unwrap();
+        let (debit, _) = deposit(&from, "idemp_1");
+        let (credit, _) = deposit(&to, "idemp_1");
+        let result = storage.save_transactions(vec![debit, credit], vec![]).await;
+        assert_eq!(result.unwrap_err(), IDEMPOTENCY_KEY_REUSED);
+    }
+
+    #[tokio::test]
+    async fn test_stale_version_writes_nothing() {
+        let (storage, account) = setup().await;
+        let (first, update) = deposit(&account, "idemp_1");
+        storage
+            .save_transactions(vec![first], vec![update])
+            .await
+            .unwrap();
+
+        let (stale, update) = deposit(&account, "idemp_2");
+        let result = storage
+            .save_transactions(vec![stale.clone()], vec![update])
+            .await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+        assert!(storage.get_transaction(stale.id).await.unwrap().is_none());
+        let account = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(account.balance, BigDecimal::from(10));
+    }
+
+    #[tokio::test]
+    async fn test_missing_account_is_a_conflict() {
+        let storage = InMemoryStorage::new();
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let (tx, update) = deposit(&account, "idemp_1");
+
+        let result = storage.save_transactions(vec![tx], vec![update]).await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+    }
 }
@@ -0,0 +1,179 @@
+use std::{future::Future, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    metrics::{error_kind, STORAGE_OPERATION_DURATION},
+    model::{Account, AccountUpdate, Transaction},
+};
+
+use super::{AccountCreation, Storage};
+
+/// Storage decorator that records the latency of every call, labelled with
+/// `backend`, the operation and its outcome.
+pub struct MeteredStorage<S: Storage> {
+    inner: Arc<S>,
+    backend: &'static str,
+}
+
+impl<S: Storage> MeteredStorage<S> {
+    pub fn new(inner: &Arc<S>, backend: &'static str) -> Self {
+        MeteredStorage {
+            inner: Arc::clone(inner),
+            backend,
+        }
+    }
+
+    async fn observe<T>(
+        &self,
+        operation: &str,
+        call: impl Future<Output = Result<T, String>>,
+    ) -> Result<T, String> {
+        let start = Instant::now();
+        let result = call.await;
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(error) => error_kind(error),
+        };
+        STORAGE_OPERATION_DURATION
+            .with_label_values(&[self.backend, operation, outcome])
+            .observe(start.elapsed().as_secs_f64());
+        result
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for MeteredStorage<S> {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        self.observe("save_account", self.inner.save_account(account))
+            .await
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        self.observe("get_account", self.inner.get_account(uuid))
+            .await
+    }
+
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        self.observe(
+            "save_transactions",
+            self.inner
+                .save_transactions(created_transactions, updated_accounts),
+        )
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.observe(
+            "get_transactions",
+            self.inner.get_transactions(account_id, from, to, limit),
+        )
+        .await
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        self.observe("get_transaction", self.inner.get_transaction(id))
+            .await
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.observe(
+            "get_operation_transactions",
+            self.inner.get_operation_transactions(operation_id),
+        )
+        .await
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        self.observe(
+            "get_idempotency_key",
+            self.inner.get_idempotency_key(account_id, idempotency_key),
+        )
+        .await
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.observe(
+            "search_transactions",
+            self.inner.search_transactions(query, after, limit),
+        )
+        .await
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        self.observe("health_check", self.inner.health_check())
+            .await
+    }
+
+    async fn close(&self) -> Result<(), String> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+
+    use crate::{model::OperationKind, storage::InMemoryStorage};
+
+    use super::*;
+
+    fn deposit(account: &Account, key: &str) -> (Transaction, AccountUpdate) {
+        let tx = Transaction::new(account, key, &BigDecimal::from(1), OperationKind::Deposit);
+        let update = account.apply(&tx);
+        (tx, update)
+    }
+
+    #[tokio::test]
+    async fn test_observes_calls_by_outcome() {
+        let storage = MeteredStorage::new(&Arc::new(InMemoryStorage::new()), "metered_test");
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        storage.get_account(account.uuid).await.unwrap();
+        let (tx, update) = deposit(&account, "idemp_1");
+        storage
+            .save_transactions(vec![tx], vec![update])
+            .await
+            .unwrap();
+        let (stale, stale_update) = deposit(&account, "idemp_2");
+        let result = storage
+            .save_transactions(vec![stale], vec![stale_update])
+            .await;
+        assert!(result.is_err());
+
+        let count = |operation, outcome| {
+            STORAGE_OPERATION_DURATION
+                .with_label_values(&["metered_test", operation, outcome])
+                .get_sample_count()
+        };
+        assert_eq!(count("save_account", "ok"), 1);
+        assert_eq!(count("get_account", "ok"), 1);
+        assert_eq!(count("save_transactions", "ok"), 1);
+        assert_eq!(count("save_transactions", "version_conflict"), 1);
+    }
+}
@@ -1,18 +1,99 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::model::{Account, Transaction};
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Transaction},
+};
+
+/// Returned by `save_transactions` when an account is no longer at its
+/// expected version. Nothing was written, so the operation may be retried
+/// against freshly read accounts.
+pub const VERSION_CONFLICT: &str = "Account version conflict";
+
+/// Returned by `save_transactions` when an idempotency key of the
+/// transactions was already recorded for another operation.
+pub const IDEMPOTENCY_KEY_REUSED: &str = "Idempotency key was already used by another operation";
+
+/// Outcome of `save_account`, which never overwrites a stored account.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccountCreation {
+    Created,
+    /// An account with the same id was already stored and is left as it was.
+    AlreadyExists(Account),
+}
 
 #[async_trait]
 pub trait Storage: Send + Sync {
-    async fn save_account(&self, account: Account) -> Result<(), String>;
+    /// Stores a new account. An account already stored under the same id is
+    /// returned instead of being replaced.
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String>;
     async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String>;
+    /// Saves the transactions and account updates atomically. Nothing is
+    /// written unless every account is still at its expected version;
+    /// otherwise fails with `VERSION_CONFLICT`.
     async fn save_transactions(
         &self,
         created_transactions: Vec<Transaction>,
-        updated_accounts: Vec<Account>,
+        updated_accounts: Vec<AccountUpdate>,
     ) -> Result<Vec<Transaction>, String>;
+    /// Returns up to `limit` transactions of the account created in `[from, to)`,
+    /// oldest first. Transactions sharing a timestamp may straddle `limit`, so
+    /// callers paging through a history use `search_transactions` instead.
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String>;
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String>;
+    /// Returns every transaction written by the operation, e.g. both legs of
+    /// a transfer.
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String>;
+    /// Returns the operation that recorded `idempotency_key` for the account.
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String>;
+    /// Returns up to `limit` transactions matching every filter of `query`,
+    /// ordered by creation time then id, starting after `after` when given.
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String>;
+    /// Succeeds when the backend is reachable and its schema is up to date,
+    /// i.e. the service can take traffic.
+    async fn health_check(&self) -> Result<(), String>;
+    /// Waits for writes in progress and releases the backend's connections.
+    /// Called once on shutdown, after which the storage is not used again.
+    async fn close(&self) -> Result<(), String>;
 }
 
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
+
+pub mod cached;
+pub use cached::{CacheConfig, CachedStorage};
+
+pub mod faulty;
+pub use faulty::{Fault, FaultyStorage, StorageOperation, Trigger, STORAGE_TIMEOUT};
+
 pub mod in_memory;
 pub use in_memory::InMemoryStorage;
+
+pub mod metered;
+pub use metered::MeteredStorage;
+
+pub mod traced;
+pub use traced::TracedStorage;
+
+pub mod wal;
+pub use wal::{WalConfig, WalStorage};
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Transaction},
+};
+
+use super::{AccountCreation, Storage};
+
+/// Storage decorator that wraps every call in a `storage.*` span carrying the
+/// ids it touches. Errors are recorded on the span at `info`, since version
+/// conflicts and replays are part of normal operation.
+pub struct TracedStorage<S: Storage> {
+    inner: Arc<S>,
+}
+
+impl<S: Storage> TracedStorage<S> {
+    pub fn new(inner: &Arc<S>) -> Self {
+        TracedStorage {
+            inner: Arc::clone(inner),
+        }
+    }
+}
+
+fn account_ids(updates: &[AccountUpdate]) -> Vec<Uuid> {
+    updates.iter().map(|update| update.account.uuid).collect()
+}
+
+fn idempotency_keys(transactions: &[Transaction]) -> Vec<&str> {
+    let mut keys: Vec<&str> = transactions
+        .iter()
+        .map(|tx| tx.idempotency_key.as_str())
+        .collect();
+    keys.dedup();
+    keys
+}
+
+#[async_trait]
+impl<S: Storage> Storage for TracedStorage<S> {
+    #[tracing::instrument(
+        name = "storage.save_account",
+        skip_all,
+        fields(account_id = %account.uuid),
+        err(level = "info")
+    )]
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        self.inner.save_account(account).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_account",
+        skip_all,
+        fields(account_id = %uuid),
+        err(level = "info")
+    )]
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        self.inner.get_account(uuid).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.save_transactions",
+        skip_all,
+        fields(
+            account_ids = ?account_ids(&updated_accounts),
+            idempotency_keys = ?idempotency_keys(&created_transactions),
+            transactions = created_transactions.len(),
+        ),
+        err(level = "info")
+    )]
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner
+            .save_transactions(created_transactions, updated_accounts)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_transactions",
+        skip_all,
+        fields(account_id = %account_id, %from, %to, limit),
+        err(level = "info")
+    )]
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner
+            .get_transactions(account_id, from, to, limit)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_transaction",
+        skip_all,
+        fields(transaction_id = %id),
+        err(level = "info")
+    )]
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        self.inner.get_transaction(id).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_operation_transactions",
+        skip_all,
+        fields(operation_id = %operation_id),
+        err(level = "info")
+    )]
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner.get_operation_transactions(operation_id).await
+    }
+
+    #[tracing::instrument(
+        name = "storage.get_idempotency_key",
+        skip_all,
+        fields(account_id = %account_id, idempotency_key = %idempotency_key),
+        err(level = "info")
+    )]
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        self.inner
+            .get_idempotency_key(account_id, idempotency_key)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "storage.search_transactions",
+        skip_all,
+        fields(account_id = ?query.account_id, limit),
+        err(level = "info")
+    )]
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.inner.search_transactions(query, after, limit).await
+    }
+
+    #[tracing::instrument(name = "storage.health_check", skip_all, err(level = "info"))]
+    async fn health_check(&self) -> Result<(), String> {
+        self.inner.health_check().await
+    }
+
+    #[tracing::instrument(name = "storage.close", skip_all, err)]
+    async fn close(&self) -> Result<(), String> {
+        self.inner.close().await
+    }
+}
@@ -0,0 +1,594 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Transaction},
+    shutdown::StopSignal,
+};
+
+use self::record::Record;
+
+use super::{AccountCreation, InMemoryStorage, Storage};
+
+mod record;
+
+pub const DEFAULT_COMPACT_AFTER_BYTES: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_COMPACT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const SNAPSHOT_FILE: &str = "snapshot";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.tmp";
+
+const UNUSABLE: &str = "WAL storage stopped after a failed write; restart to recover";
+
+pub struct WalConfig {
+    /// Directory holding the snapshot and the log, created if missing.
+    pub dir: PathBuf,
+    /// The log is compacted into a new snapshot once it grows past this size.
+    pub compact_after_bytes: u64,
+}
+
+/// The log currently appended to. Each compaction starts a new generation,
+/// and the snapshot names the generation that continues it.
+struct Log {
+    file: File,
+    generation: u64,
+    len: u64,
+}
+
+/// Durable storage without a database server: every write is appended to a
+/// checksummed log and fsynced before it is acknowledged, and the state is
+/// served from memory, rebuilt from the snapshot and log on startup.
+///
+/// Writes are serialized by the log lock. If appending fails, the log may end
+/// in a partial record, so the storage refuses further writes until it is
+/// reopened, which drops that record.
+pub struct WalStorage {
+    state: InMemoryStorage,
+    log: Mutex<Log>,
+    dir: PathBuf,
+    compact_after_bytes: u64,
+    failed: AtomicBool,
+}
+
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("wal-{}.log", generation))
+}
+
+async fn read_if_exists(path: &Path) -> Result<Vec<u8>, String> {
+    match fs::read(path).await {
+        Ok(bytes) => Ok(bytes),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+async fn sync_dir(dir: &Path) -> Result<(), String> {
+    let sync_error = |e: std::io::Error| format!("Failed to sync {}: {}", dir.display(), e);
+    let dir = File::open(dir).await.map_err(sync_error)?;
+    dir.sync_all().await.map_err(sync_error)
+}
+
+async fn replay(state: &InMemoryStorage, record: Record) -> Result<(), String> {
+    match record {
+        Record::Snapshot { .. } => Err("Unexpected snapshot record in the WAL".to_string()),
+        Record::Account { account } => {
+            state.save_account(account.into()).await?;
+            Ok(())
+        }
+        Record::Commit {
+            transactions,
+            updates,
+        } => {
+            let transactions = transactions.into_iter().map(Into::into).collect();
+            let updates = updates.into_iter().map(Into::into).collect();
+            state.save_transactions(transactions, updates).await?;
+            Ok(())
+        }
+    }
+}
+
+impl WalStorage {
+    /// Loads the snapshot and replays the log after it. A torn final record,
+    /// left by a crash in the middle of an append, is truncated away.
+    pub async fn open(config: WalConfig) -> Result<Self, String> {
+        let dir = config.dir;
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let state = InMemoryStorage::new();
+        let mut generation = 0;
+        let snapshot = read_if_exists(&dir.join(SNAPSHOT_FILE)).await?;
+        if !snapshot.is_empty() {
+            let (records, len) = record::decode(&snapshot)?;
+            let mut records = records.into_iter();
+            match records.next() {
+                Some(Record::Snapshot { generation: next }) if len == snapshot.len() => {
+                    generation = next
+                }
+                _ => return Err("Snapshot is incomplete".to_string()),
+            }
+            for record in records {
+                replay(&state, record).await?;
+            }
+        }
+
+        let path = log_path(&dir, generation);
+        let bytes = read_if_exists(&path).await?;
+        let (records, len) = record::decode(&bytes)?;
+        for record in records {
+            replay(&state, record).await?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        if len < bytes.len() {
+            let truncate_error = |e| format!("Failed to truncate {}: {}", path.display(), e);
+            file.set_len(len as u64).await.map_err(truncate_error)?;
+            file.sync_all().await.map_err(truncate_error)?;
+        }
+
+        let storage = WalStorage {
+            state,
+            log: Mutex::new(Log {
+                file,
+                generation,
+                len: len as u64,
+            }),
+            dir,
+            compact_after_bytes: config.compact_after_bytes,
+            failed: AtomicBool::new(false),
+        };
+        storage.remove_stale_files(generation).await?;
+        Ok(storage)
+    }
+
+    /// Writes the whole state into a new snapshot and starts an empty log.
+    /// Until the snapshot is renamed into place, a crash leaves the previous
+    /// snapshot and log untouched.
+    pub async fn compact(&self) -> Result<(), String> {
+        let mut log = self.log.lock().await;
+        self.check_usable()?;
+        self.compact_log(&mut log).await
+    }
+
+    /// Compacts every `interval` until `stop` flips, so a log that grows
+    /// slowly is not replayed in full on every restart. An empty log is left
+    /// alone, and a failure is logged and retried at the next tick.
+    pub async fn compact_every(&self, interval: Duration, mut stop: StopSignal) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = stop.stopped() => return,
+            }
+            let mut log = self.log.lock().await;
+            if log.len == 0 || self.check_usable().is_err() {
+                continue;
+            }
+            if let Err(error) = self.compact_log(&mut log).await {
+                tracing::warn!(%error, "Periodic WAL compaction failed");
+            }
+        }
+    }
+
+    fn check_usable(&self) -> Result<(), String> {
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(UNUSABLE.to_string());
+        }
+        Ok(())
+    }
+
+    async fn append(&self, log: &mut Log, record: &Record) -> Result<(), String> {
+        let frame = record.encode();
+        let result = async {
+            log.file.write_all(&frame).await?;
+            log.file.flush().await?;
+            log.file.sync_data().await
+        }
+        .await;
+        if let Err(e) = result {
+            self.failed.store(true, Ordering::SeqCst);
+            return Err(format!("Failed to append to the WAL: {}", e));
+        }
+        log.len += frame.len() as u64;
+        Ok(())
+    }
+
+    /// Compacts once the log is big enough. The write that triggered it is
+    /// already durable, so a failure here is not reported to its caller: a
+    /// failure before the new snapshot is in place is retried on the next
+    /// write, and one after it stops the storage.
+    async fn compact_if_needed(&self, log: &mut Log) {
+        if log.len >= self.compact_after_bytes {
+            let _ = self.compact_log(log).await;
+        }
+    }
+
+    async fn compact_log(&self, log: &mut Log) -> Result<(), String> {
+        let generation = log.generation + 1;
+        let mut snapshot = Record::Snapshot { generation }.encode();
+        for account in self.state.accounts() {
+            snapshot.extend(Record::account(&account).encode());
+        }
+        let mut operations: Vec<Vec<Transaction>> = vec![];
+        let mut positions: HashMap<Uuid, usize> = HashMap::new();
+        for tx in self.state.transactions() {
+            let position = *positions.entry(tx.operation_id).or_insert_with(|| {
+                operations.push(vec![]);
+                operations.len() - 1
+            });
+            operations[position].push(tx);
+        }
+        for operation in &operations {
+            snapshot.extend(Record::commit(operation, &[]).encode());
+        }
+
+        let tmp_path = self.dir.join(SNAPSHOT_TMP_FILE);
+        let mut tmp = File::create(&tmp_path)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", tmp_path.display(), e))?;
+        let write_error = |e| format!("Failed to write {}: {}", tmp_path.display(), e);
+        tmp.write_all(&snapshot).await.map_err(write_error)?;
+        tmp.sync_all().await.map_err(write_error)?;
+        fs::rename(&tmp_path, self.dir.join(SNAPSHOT_FILE))
+            .await
+            .map_err(|e| format!("Failed to replace the snapshot: {}", e))?;
+
+        // The new snapshot now points at a log that does not exist yet, so
+        // appending to the previous one would lose writes on restart.
+        let result = self.start_generation(log, generation).await;
+        if result.is_err() {
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        result
+    }
+
+    async fn start_generation(&self, log: &mut Log, generation: u64) -> Result<(), String> {
+        sync_dir(&self.dir).await?;
+        let path = log_path(&self.dir, generation);
+        let file = File::create(&path)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        sync_dir(&self.dir).await?;
+        *log = Log {
+            file,
+            generation,
+            len: 0,
+        };
+        self.remove_stale_files(generation).await
+    }
+
+    /// Removes logs of other generations and unfinished snapshots.
+    async fn remove_stale_files(&self, generation: u64) -> Result<(), String> {
+        let current = log_path(&self.dir, generation);
+        let mut entries = fs::read_dir(&self.dir)
+            .await
+            .map_err(|e| format!("Failed to list {}: {}", self.dir.display(), e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to list {}: {}", self.dir.display(), e))?
+        {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let stale_log = name.starts_with("wal-") && name.ends_with(".log") && path != current;
+            if stale_log || name == SNAPSHOT_TMP_FILE {
+                fs::remove_file(&path)
+                    .await
+                    .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for WalStorage {
+    /// Accounts that already exist are not logged again.
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        let mut log = self.log.lock().await;
+        self.check_usable()?;
+        if let Some(stored) = self.state.get_account(account.uuid).await? {
+            return Ok(AccountCreation::AlreadyExists(stored));
+        }
+        self.append(&mut log, &Record::account(&account)).await?;
+        let creation = self.state.save_account(account).await?;
+        self.compact_if_needed(&mut log).await;
+        Ok(creation)
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        self.state.get_account(uuid).await
+    }
+
+    /// The commit is checked against the in-memory state first, so conflicts
+    /// and idempotent replays never reach the log, and applied only once it
+    /// is durable. Holding the log lock, nothing else writes in between.
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        let mut log = self.log.lock().await;
+        self.check_usable()?;
+        let replayed = self
+            .state
+            .check_commit(&created_transactions, &updated_accounts)?;
+        if let Some(operation) = replayed {
+            return Ok(operation);
+        }
+        let record = Record::commit(&created_transactions, &updated_accounts);
+        self.append(&mut log, &record).await?;
+        let saved = self
+            .state
+            .save_transactions(created_transactions, updated_accounts)
+            .await?;
+        self.compact_if_needed(&mut log).await;
+        Ok(saved)
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.state
+            .get_transactions(account_id, from, to, limit)
+            .await
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        self.state.get_transaction(id).await
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.state.get_operation_transactions(operation_id).await
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        self.state
+            .get_idempotency_key(account_id, idempotency_key)
+            .await
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        self.state.search_transactions(query, after, limit).await
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(UNUSABLE.to_string());
+        }
+        Ok(())
+    }
+
+    /// Every acknowledged write is already fsynced, so closing only waits for
+    /// the append in progress, if any.
+    async fn close(&self) -> Result<(), String> {
+        let _log = self.log.lock().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+
+    use std::sync::Arc;
+
+    use crate::{model::OperationKind, shutdown::Workers, storage::VERSION_CONFLICT};
+
+    use super::*;
+
+    fn config(dir: &Path, compact_after_bytes: u64) -> WalConfig {
+        WalConfig {
+            dir: dir.to_path_buf(),
+            compact_after_bytes,
+        }
+    }
+
+    async fn setup(compact_after_bytes: u64) -> (PathBuf, WalStorage, Account) {
+        let dir = std::env::temp_dir().join(format!("wal-test-{}", Uuid::new_v4()));
+        let storage = WalStorage::open(config(&dir, compact_after_bytes))
+            .await
+            .unwrap();
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        (dir, storage, account)
+    }
+
+    async fn deposit(storage: &WalStorage, account_id: Uuid, key: &str) -> Transaction {
+        let account = storage.get_account(account_id).await.unwrap().unwrap();
+        let tx = Transaction::new(&account, key, &BigDecimal::from(10), OperationKind::Deposit);
+        let update = account.apply(&tx);
+        storage
+            .save_transactions(vec![tx], vec![update])
+            .await
+            .unwrap()
+            .remove(0)
+    }
+
+    fn log_files(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".log"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let (dir, storage, _) = setup(DEFAULT_COMPACT_AFTER_BYTES).await;
+        crate::storage::conformance::run(&storage).await;
+        drop(storage);
+
+        // Replaying the log must rebuild a state that still conforms.
+        let storage = WalStorage::open(config(&dir, DEFAULT_COMPACT_AFTER_BYTES))
+            .await
+            .unwrap();
+        crate::storage::conformance::run(&storage).await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reopen_restores_state() {
+        let (dir, storage, account) = setup(DEFAULT_COMPACT_AFTER_BYTES).await;
+        let tx = deposit(&storage, account.uuid, "idemp_1").await;
+        drop(storage);
+
+        let storage = WalStorage::open(config(&dir, DEFAULT_COMPACT_AFTER_BYTES))
+            .await
+            .unwrap();
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+        assert_eq!(stored.version, tx.account_version);
+        let stored_tx = storage.get_transaction(tx.id).await.unwrap().unwrap();
+        assert_eq!(stored_tx.created_at, tx.created_at);
+        assert_eq!(stored_tx.balance_after, tx.balance_after);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_torn_final_record_is_dropped() {
+        let (dir, storage, account) = setup(DEFAULT_COMPACT_AFTER_BYTES).await;
+        deposit(&storage, account.uuid, "idemp_1").await;
+        drop(storage);
+
+        let path = log_path(&dir, 0);
+        let intact = std::fs::read(&path).unwrap();
+        let frame = Record::account(&account).encode();
+        let mut torn = intact.clone();
+        torn.extend_from_slice(&frame[..frame.len() / 2]);
+        std::fs::write(&path, torn).unwrap();
+
+        let storage = WalStorage::open(config(&dir, DEFAULT_COMPACT_AFTER_BYTES))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), intact);
+        deposit(&storage, account.uuid, "idemp_2").await;
+        drop(storage);
+
+        let storage = WalStorage::open(config(&dir, DEFAULT_COMPACT_AFTER_BYTES))
+            .await
+            .unwrap();
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(20));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_are_not_logged() {
+        let (dir, storage, account) = setup(DEFAULT_COMPACT_AFTER_BYTES).await;
+        deposit(&storage, account.uuid, "idemp_1").await;
+        let len = storage.log.lock().await.len;
+
+        let tx = Transaction::new(
+            &account,
+            "idemp_2",
+            &BigDecimal::from(10),
+            OperationKind::Deposit,
+        );
+        let update = account.apply(&tx);
+        let result = storage.save_transactions(vec![tx], vec![update]).await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+        assert_eq!(storage.log.lock().await.len, len);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_append_is_not_applied() {
+        let (dir, storage, account) = setup(DEFAULT_COMPACT_AFTER_BYTES).await;
+        // A read-only handle makes the next append fail.
+        storage.log.lock().await.file = File::open(log_path(&dir, 0)).await.unwrap();
+
+        let tx = Transaction::new(
+            &account,
+            "idemp_1",
+            &BigDecimal::from(10),
+            OperationKind::Deposit,
+        );
+        let update = account.apply(&tx);
+        let result = storage
+            .save_transactions(vec![tx.clone()], vec![update])
+            .await;
+        assert!(result.is_err());
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored, account);
+        assert!(storage.get_transaction(tx.id).await.unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_periodic_compaction() {
+        let (dir, storage, account) = setup(DEFAULT_COMPACT_AFTER_BYTES).await;
+        let storage = Arc::new(storage);
+        let mut workers = Workers::new();
+        let compactor = Arc::clone(&storage);
+        workers.spawn("wal_compactor", |stop| async move {
+            compactor.compact_every(Duration::from_secs(60), stop).await
+        });
+
+        tokio::time::sleep(Duration::from_secs(90)).await;
+        assert_eq!(storage.log.lock().await.generation, 1);
+        // Nothing was written since, so the next tick leaves the log alone.
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(storage.log.lock().await.generation, 1);
+
+        deposit(&storage, account.uuid, "idemp_1").await;
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(storage.log.lock().await.generation, 2);
+        assert_eq!(workers.shutdown(Duration::from_secs(1)).await, 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compaction_keeps_state_and_idempotency() {
+        let (dir, storage, account) = setup(1).await;
+        let first = deposit(&storage, account.uuid, "idemp_1").await;
+        deposit(&storage, account.uuid, "idemp_2").await;
+        assert_eq!(storage.log.lock().await.generation, 3);
+        assert_eq!(log_files(&dir), vec!["wal-3.log".to_string()]);
+        drop(storage);
+
+        let storage = WalStorage::open(config(&dir, DEFAULT_COMPACT_AFTER_BYTES))
+            .await
+            .unwrap();
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(20));
+        let replay = deposit(&storage, account.uuid, "idemp_1").await;
+        assert_eq!(replay.id, first.id);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
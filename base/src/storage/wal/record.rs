@@ -0,0 +1,288 @@
+use bigdecimal::BigDecimal;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::model::{
+    Account, AccountUpdate, Details, OperationKind, Transaction, TransactionStatus,
+};
+
+/// Every record is framed as a little-endian payload length, the CRC-32 of
+/// the payload, then the JSON payload itself.
+const HEADER_LEN: usize = 8;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (IEEE), as used by zip and Ethernet.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc = CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum Record {
+    /// First record of a snapshot: the generation of the log that continues it.
+    Snapshot {
+        generation: u64,
+    },
+    Account {
+        account: AccountRecord,
+    },
+    Commit {
+        transactions: Vec<TransactionRecord>,
+        updates: Vec<AccountUpdateRecord>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct AccountRecord {
+    uuid: Uuid,
+    currency: String,
+    balance: BigDecimal,
+    created_at_in_nanos: i64,
+    last_updated_at_in_nanos: i64,
+    version: Uuid,
+    details: Details,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct AccountUpdateRecord {
+    account: AccountRecord,
+    expected_version: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct TransactionRecord {
+    id: Uuid,
+    operation_id: Uuid,
+    counterparty_account_id: Option<Uuid>,
+    kind: OperationKind,
+    status: TransactionStatus,
+    idempotency_key: String,
+    account_version: Uuid,
+    account_id: Uuid,
+    amount: BigDecimal,
+    balance_after: BigDecimal,
+    created_at_in_nanos: i64,
+    currency: String,
+    details: Details,
+}
+
+impl Record {
+    pub(super) fn account(account: &Account) -> Self {
+        Record::Account {
+            account: account.into(),
+        }
+    }
+
+    pub(super) fn commit(transactions: &[Transaction], updates: &[AccountUpdate]) -> Self {
+        Record::Commit {
+            transactions: transactions.iter().map(Into::into).collect(),
+            updates: updates.iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The framed record, ready to be appended.
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let payload = serde_json::to_vec(self).unwrap();
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        frame
+    }
+}
+
+/// Decodes the records of a log, stopping at a torn final record: one cut
+/// short, or whose checksum fails with nothing after it. Returns the records
+/// and the length of the intact prefix. A damaged record followed by more
+/// data is not a torn write, so it fails instead of dropping what follows.
+pub(super) fn decode(bytes: &[u8]) -> Result<(Vec<Record>, usize), String> {
+    let mut records = vec![];
+    let mut offset = 0;
+    while bytes.len() - offset >= HEADER_LEN {
+        let header = &bytes[offset..offset + HEADER_LEN];
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let end = offset + HEADER_LEN + len;
+        let Some(payload) = bytes.get(offset + HEADER_LEN..end) else {
+            break;
+        };
+        let record = (crc32(payload) == crc)
+            .then(|| serde_json::from_slice(payload).ok())
+            .flatten();
+        match record {
+            Some(record) => records.push(record),
+            None if end == bytes.len() => break,
+            None => return Err(format!("Corrupt WAL record at byte {}", offset)),
+        }
+        offset = end;
+    }
+    Ok((records, offset))
+}
+
+impl From<&Account> for AccountRecord {
+    fn from(account: &Account) -> Self {
+        AccountRecord {
+            uuid: account.uuid,
+            currency: account.currency.clone(),
+            balance: account.balance.clone(),
+            created_at_in_nanos: account.created_at.timestamp_nanos_opt().unwrap(),
+            last_updated_at_in_nanos: account.last_updated_at.timestamp_nanos_opt().unwrap(),
+            version: account.version,
+            details: account.details.clone(),
+        }
+    }
+}
+
+impl From<AccountRecord> for Account {
+    fn from(record: AccountRecord) -> Self {
+        let mut account = Account::from_storage(
+            record.uuid,
+            record.currency,
+            record.balance,
+            record.created_at_in_nanos,
+            record.last_updated_at_in_nanos,
+            record.version,
+        );
+        account.details = record.details;
+        account
+    }
+}
+
+impl From<&AccountUpdate> for AccountUpdateRecord {
+    fn from(update: &AccountUpdate) -> Self {
+        AccountUpdateRecord {
+            account: (&update.account).into(),
+            expected_version: update.expected_version,
+        }
+    }
+}
+
+impl From<AccountUpdateRecord> for AccountUpdate {
+    fn from(record: AccountUpdateRecord) -> Self {
+        AccountUpdate {
+            account: record.account.into(),
+            expected_version: record.expected_version,
+        }
+    }
+}
+
+impl From<&Transaction> for TransactionRecord {
+    fn from(tx: &Transaction) -> Self {
+        TransactionRecord {
+            id: tx.id,
+            operation_id: tx.operation_id,
+            counterparty_account_id: tx.counterparty_account_id,
+            kind: tx.kind,
+            status: tx.status,
+            idempotency_key: tx.idempotency_key.clone(),
+            account_version: tx.account_version,
+            account_id: tx.account_id,
+            amount: tx.amount.clone(),
+            balance_after: tx.balance_after.clone(),
+            created_at_in_nanos: tx.created_at.timestamp_nanos_opt().unwrap(),
+            currency: tx.currency.clone(),
+            details: tx.details.clone(),
+        }
+    }
+}
+
+impl From<TransactionRecord> for Transaction {
+    fn from(record: TransactionRecord) -> Self {
+        Transaction {
+            id: record.id,
+            operation_id: record.operation_id,
+            counterparty_account_id: record.counterparty_account_id,
+            kind: record.kind,
+            status: record.status,
+            idempotency_key: record.idempotency_key,
+            account_version: record.account_version,
+            account_id: record.account_id,
+            amount: record.amount,
+            balance_after: record.balance_after,
+            created_at: Utc.timestamp_nanos(record.created_at_in_nanos),
+            currency: record.currency,
+            details: record.details,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_record() -> Vec<u8> {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        Record::account(&account).encode()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let mut log = account_record();
+        log.extend(account_record());
+        let (records, len) = decode(&log).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(len, log.len());
+    }
+
+    #[test]
+    fn test_decode_stops_at_torn_record() {
+        let mut log = account_record();
+        let intact = log.len();
+        let second = account_record();
+        log.extend(&second[..second.len() - 3]);
+        let (records, len) = decode(&log).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(len, intact);
+
+        log.truncate(intact + 5);
+        assert_eq!(decode(&log).unwrap().1, intact);
+    }
+
+    #[test]
+    fn test_decode_stops_at_damaged_final_record() {
+        let mut log = account_record();
+        let intact = log.len();
+        log.extend(account_record());
+        *log.last_mut().unwrap() ^= 0xFF;
+        assert_eq!(decode(&log).unwrap().1, intact);
+    }
+
+    #[test]
+    fn test_decode_rejects_damage_before_the_end() {
+        let mut log = account_record();
+        log[HEADER_LEN + 2] ^= 0xFF;
+        log.extend(account_record());
+        assert!(decode(&log).is_err());
+    }
+}
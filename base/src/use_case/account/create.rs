@@ -1,19 +1,21 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
 
 use crate::{
-    dto::account::AccountCreationDTO, model::Account, storage::Storage, use_case::UseCase,
+    dto::account::{AccountCreationDTO, AccountCreationResultDTO},
+    model::{Account, ACCOUNT_ALREADY_EXISTS},
+    storage::{AccountCreation, Storage},
+    use_case::UseCase,
 };
 
 #[derive(Debug, Clone)]
 pub struct CreateAccountUseCase<S: Storage> {
-    storage: Arc<Mutex<S>>,
+    storage: Arc<S>,
 }
 
 impl<S: Storage> CreateAccountUseCase<S> {
-    pub fn new(storage: &Arc<Mutex<S>>) -> Self {
+    pub fn new(storage: &Arc<S>) -> Self {
         CreateAccountUseCase {
             storage: Arc::clone(storage),
         }
@@ -21,21 +23,38 @@ impl<S: Storage> CreateAccountUseCase<S> {
 }
 
 #[async_trait]
-impl<S: Storage> UseCase<AccountCreationDTO, Account> for CreateAccountUseCase<S> {
-    async fn execute(&self, input: AccountCreationDTO) -> Result<Account, String> {
-        let storage = self.storage.lock().await;
+impl<S: Storage> UseCase<AccountCreationDTO, AccountCreationResultDTO> for CreateAccountUseCase<S> {
+    /// Repeating an identical request returns the account it created, so
+    /// clients can retry creation safely.
+    #[tracing::instrument(name = "create_account", skip_all, fields(account_id = %input.uuid, currency = %input.currency))]
+    async fn execute(&self, input: AccountCreationDTO) -> Result<AccountCreationResultDTO, String> {
+        input.details.validate()?;
         let account: Account = input.into();
-        storage.save_account(account.clone()).await?;
-        Ok(account)
+        match self.storage.save_account(account.clone()).await? {
+            AccountCreation::Created => Ok(AccountCreationResultDTO {
+                account,
+                created: true,
+            }),
+            AccountCreation::AlreadyExists(stored)
+                if stored.currency == account.currency && stored.details == account.details =>
+            {
+                Ok(AccountCreationResultDTO {
+                    account: stored,
+                    created: false,
+                })
+            }
+            AccountCreation::AlreadyExists(_) => Err(ACCOUNT_ALREADY_EXISTS.to_string()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use bigdecimal::BigDecimal;
     use uuid::Uuid;
 
-    use crate::storage::InMemoryStorage;
+    use crate::{model::Details, storage::InMemoryStorage};
 
     use super::*;
 
@@ -43,15 +62,15 @@ mod tests {
     async fn test_execute_successful() {
         let test_uuid = Uuid::new_v4();
         let account = Account::new(test_uuid, &"BRL".to_string());
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let storage = Arc::new(InMemoryStorage::new());
         let use_case = CreateAccountUseCase::new(&storage);
         let input = AccountCreationDTO {
             uuid: test_uuid,
             currency: "BRL".to_string(),
+            details: Details::default(),
         };
         let result = use_case.execute(input).await;
         assert!(result.is_ok());
-        let storage = storage.lock().await;
         let stored_account = storage.get_account(test_uuid).await;
         assert!(stored_account.clone().is_ok());
         assert!(stored_account.clone().unwrap().is_some());
@@ -59,4 +78,90 @@ mod tests {
         assert_eq!(&stored_account.uuid, &account.uuid);
         assert_eq!(&stored_account.currency, &account.currency);
     }
+
+    #[tokio::test]
+    async fn test_execute_with_details() {
+        let test_uuid = Uuid::new_v4();
+        let storage = Arc::new(InMemoryStorage::new());
+        let use_case = CreateAccountUseCase::new(&storage);
+        let details = Details {
+            description: Some("Merchant settlement".to_string()),
+            external_reference: Some("merchant-7".to_string()),
+            metadata: vec![("segment".to_string(), "retail".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        let input = AccountCreationDTO {
+            uuid: test_uuid,
+            currency: "BRL".to_string(),
+            details: details.clone(),
+        };
+        assert!(use_case.execute(input).await.is_ok());
+        let stored_account = storage.get_account(test_uuid).await.unwrap().unwrap();
+        assert_eq!(stored_account.details, details);
+    }
+
+    #[tokio::test]
+    async fn test_identical_request_returns_existing_account() {
+        let test_uuid = Uuid::new_v4();
+        let storage = Arc::new(InMemoryStorage::new());
+        let use_case = CreateAccountUseCase::new(&storage);
+        let input = AccountCreationDTO {
+            uuid: test_uuid,
+            currency: "BRL".to_string(),
+            details: Details::default(),
+        };
+        let created = use_case.execute(input.clone()).await.unwrap();
+        assert!(created.created);
+        let stored = storage.get_account(test_uuid).await.unwrap().unwrap();
+        let mut moved = stored.clone();
+        moved.balance = BigDecimal::from(10);
+        storage.set_accounts([(test_uuid, moved)].into()).await;
+
+        let repeated = use_case.execute(input).await.unwrap();
+        assert!(!repeated.created);
+        assert_eq!(repeated.account.version, created.account.version);
+        assert_eq!(repeated.account.balance, BigDecimal::from(10));
+    }
+
+    #[tokio::test]
+    async fn test_different_request_conflicts() {
+        let test_uuid = Uuid::new_v4();
+        let storage = Arc::new(InMemoryStorage::new());
+        let use_case = CreateAccountUseCase::new(&storage);
+        let input = AccountCreationDTO {
+            uuid: test_uuid,
+            currency: "BRL".to_string(),
+            details: Details::default(),
+        };
+        use_case.execute(input.clone()).await.unwrap();
+
+        let result = use_case
+            .execute(AccountCreationDTO {
+                currency: "USD".to_string(),
+                ..input
+            })
+            .await;
+        assert_eq!(result.unwrap_err(), ACCOUNT_ALREADY_EXISTS);
+        let stored = storage.get_account(test_uuid).await.unwrap().unwrap();
+        assert_eq!(stored.currency, "BRL");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_details() {
+        let test_uuid = Uuid::new_v4();
+        let storage = Arc::new(InMemoryStorage::new());
+        let use_case = CreateAccountUseCase::new(&storage);
+        let input = AccountCreationDTO {
+            uuid: test_uuid,
+            currency: "BRL".to_string(),
+            details: Details {
+                description: Some("x".repeat(257)),
+                ..Details::default()
+            },
+        };
+        let result = use_case.execute(input).await;
+        assert_eq!(result.unwrap_err(), "description exceeds 256 characters");
+        assert!(storage.get_account(test_uuid).await.unwrap().is_none());
+    }
 }
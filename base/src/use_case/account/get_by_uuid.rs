@@ -1,18 +1,17 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
 use uuid::Uuid;
 
 use crate::{model::Account, storage::Storage, use_case::UseCase};
 
 #[derive(Debug, Clone)]
 pub struct GetAccountByUuidUseCase<S: Storage> {
-    storage: Arc<Mutex<S>>,
+    storage: Arc<S>,
 }
 
 impl<S: Storage> GetAccountByUuidUseCase<S> {
-    pub fn new(storage: &Arc<Mutex<S>>) -> Self {
+    pub fn new(storage: &Arc<S>) -> Self {
         GetAccountByUuidUseCase {
             storage: Arc::clone(storage),
         }
@@ -21,9 +20,9 @@ impl<S: Storage> GetAccountByUuidUseCase<S> {
 
 #[async_trait]
 impl<S: Storage> UseCase<Uuid, Option<Account>> for GetAccountByUuidUseCase<S> {
+    #[tracing::instrument(name = "get_account", skip_all, fields(account_id = %input))]
     async fn execute(&self, input: Uuid) -> Result<Option<Account>, String> {
-        let storage = self.storage.lock().await;
-        let result = storage.get_account(input).await?;
+        let result = self.storage.get_account(input).await?;
         Ok(result)
     }
 }
@@ -39,10 +38,8 @@ mod tests {
     async fn test_execute_successful() {
         let test_id = Uuid::new_v4();
         let account = Account::new(test_id, &"BRL".to_string());
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let storage = Arc::new(InMemoryStorage::new());
         storage
-            .lock()
-            .await
             .set_accounts(vec![(test_id, account.clone())].into_iter().collect())
             .await;
         let use_case = GetAccountByUuidUseCase::new(&storage);
@@ -54,7 +51,7 @@ mod tests {
     #[tokio::test]
     async fn test_execute_no_account_found() {
         let test_id = Uuid::new_v4();
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let storage = Arc::new(InMemoryStorage::new());
         let use_case = GetAccountByUuidUseCase::new(&storage);
         let result = use_case.execute(test_id).await;
         assert!(result.is_ok());
@@ -1,5 +1,7 @@
 pub mod create;
 pub mod get_by_uuid;
+pub mod statement;
 
 pub use create::CreateAccountUseCase;
 pub use get_by_uuid::GetAccountByUuidUseCase;
+pub use statement::GenerateStatementUseCase;
@@ -0,0 +1,410 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use uuid::Uuid;
+
+use crate::{
+    dto::{
+        search::{SearchCursor, TransactionSearchDTO},
+        statement::StatementRequestDTO,
+    },
+    model::{
+        Account, Statement, StatementEntry, StatementHeader, StatementLine, StatementSummary,
+        Transaction,
+    },
+    storage::Storage,
+    use_case::UseCase,
+};
+
+const PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct GenerateStatementUseCase<S: Storage> {
+    storage: Arc<S>,
+}
+
+impl<S: Storage> GenerateStatementUseCase<S> {
+    pub fn new(storage: &Arc<S>) -> Self {
+        GenerateStatementUseCase {
+            storage: Arc::clone(storage),
+        }
+    }
+}
+
+/// Pages by `(created_at, id)` rather than by time alone, so transactions
+/// sharing a timestamp are never skipped across a page boundary.
+async fn fetch_page<S: Storage>(
+    storage: &Arc<S>,
+    account_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    after: Option<SearchCursor>,
+) -> Result<Vec<Transaction>, String> {
+    let query = TransactionSearchDTO {
+        account_id: Some(account_id),
+        from: Some(from),
+        to: Some(to),
+        ..Default::default()
+    };
+    storage.search_transactions(&query, after, PAGE_SIZE).await
+}
+
+fn next_after(page: &[Transaction], after: Option<SearchCursor>) -> Option<SearchCursor> {
+    page.last().map(SearchCursor::of).or(after)
+}
+
+/// Each transaction records the balance it left, so the opening balance is
+/// the one the first transaction of the period started from. Without
+/// transactions since `from`, it is the account's current balance.
+async fn opening_balance<S: Storage>(
+    storage: &Arc<S>,
+    account: &Account,
+    from: DateTime<Utc>,
+) -> Result<BigDecimal, String> {
+    let query = TransactionSearchDTO {
+        account_id: Some(account.uuid),
+        from: Some(from),
+        ..Default::default()
+    };
+    let first = storage.search_transactions(&query, None, 1).await?;
+    let balance = match first.first() {
+        Some(transaction) => &transaction.balance_after - &transaction.amount,
+        None => account.balance.clone(),
+    };
+    Ok(balance.with_scale(2))
+}
+
+struct StatementCursor<S: Storage> {
+    storage: Arc<S>,
+    account_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    after: Option<SearchCursor>,
+    balance: BigDecimal,
+    total_in: BigDecimal,
+    total_out: BigDecimal,
+    page: VecDeque<Transaction>,
+    exhausted: bool,
+    finished: bool,
+}
+
+impl<S: Storage> StatementCursor<S> {
+    fn apply(&mut self, transaction: Transaction) -> StatementLine {
+        if transaction.amount < BigDecimal::zero() {
+            self.total_out = &self.total_out - &transaction.amount;
+        } else {
+            self.total_in = &self.total_in + &transaction.amount;
+        }
+        self.balance = &self.balance + &transaction.amount;
+        StatementLine {
+            transaction,
+            running_balance: self.balance.clone(),
+        }
+    }
+
+    fn summary(&self) -> StatementSummary {
+        StatementSummary {
+            total_in: self.total_in.clone(),
+            total_out: self.total_out.clone(),
+            closing_balance: self.balance.clone(),
+        }
+    }
+}
+
+async fn next_entry<S: Storage>(
+    mut cursor: StatementCursor<S>,
+) -> Option<(Result<StatementEntry, String>, StatementCursor<S>)> {
+    if cursor.finished {
+        return None;
+    }
+
+    if cursor.page.is_empty() && !cursor.exhausted {
+        let page = fetch_page(
+            &cursor.storage,
+            cursor.account_id,
+            cursor.from,
+            cursor.to,
+            cursor.after,
+        )
+        .await;
+        match page {
+            Ok(page) => {
+                cursor.exhausted = page.len() < PAGE_SIZE;
+                cursor.after = next_after(&page, cursor.after);
+                cursor.page = page.into();
+            }
+            Err(error) => {
+                cursor.finished = true;
+                return Some((Err(error), cursor));
+            }
+        }
+    }
+
+    match cursor.page.pop_front() {
+        Some(transaction) => {
+            let line = cursor.apply(transaction);
+            Some((Ok(StatementEntry::Line(Box::new(line))), cursor))
+        }
+        None => {
+            cursor.finished = true;
+            let summary = cursor.summary();
+            Some((Ok(StatementEntry::Summary(summary)), cursor))
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Storage + 'static> UseCase<StatementRequestDTO, Statement> for GenerateStatementUseCase<S> {
+    #[tracing::instrument(name = "generate_statement", skip_all, fields(account_id = %input.account_id, from = %input.from, to = %input.to))]
+    async fn execute(&self, input: StatementRequestDTO) -> Result<Statement, String> {
+        if input.from >= input.to {
+            return Err("Invalid statement period".to_string());
+        }
+
+        let account = self
+            .storage
+            .get_account(input.account_id)
+            .await?
+            .ok_or("Account not found".to_string())?;
+
+        let opening_balance = opening_balance(&self.storage, &account, input.from).await?;
+
+        let cursor = StatementCursor {
+            storage: Arc::clone(&self.storage),
+            account_id: account.uuid,
+            from: input.from,
+            to: input.to,
+            after: None,
+            balance: opening_balance.clone(),
+            total_in: BigDecimal::zero().with_scale(2),
+            total_out: BigDecimal::zero().with_scale(2),
+            page: VecDeque::new(),
+            exhausted: false,
+            finished: false,
+        };
+
+        Ok(Statement {
+            header: StatementHeader {
+                account_id: account.uuid,
+                currency: account.currency,
+                from: input.from,
+                to: input.to,
+                opening_balance,
+            },
+            entries: stream::unfold(cursor, next_entry).boxed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::FromPrimitive;
+    use chrono::Duration;
+    use futures::TryStreamExt;
+
+    use crate::{model::OperationKind, storage::InMemoryStorage};
+
+    use super::*;
+
+    /// Posts the transactions in order, each starting from the balance the
+    /// previous one left, and stores the account as they leave it.
+    async fn post(
+        storage: &Arc<InMemoryStorage>,
+        account: &Account,
+        mut transactions: Vec<Transaction>,
+    ) -> Account {
+        let mut account = account.clone();
+        for tx in transactions.iter_mut() {
+            tx.balance_after = &account.balance + &tx.amount;
+            account = account.apply(tx).account;
+        }
+        storage
+            .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
+            .await;
+        storage
+            .save_transactions(transactions, vec![])
+            .await
+            .unwrap();
+        account
+    }
+
+    async fn setup(amounts: &[f64]) -> (GenerateStatementUseCase<InMemoryStorage>, Account) {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        let transactions = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, amount)| {
+                let mut tx = Transaction::new(
+                    &account,
+                    &format!("idemp_{}", i),
+                    &BigDecimal::from_f64(*amount).unwrap(),
+                    if *amount < 0.0 {
+                        OperationKind::Withdrawal
+                    } else {
+                        OperationKind::Deposit
+                    },
+                );
+                tx.created_at = account.created_at + Duration::hours(i as i64 + 1);
+                tx
+            })
+            .collect();
+        post(&storage, &account, transactions).await;
+        (GenerateStatementUseCase::new(&storage), account)
+    }
+
+    #[tokio::test]
+    async fn test_execute_successful() {
+        let (use_case, account) = setup(&[100.0, -30.0, 50.0, -20.0]).await;
+
+        let statement = use_case
+            .execute(StatementRequestDTO {
+                account_id: account.uuid,
+                from: account.created_at + Duration::minutes(90),
+                to: account.created_at + Duration::minutes(210),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            statement.header.opening_balance,
+            BigDecimal::from_f64(100.0).unwrap()
+        );
+
+        let entries: Vec<StatementEntry> = statement.entries.try_collect().await.unwrap();
+        assert_eq!(entries.len(), 3);
+        let running_balances: Vec<BigDecimal> = entries
+            .iter()
+            .filter_map(|entry| match entry {
+                StatementEntry::Line(line) => Some(line.running_balance.clone()),
+                StatementEntry::Summary(_) => None,
+            })
+            .collect();
+        assert_eq!(
+            running_balances,
+            vec![
+                BigDecimal::from_f64(70.0).unwrap(),
+                BigDecimal::from_f64(120.0).unwrap()
+            ]
+        );
+        match &entries[2] {
+            StatementEntry::Summary(summary) => {
+                assert_eq!(summary.total_in, BigDecimal::from_f64(50.0).unwrap());
+                assert_eq!(summary.total_out, BigDecimal::from_f64(30.0).unwrap());
+                assert_eq!(
+                    summary.closing_balance,
+                    BigDecimal::from_f64(120.0).unwrap()
+                );
+            }
+            StatementEntry::Line(_) => panic!("statement must end with a summary"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_period() {
+        let (use_case, account) = setup(&[100.0]).await;
+
+        let statement = use_case
+            .execute(StatementRequestDTO {
+                account_id: account.uuid,
+                from: account.created_at + Duration::days(1),
+                to: account.created_at + Duration::days(2),
+            })
+            .await
+            .unwrap();
+        let entries: Vec<StatementEntry> = statement.entries.try_collect().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        match &entries[0] {
+            StatementEntry::Summary(summary) => {
+                assert_eq!(
+                    summary.closing_balance,
+                    BigDecimal::from_f64(100.0).unwrap()
+                );
+            }
+            StatementEntry::Line(_) => panic!("statement must end with a summary"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transactions_sharing_a_timestamp_span_pages() {
+        let (use_case, account) = setup(&[]).await;
+        let created_at = account.created_at + Duration::hours(1);
+        let count = PAGE_SIZE * 2 + 1;
+        let mut transactions: Vec<Transaction> = (0..count)
+            .map(|i| Transaction {
+                created_at,
+                ..Transaction::new(
+                    &account,
+                    &format!("idemp_{}", i),
+                    &BigDecimal::from(1),
+                    OperationKind::Deposit,
+                )
+            })
+            .collect();
+        // Statements list transactions sharing a timestamp by id.
+        transactions.sort_by_key(|tx| tx.id);
+        post(&use_case.storage, &account, transactions).await;
+
+        let statement = use_case
+            .execute(StatementRequestDTO {
+                account_id: account.uuid,
+                from: created_at,
+                to: created_at + Duration::hours(1),
+            })
+            .await
+            .unwrap();
+        let entries: Vec<StatementEntry> = statement.entries.try_collect().await.unwrap();
+        assert_eq!(entries.len(), count + 1);
+        match entries.last().unwrap() {
+            StatementEntry::Summary(summary) => {
+                assert_eq!(summary.closing_balance, BigDecimal::from(count as u64));
+            }
+            StatementEntry::Line(_) => panic!("statement must end with a summary"),
+        }
+
+        let closing = use_case
+            .execute(StatementRequestDTO {
+                account_id: account.uuid,
+                from: created_at + Duration::hours(1),
+                to: created_at + Duration::hours(2),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            closing.header.opening_balance,
+            BigDecimal::from(count as u64)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_account_not_found() {
+        let (use_case, account) = setup(&[]).await;
+
+        let result = use_case
+            .execute(StatementRequestDTO {
+                account_id: Uuid::new_v4(),
+                from: account.created_at,
+                to: account.created_at + Duration::days(1),
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap(), "Account not found");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_period() {
+        let (use_case, account) = setup(&[]).await;
+
+        let result = use_case
+            .execute(StatementRequestDTO {
+                account_id: account.uuid,
+                from: account.created_at + Duration::days(1),
+                to: account.created_at,
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(result.err().unwrap(), "Invalid statement period");
+    }
+}
@@ -0,0 +1,86 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+
+use crate::{storage::Storage, use_case::UseCase};
+
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const HEALTH_CHECK_TIMED_OUT: &str = "Storage health check timed out";
+
+/// Checks whether the service can take traffic. A storage that does not
+/// answer within the timeout counts as unreachable, so probes never hang on
+/// a pool waiting for connections.
+#[derive(Debug, Clone)]
+pub struct HealthCheckUseCase<S: Storage> {
+    storage: Arc<S>,
+    timeout: Duration,
+}
+
+impl<S: Storage> HealthCheckUseCase<S> {
+    pub fn new(storage: &Arc<S>, timeout: Duration) -> Self {
+        HealthCheckUseCase {
+            storage: Arc::clone(storage),
+            timeout,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Storage> UseCase<(), ()> for HealthCheckUseCase<S> {
+    async fn execute(&self, _input: ()) -> Result<(), String> {
+        tokio::time::timeout(self.timeout, self.storage.health_check())
+            .await
+            .map_err(|_| HEALTH_CHECK_TIMED_OUT.to_string())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{Fault, FaultyStorage, InMemoryStorage, StorageOperation, Trigger};
+
+    use super::*;
+
+    fn setup() -> (
+        Arc<FaultyStorage<InMemoryStorage>>,
+        HealthCheckUseCase<FaultyStorage<InMemoryStorage>>,
+    ) {
+        let storage = Arc::new(FaultyStorage::new(&Arc::new(InMemoryStorage::new())));
+        let use_case = HealthCheckUseCase::new(&storage, DEFAULT_HEALTH_CHECK_TIMEOUT);
+        (storage, use_case)
+    }
+
+    #[tokio::test]
+    async fn test_healthy_storage() {
+        let (_, use_case) = setup();
+        assert_eq!(use_case.execute(()).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_storage() {
+        let (storage, use_case) = setup();
+        storage.inject(
+            StorageOperation::HealthCheck,
+            Trigger::OnCall(1),
+            Fault::Fail("Connection refused".to_string()),
+        );
+        assert_eq!(
+            use_case.execute(()).await.unwrap_err(),
+            "Connection refused"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_storage_times_out() {
+        let (storage, use_case) = setup();
+        storage.inject(
+            StorageOperation::HealthCheck,
+            Trigger::OnCall(1),
+            Fault::Delay(DEFAULT_HEALTH_CHECK_TIMEOUT * 2),
+        );
+        assert_eq!(
+            use_case.execute(()).await.unwrap_err(),
+            HEALTH_CHECK_TIMED_OUT
+        );
+    }
+}
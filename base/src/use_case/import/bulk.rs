@@ -0,0 +1,227 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use futures::{
+    future::{self, BoxFuture, Shared},
+    stream, FutureExt, StreamExt,
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::{
+    dto::{
+        account::{AccountCreationDTO, AccountCreationResultDTO},
+        import::{BulkImportDTO, ImportOperation},
+        transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+    },
+    model::{ImportLineResult, ImportReport, Transaction},
+    use_case::UseCase,
+};
+
+pub const DEFAULT_IMPORT_CONCURRENCY: usize = 8;
+
+type Completion = Shared<BoxFuture<'static, ()>>;
+
+#[derive(Clone)]
+pub struct BulkImportUseCase {
+    create_account_uc: Arc<dyn UseCase<AccountCreationDTO, AccountCreationResultDTO>>,
+    deposit_uc: Arc<dyn UseCase<DepositTransactionDTO, Vec<Transaction>>>,
+    withdrawal_uc: Arc<dyn UseCase<WithdrawalTransactionDTO, Vec<Transaction>>>,
+    transfer_uc: Arc<dyn UseCase<TransferTransactionDTO, Vec<Transaction>>>,
+    concurrency: usize,
+}
+
+impl BulkImportUseCase {
+    pub fn new(
+        create_account_uc: Arc<dyn UseCase<AccountCreationDTO, AccountCreationResultDTO>>,
+        deposit_uc: Arc<dyn UseCase<DepositTransactionDTO, Vec<Transaction>>>,
+        withdrawal_uc: Arc<dyn UseCase<WithdrawalTransactionDTO, Vec<Transaction>>>,
+        transfer_uc: Arc<dyn UseCase<TransferTransactionDTO, Vec<Transaction>>>,
+        concurrency: usize,
+    ) -> Self {
+        BulkImportUseCase {
+            create_account_uc,
+            deposit_uc,
+            withdrawal_uc,
+            transfer_uc,
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    async fn apply(&self, line: usize, operation: ImportOperation) -> ImportLineResult {
+        let transaction_ids =
+            |txs: Vec<Transaction>| txs.into_iter().map(|tx| tx.id).collect::<Vec<Uuid>>();
+        let result = match operation {
+            ImportOperation::CreateAccount(input) => self
+                .create_account_uc
+                .execute(input)
+                .await
+                .map(|result| (Some(result.account.uuid), vec![])),
+            ImportOperation::Deposit(input) => {
+                let account_id = input.account_id;
+                self.deposit_uc
+                    .execute(input)
+                    .await
+                    .map(|txs| (Some(account_id), transaction_ids(txs)))
+            }
+            ImportOperation::Withdrawal(input) => {
+                let account_id = input.account_id;
+                self.withdrawal_uc
+                    .execute(input)
+                    .await
+                    .map(|txs| (Some(account_id), transaction_ids(txs)))
+            }
+            ImportOperation::Transfer(input) => self
+                .transfer_uc
+                .execute(input)
+                .await
+                .map(|txs| (None, transaction_ids(txs))),
+        };
+        match result {
+            Ok((account_id, transaction_ids)) => {
+                ImportLineResult::ok(line, account_id, transaction_ids)
+            }
+            Err(error) => ImportLineResult::error(line, error),
+        }
+    }
+}
+
+/// Lines run concurrently, but a line only starts once every earlier line
+/// touching one of its accounts has finished, so an account is always created
+/// before it is funded and balances evolve in file order.
+#[async_trait]
+impl UseCase<BulkImportDTO, ImportReport> for BulkImportUseCase {
+    #[tracing::instrument(name = "bulk_import", skip_all, fields(format = ?input.format, resume_from = input.resume_from))]
+    async fn execute(&self, input: BulkImportDTO) -> Result<ImportReport, String> {
+        let mut pending: HashMap<Uuid, Completion> = HashMap::new();
+        let mut tasks: Vec<BoxFuture<'static, ImportLineResult>> = Vec::new();
+
+        for (line, operation) in input.parse() {
+            let operation = match operation {
+                Ok(operation) => operation,
+                Err(error) => {
+                    tasks.push(future::ready(ImportLineResult::error(line, error)).boxed());
+                    continue;
+                }
+            };
+
+            let (done, completion) = oneshot::channel::<()>();
+            let completion: Completion = completion.map(|_| ()).boxed().shared();
+            let dependencies: Vec<Completion> = operation
+                .account_ids()
+                .into_iter()
+                .filter_map(|account_id| pending.insert(account_id, completion.clone()))
+                .collect();
+
+            let use_case = self.clone();
+            tasks.push(
+                async move {
+                    future::join_all(dependencies).await;
+                    let result = use_case.apply(line, operation).await;
+                    let _ = done.send(());
+                    result
+                }
+                .boxed(),
+            );
+        }
+
+        Ok(stream::iter(tasks).buffered(self.concurrency).boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::{BigDecimal, FromPrimitive};
+
+    use crate::{
+        dto::import::ImportFormat,
+        lock::AccountLocks,
+        model::ImportStatus,
+        storage::{InMemoryStorage, Storage},
+        use_case::{CreateAccountUseCase, DepositUseCase, TransferUseCase, WithdrawalUseCase},
+    };
+
+    use super::*;
+
+    fn setup() -> (Arc<InMemoryStorage>, BulkImportUseCase) {
+        let storage = Arc::new(InMemoryStorage::new());
+        let locks = Arc::new(AccountLocks::default());
+        let use_case = BulkImportUseCase::new(
+            Arc::new(CreateAccountUseCase::new(&storage)),
+            Arc::new(DepositUseCase::new(&storage, &locks)),
+            Arc::new(WithdrawalUseCase::new(&storage, &locks)),
+            Arc::new(TransferUseCase::new(&storage, &locks)),
+            4,
+        );
+        (storage, use_case)
+    }
+
+    fn jsonl(from: Uuid, to: Uuid) -> String {
+        let mut lines = vec![
+            format!(r#"{{"operation":"create_account","uuid":"{from}","currency":"BRL"}}"#),
+            format!(r#"{{"operation":"create_account","uuid":"{to}","currency":"BRL"}}"#),
+        ];
+        for i in 0..10 {
+            lines.push(format!(
+                r#"{{"operation":"deposit","idempotency_key":"d{i}","account_id":"{from}","amount":"10.00"}}"#
+            ));
+        }
+        lines.push(format!(
+            r#"{{"operation":"transfer","idempotency_key":"t1","from_account_id":"{from}","to_account_id":"{to}","amount":"100.00"}}"#
+        ));
+        lines.push(format!(
+            r#"{{"operation":"withdrawal","idempotency_key":"w1","account_id":"{from}","amount":"0.01"}}"#
+        ));
+        lines.join("\n")
+    }
+
+    #[tokio::test]
+    async fn test_execute_successful() {
+        let (storage, use_case) = setup();
+        let (from, to) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let report = use_case
+            .execute(BulkImportDTO {
+                content: jsonl(from, to),
+                format: ImportFormat::Jsonl,
+                resume_from: 0,
+            })
+            .await
+            .unwrap();
+        let results: Vec<ImportLineResult> = report.collect().await;
+
+        assert_eq!(results.len(), 14);
+        let lines: Vec<usize> = results.iter().map(|r| r.line).collect();
+        assert_eq!(lines, (1..=14).collect::<Vec<usize>>());
+        assert!(results[..13].iter().all(|r| r.status == ImportStatus::Ok));
+        assert_eq!(results[12].transaction_ids.len(), 2);
+        assert_eq!(results[13].status, ImportStatus::Error);
+        assert_eq!(results[13].error.as_deref(), Some("Insufficient balance"));
+
+        let from = storage.get_account(from).await.unwrap().unwrap();
+        let to = storage.get_account(to).await.unwrap().unwrap();
+        assert_eq!(from.balance, BigDecimal::from_f64(0.0).unwrap());
+        assert_eq!(to.balance, BigDecimal::from_f64(100.0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_line() {
+        let (storage, use_case) = setup();
+        let (from, to) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let report = use_case
+            .execute(BulkImportDTO {
+                content: jsonl(from, to),
+                format: ImportFormat::Jsonl,
+                resume_from: 3,
+            })
+            .await
+            .unwrap();
+        let results: Vec<ImportLineResult> = report.collect().await;
+
+        assert_eq!(results.len(), 12);
+        assert_eq!(results[0].line, 3);
+        assert_eq!(results[0].error.as_deref(), Some("Account not found"));
+        assert!(storage.get_account(from).await.unwrap().is_none());
+    }
+}
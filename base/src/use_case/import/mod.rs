@@ -0,0 +1,3 @@
+pub mod bulk;
+
+pub use bulk::{BulkImportUseCase, DEFAULT_IMPORT_CONCURRENCY};
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::metrics::{error_kind, USE_CASE_EXECUTIONS};
+
+use super::UseCase;
+
+/// Use case decorator that counts executions of `inner` under `name`, by
+/// outcome.
+pub struct MeteredUseCase<I, O> {
+    name: &'static str,
+    inner: Arc<dyn UseCase<I, O>>,
+}
+
+impl<I, O> MeteredUseCase<I, O> {
+    pub fn new(name: &'static str, inner: Arc<dyn UseCase<I, O>>) -> Self {
+        MeteredUseCase { name, inner }
+    }
+}
+
+#[async_trait]
+impl<I: Send + 'static, O: Send + 'static> UseCase<I, O> for MeteredUseCase<I, O> {
+    async fn execute(&self, input: I) -> Result<O, String> {
+        let result = self.inner.execute(input).await;
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(error) => error_kind(error),
+        };
+        USE_CASE_EXECUTIONS
+            .with_label_values(&[self.name, outcome])
+            .inc();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    #[async_trait]
+    impl UseCase<Result<u32, String>, u32> for Echo {
+        async fn execute(&self, input: Result<u32, String>) -> Result<u32, String> {
+            input
+        }
+    }
+
+    #[tokio::test]
+    async fn test_counts_outcomes() {
+        let use_case = MeteredUseCase::new("metered_test", Arc::new(Echo));
+        let count = |outcome| {
+            USE_CASE_EXECUTIONS
+                .with_label_values(&["metered_test", outcome])
+                .get()
+        };
+
+        assert_eq!(use_case.execute(Ok(1)).await, Ok(1));
+        assert!(use_case.execute(Ok(2)).await.is_ok());
+        let error = "Account not found".to_string();
+        assert!(use_case.execute(Err(error)).await.is_err());
+        assert_eq!(count("ok"), 2);
+        assert_eq!(count("not_found"), 1);
+    }
+}
@@ -6,6 +6,15 @@ pub trait UseCase<I, O>: Send + Sync {
 }
 
 mod account;
+mod health;
+mod import;
+mod metered;
 mod transaction;
-pub use account::{CreateAccountUseCase, GetAccountByUuidUseCase};
-pub use transaction::{DepositUseCase, TransferUseCase, WithdrawalUseCase};
+pub use account::{CreateAccountUseCase, GenerateStatementUseCase, GetAccountByUuidUseCase};
+pub use health::{HealthCheckUseCase, DEFAULT_HEALTH_CHECK_TIMEOUT, HEALTH_CHECK_TIMED_OUT};
+pub use import::{BulkImportUseCase, DEFAULT_IMPORT_CONCURRENCY};
+pub use metered::MeteredUseCase;
+pub use transaction::{
+    DepositUseCase, GetTransactionByIdUseCase, GetTransferUseCase, SearchTransactionsUseCase,
+    TransferUseCase, WithdrawalUseCase,
+};
@@ -1,74 +1,92 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
 
 use crate::{
-    dto::transaction::DepositTransactionDTO,
-    model::{Account, Transaction},
-    storage::Storage,
-    use_case::UseCase,
+    dto::transaction::DepositTransactionDTO, lock::AccountLocks, metrics::record_volume,
+    model::Transaction, storage::Storage, use_case::UseCase,
 };
 
+use super::{replay::replayed_operation, retry::retry_on_conflict};
+
 #[derive(Debug, Clone)]
 pub struct DepositUseCase<S: Storage> {
-    storage: Arc<Mutex<S>>,
+    storage: Arc<S>,
+    locks: Arc<AccountLocks>,
 }
 
 impl<S: Storage> DepositUseCase<S> {
-    pub fn new(storage: &Arc<Mutex<S>>) -> Self {
+    pub fn new(storage: &Arc<S>, locks: &Arc<AccountLocks>) -> Self {
         DepositUseCase {
             storage: Arc::clone(storage),
+            locks: Arc::clone(locks),
         }
     }
-}
 
-#[async_trait]
-impl<S: Storage> UseCase<DepositTransactionDTO, Vec<Transaction>> for DepositUseCase<S> {
-    async fn execute(&self, input: DepositTransactionDTO) -> Result<Vec<Transaction>, String> {
-        let storage = self.storage.lock().await;
-        let to = storage
+    /// Reads the accounts and writes the operation once, under their locks,
+    /// unless its idempotency key was used already.
+    async fn attempt(&self, input: &DepositTransactionDTO) -> Result<Vec<Transaction>, String> {
+        let _guard = self.locks.lock(&[input.account_id]).await;
+        let replayed =
+            replayed_operation(&*self.storage, input.account_id, &input.idempotency_key, 1).await?;
+        if let Some(operation) = replayed {
+            return Ok(operation);
+        }
+        let to = self
+            .storage
             .get_account(input.account_id.clone())
             .await?
             .ok_or("Account not found".to_string())?;
+        to.check_version(input.expected_version)?;
         let tx = input.to_transaction(&to);
-        let updated_account = Account {
-            balance: to.balance + tx.amount.clone(),
-            ..to
-        };
+        let update = to.apply(&tx);
 
-        let result = storage
-            .save_transactions(vec![tx], vec![updated_account])
+        let result = self
+            .storage
+            .save_transactions(vec![tx.clone()], vec![update])
             .await?;
+        record_volume("deposit", &tx, &result);
         Ok(result)
     }
 }
 
+#[async_trait]
+impl<S: Storage> UseCase<DepositTransactionDTO, Vec<Transaction>> for DepositUseCase<S> {
+    #[tracing::instrument(name = "deposit", skip_all, fields(account_id = %input.account_id, amount = %input.amount, idempotency_key = %input.idempotency_key))]
+    async fn execute(&self, input: DepositTransactionDTO) -> Result<Vec<Transaction>, String> {
+        input.details.validate()?;
+        retry_on_conflict(|| self.attempt(&input)).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use bigdecimal::{BigDecimal, FromPrimitive};
     use uuid::Uuid;
 
-    use crate::storage::InMemoryStorage;
+    use crate::{
+        model::{Account, Details, OperationKind, TransactionStatus, ACCOUNT_VERSION_MISMATCH},
+        storage::InMemoryStorage,
+    };
 
     use super::*;
 
     #[tokio::test]
     async fn test_execute_successful() {
         let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let storage = Arc::new(InMemoryStorage::new());
         storage
-            .lock()
-            .await
             .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
             .await;
-        let use_case = DepositUseCase::new(&storage);
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
 
         let result = use_case
             .execute(DepositTransactionDTO {
                 idempotency_key: String::from("idemp_1"),
                 account_id: account.uuid,
                 amount: BigDecimal::from_f64(99.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_ok());
@@ -78,27 +96,30 @@ mod tests {
         assert_eq!(tx.amount, BigDecimal::from_f64(99.99).unwrap());
         assert_eq!(tx.account_id, account.uuid);
         assert_eq!(tx.idempotency_key, String::from("idemp_1"));
-        let storage = storage.lock().await;
-        let account = storage.get_account(account.uuid).await.unwrap().unwrap();
-        assert_eq!(account.balance, BigDecimal::from_f64(99.99).unwrap());
+        assert_eq!(tx.kind, OperationKind::Deposit);
+        assert_eq!(tx.status, TransactionStatus::Posted);
+        assert_eq!(tx.balance_after, BigDecimal::from_f64(99.99).unwrap());
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from_f64(99.99).unwrap());
+        assert_ne!(stored.version, account.version);
+        assert_eq!(stored.version, tx.account_version);
     }
 
     #[tokio::test]
     async fn test_concurrent_successful() {
         let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let storage = Arc::new(InMemoryStorage::new());
         storage
-            .lock()
-            .await
             .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
             .await;
 
         let deposit_amount = BigDecimal::from_f64(10.0).unwrap();
+        let locks = Arc::new(AccountLocks::default());
 
         // Spawn 10 concurrent deposit tasks
         let mut handles = vec![];
         for i in 0..10 {
-            let use_case: DepositUseCase<InMemoryStorage> = DepositUseCase::new(&storage);
+            let use_case: DepositUseCase<InMemoryStorage> = DepositUseCase::new(&storage, &locks);
             let account_id = account.uuid;
             let amount = deposit_amount.clone();
             let handle = tokio::spawn(async move {
@@ -107,6 +128,8 @@ mod tests {
                         idempotency_key: format!("idemp_{}", i),
                         account_id,
                         amount,
+                        details: Details::default(),
+                        expected_version: None,
                     })
                     .await
             });
@@ -125,26 +148,194 @@ mod tests {
         assert_eq!(successful_deposits, 10);
 
         // Verify the final account balance is correct (10 * 10.0 = 100.0)
-        let storage = storage.lock().await;
         let updated_account = storage.get_account(account.uuid).await.unwrap().unwrap();
         let expected_balance = BigDecimal::from_f64(100.0).unwrap();
         assert_eq!(updated_account.balance, expected_balance);
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_instances_retry_conflicts() {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
+            .await;
+
+        // Separate locks stand in for separate server instances, so only the
+        // storage's version check keeps the deposits from overwriting each other.
+        let mut handles = vec![];
+        for i in 0..10 {
+            let use_case = DepositUseCase::new(&storage, &Arc::default());
+            let account_id = account.uuid;
+            handles.push(tokio::spawn(async move {
+                use_case
+                    .execute(DepositTransactionDTO {
+                        idempotency_key: format!("idemp_{}", i),
+                        account_id,
+                        amount: BigDecimal::from_f64(10.0).unwrap(),
+                        details: Details::default(),
+                        expected_version: None,
+                    })
+                    .await
+            }));
+        }
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+
+        let updated_account = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(
+            updated_account.balance,
+            BigDecimal::from_f64(100.0).unwrap()
+        );
+    }
+
     #[tokio::test]
     async fn test_account_not_found() {
         let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
-        let use_case = DepositUseCase::new(&storage);
+        let storage = Arc::new(InMemoryStorage::new());
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
 
         let result = use_case
             .execute(DepositTransactionDTO {
                 idempotency_key: String::from("idemp_1"),
                 account_id: account.uuid,
                 amount: BigDecimal::from_f64(99.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Account not found");
     }
+
+    #[tokio::test]
+    async fn test_details_are_recorded() {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
+            .await;
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
+        let details = Details {
+            description: Some("Order payment".to_string()),
+            external_reference: Some("order-42".to_string()),
+            metadata: vec![("channel".to_string(), "pix".to_string())]
+                .into_iter()
+                .collect(),
+        };
+
+        let result = use_case
+            .execute(DepositTransactionDTO {
+                idempotency_key: String::from("idemp_1"),
+                account_id: account.uuid,
+                amount: BigDecimal::from_f64(10.0).unwrap(),
+                details: details.clone(),
+                expected_version: None,
+            })
+            .await;
+        assert_eq!(result.unwrap()[0].details, details);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_details() {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
+            .await;
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
+
+        let result = use_case
+            .execute(DepositTransactionDTO {
+                idempotency_key: String::from("idemp_1"),
+                account_id: account.uuid,
+                amount: BigDecimal::from_f64(10.0).unwrap(),
+                details: Details {
+                    metadata: vec![("".to_string(), "value".to_string())]
+                        .into_iter()
+                        .collect(),
+                    ..Details::default()
+                },
+                expected_version: None,
+            })
+            .await;
+        assert_eq!(result.unwrap_err(), "metadata keys must not be blank");
+        let account = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(account.balance, BigDecimal::from_f64(0.0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expected_version() {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .set_accounts(vec![(account.uuid, account.clone())].into_iter().collect())
+            .await;
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
+        let deposit = |key: &str, expected_version| DepositTransactionDTO {
+            idempotency_key: key.to_string(),
+            account_id: account.uuid,
+            amount: BigDecimal::from_f64(10.0).unwrap(),
+            details: Details::default(),
+            expected_version,
+        };
+
+        let first = use_case
+            .execute(deposit("idemp_1", Some(account.version)))
+            .await
+            .unwrap();
+        let result = use_case
+            .execute(deposit("idemp_2", Some(account.version)))
+            .await;
+        assert_eq!(result.unwrap_err(), ACCOUNT_VERSION_MISMATCH);
+        let result = use_case
+            .execute(deposit("idemp_3", Some(first[0].account_version)))
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replay_is_not_counted_in_volume() {
+        // A currency of its own keeps other tests out of the counter.
+        let currency = format!("T{}", Uuid::new_v4().simple());
+        let account = Account::new(Uuid::new_v4(), &currency);
+        let storage = Arc::new(InMemoryStorage::new());
+        storage.save_account(account.clone()).await.unwrap();
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
+        let deposit = || DepositTransactionDTO {
+            idempotency_key: String::from("idemp_1"),
+            account_id: account.uuid,
+            amount: BigDecimal::from_f64(10.0).unwrap(),
+            details: Details::default(),
+            expected_version: None,
+        };
+
+        use_case.execute(deposit()).await.unwrap();
+        use_case.execute(deposit()).await.unwrap();
+        let volume = crate::metrics::VOLUME.with_label_values(&["deposit", currency.as_str()]);
+        assert_eq!(volume.get(), 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_replay_skips_version_check() {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        storage.save_account(account.clone()).await.unwrap();
+        let use_case = DepositUseCase::new(&storage, &Arc::default());
+        let deposit = |key: &str| DepositTransactionDTO {
+            idempotency_key: key.to_string(),
+            account_id: account.uuid,
+            amount: BigDecimal::from_f64(10.0).unwrap(),
+            details: Details::default(),
+            expected_version: Some(account.version),
+        };
+
+        let first = use_case.execute(deposit("idemp_1")).await.unwrap();
+        let replayed = use_case.execute(deposit("idemp_1")).await.unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].id, first[0].id);
+        let result = use_case.execute(deposit("idemp_2")).await;
+        assert_eq!(result.unwrap_err(), ACCOUNT_VERSION_MISMATCH);
+    }
 }
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{model::Transaction, storage::Storage, use_case::UseCase};
+
+#[derive(Debug, Clone)]
+pub struct GetTransactionByIdUseCase<S: Storage> {
+    storage: Arc<S>,
+}
+
+impl<S: Storage> GetTransactionByIdUseCase<S> {
+    pub fn new(storage: &Arc<S>) -> Self {
+        GetTransactionByIdUseCase {
+            storage: Arc::clone(storage),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Storage> UseCase<Uuid, Option<Transaction>> for GetTransactionByIdUseCase<S> {
+    #[tracing::instrument(name = "get_transaction", skip_all, fields(transaction_id = %input))]
+    async fn execute(&self, input: Uuid) -> Result<Option<Transaction>, String> {
+        self.storage.get_transaction(input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::{BigDecimal, FromPrimitive};
+
+    use crate::{
+        model::{Account, OperationKind},
+        storage::InMemoryStorage,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute_successful() {
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let tx = Transaction::new(
+            &account,
+            "idemp_1",
+            &BigDecimal::from_f64(10.0).unwrap(),
+            OperationKind::Deposit,
+        );
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .save_transactions(vec![tx.clone()], vec![])
+            .await
+            .unwrap();
+        let use_case = GetTransactionByIdUseCase::new(&storage);
+
+        let found = use_case.execute(tx.id).await.unwrap().unwrap();
+        assert_eq!(found.id, tx.id);
+        assert_eq!(found.operation_id, tx.operation_id);
+        assert!(use_case.execute(Uuid::new_v4()).await.unwrap().is_none());
+    }
+}
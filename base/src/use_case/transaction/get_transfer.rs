@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{model::Transfer, storage::Storage, use_case::UseCase};
+
+#[derive(Debug, Clone)]
+pub struct GetTransferUseCase<S: Storage> {
+    storage: Arc<S>,
+}
+
+impl<S: Storage> GetTransferUseCase<S> {
+    pub fn new(storage: &Arc<S>) -> Self {
+        GetTransferUseCase {
+            storage: Arc::clone(storage),
+        }
+    }
+}
+
+/// Operations that are not transfers, such as deposits, are reported as not
+/// found.
+#[async_trait]
+impl<S: Storage> UseCase<Uuid, Option<Transfer>> for GetTransferUseCase<S> {
+    #[tracing::instrument(name = "get_transfer", skip_all, fields(operation_id = %input))]
+    async fn execute(&self, input: Uuid) -> Result<Option<Transfer>, String> {
+        let legs = self.storage.get_operation_transactions(input).await?;
+        Ok(Transfer::from_legs(legs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::{BigDecimal, FromPrimitive};
+
+    use crate::{
+        dto::transaction::{DepositTransactionDTO, TransferTransactionDTO},
+        model::{Account, Details},
+        storage::InMemoryStorage,
+        use_case::{DepositUseCase, TransferUseCase},
+    };
+
+    use super::*;
+
+    async fn setup() -> (Arc<InMemoryStorage>, Account, Account) {
+        let mut from = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        from.balance = BigDecimal::from_f64(100.0).unwrap();
+        let to = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .set_accounts(
+                vec![(from.uuid, from.clone()), (to.uuid, to.clone())]
+                    .into_iter()
+                    .collect(),
+            )
+            .await;
+        (storage, from, to)
+    }
+
+    #[tokio::test]
+    async fn test_execute_successful() {
+        let (storage, from, to) = setup().await;
+        let legs = TransferUseCase::new(&storage, &Arc::default())
+            .execute(TransferTransactionDTO {
+                idempotency_key: "idemp_1".to_string(),
+                from_account_id: from.uuid,
+                to_account_id: to.uuid,
+                amount: BigDecimal::from_f64(40.0).unwrap(),
+                details: Details::default(),
+                expected_version: None,
+            })
+            .await
+            .unwrap();
+        let use_case = GetTransferUseCase::new(&storage);
+
+        let transfer = use_case
+            .execute(legs[0].operation_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transfer.from_account_id, from.uuid);
+        assert_eq!(transfer.to_account_id, to.uuid);
+        assert_eq!(transfer.amount, BigDecimal::from_f64(40.0).unwrap());
+        assert_eq!(transfer.debit.counterparty_account_id, Some(to.uuid));
+        assert_eq!(transfer.credit.counterparty_account_id, Some(from.uuid));
+    }
+
+    #[tokio::test]
+    async fn test_deposit_is_not_a_transfer() {
+        let (storage, from, _) = setup().await;
+        let txs = DepositUseCase::new(&storage, &Arc::default())
+            .execute(DepositTransactionDTO {
+                idempotency_key: "idemp_1".to_string(),
+                account_id: from.uuid,
+                amount: BigDecimal::from_f64(10.0).unwrap(),
+                details: Details::default(),
+                expected_version: None,
+            })
+            .await
+            .unwrap();
+        let use_case = GetTransferUseCase::new(&storage);
+
+        assert!(use_case
+            .execute(txs[0].operation_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(use_case.execute(Uuid::new_v4()).await.unwrap().is_none());
+    }
+}
@@ -1,7 +1,15 @@
 mod deposit;
+mod get_by_id;
+mod get_transfer;
+mod replay;
+mod retry;
+mod search;
 mod transfer;
 mod withdraw;
 
 pub use deposit::DepositUseCase;
+pub use get_by_id::GetTransactionByIdUseCase;
+pub use get_transfer::GetTransferUseCase;
+pub use search::SearchTransactionsUseCase;
 pub use transfer::TransferUseCase;
 pub use withdraw::WithdrawalUseCase;
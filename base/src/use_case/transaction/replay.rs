@@ -0,0 +1,32 @@
+use uuid::Uuid;
+
+use crate::{
+    model::Transaction,
+    storage::{Storage, IDEMPOTENCY_KEY_REUSED},
+};
+
+/// The operation that already used `idempotency_key` on the account, looked
+/// up before the account is read so that replaying it returns the original
+/// even once the balance or version it was checked against has moved on.
+/// An operation with a different number of transactions used the key for
+/// something else. A key whose operation has no transactions yet is still
+/// being written, and is left for `save_transactions` to settle.
+pub(crate) async fn replayed_operation<S: Storage>(
+    storage: &S,
+    account_id: Uuid,
+    idempotency_key: &str,
+    legs: usize,
+) -> Result<Option<Vec<Transaction>>, String> {
+    let Some(operation_id) = storage
+        .get_idempotency_key(account_id, idempotency_key)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let operation = storage.get_operation_transactions(operation_id).await?;
+    match operation.len() {
+        0 => Ok(None),
+        len if len == legs => Ok(Some(operation)),
+        _ => Err(IDEMPOTENCY_KEY_REUSED.to_string()),
+    }
+}
@@ -0,0 +1,86 @@
+use std::{future::Future, time::Duration};
+
+use rand::Rng;
+
+use crate::{metrics::VERSION_CONFLICT_RETRIES, storage::VERSION_CONFLICT};
+
+pub const MAX_ATTEMPTS: u32 = 5;
+const BASE_DELAY: Duration = Duration::from_millis(10);
+
+/// Full jitter: a random delay up to an exponentially growing cap, so
+/// writers that collided once are unlikely to collide again.
+fn backoff(attempt: u32) -> Duration {
+    let cap = BASE_DELAY * 2u32.pow(attempt - 1);
+    rand::thread_rng().gen_range(Duration::ZERO..=cap)
+}
+
+/// Runs `operation` again whenever the storage rejects its write because an
+/// account changed since it was read, e.g. by another server instance. Each
+/// attempt must re-read the accounts it updates. Gives up with the conflict
+/// after `MAX_ATTEMPTS`.
+pub(crate) async fn retry_on_conflict<T, F, Fut>(mut operation: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(error) if error == VERSION_CONFLICT && attempt < MAX_ATTEMPTS => {
+                VERSION_CONFLICT_RETRIES.inc();
+                tokio::time::sleep(backoff(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    async fn run(conflicts: u32, error: &str) -> (Result<u32, String>, u32) {
+        let calls = AtomicU32::new(0);
+        let result = retry_on_conflict(|| async {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if call <= conflicts {
+                Err(error.to_string())
+            } else {
+                Ok(call)
+            }
+        })
+        .await;
+        (result, calls.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let (result, calls) = run(2, VERSION_CONFLICT).await;
+        assert_eq!(result, Ok(3));
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (result, calls) = run(u32::MAX, VERSION_CONFLICT).await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+        assert_eq!(calls, MAX_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        let (result, calls) = run(u32::MAX, "Insufficient balance").await;
+        assert_eq!(result.unwrap_err(), "Insufficient balance");
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_backoff_is_bounded() {
+        for attempt in 1..MAX_ATTEMPTS {
+            assert!(backoff(attempt) <= BASE_DELAY * 2u32.pow(attempt - 1));
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::TransactionPage,
+    storage::Storage,
+    use_case::UseCase,
+};
+
+#[derive(Debug, Clone)]
+pub struct SearchTransactionsUseCase<S: Storage> {
+    storage: Arc<S>,
+}
+
+impl<S: Storage> SearchTransactionsUseCase<S> {
+    pub fn new(storage: &Arc<S>) -> Self {
+        SearchTransactionsUseCase {
+            storage: Arc::clone(storage),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Storage> UseCase<TransactionSearchDTO, TransactionPage> for SearchTransactionsUseCase<S> {
+    #[tracing::instrument(name = "search_transactions", skip_all, fields(account_id = ?input.account_id))]
+    async fn execute(&self, input: TransactionSearchDTO) -> Result<TransactionPage, String> {
+        input.validate()?;
+        let limit = input.limit();
+        // One extra item tells whether another page follows.
+        let mut transactions = self
+            .storage
+            .search_transactions(&input, input.cursor()?, limit + 1)
+            .await?;
+        let next_cursor = if transactions.len() > limit {
+            transactions.truncate(limit);
+            transactions
+                .last()
+                .map(|tx| SearchCursor::of(tx).to_string())
+        } else {
+            None
+        };
+        Ok(TransactionPage {
+            transactions,
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::{BigDecimal, FromPrimitive};
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    use crate::{
+        dto::search::{TransactionDirection, SEARCH_PARTITION_REQUIRED},
+        model::{Account, Details, OperationKind, Transaction, TransactionStatus},
+        storage::InMemoryStorage,
+    };
+
+    use super::*;
+
+    async fn setup() -> (SearchTransactionsUseCase<InMemoryStorage>, Account, Account) {
+        let brl = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let usd = Account::new(Uuid::new_v4(), &"USD".to_string());
+        let entries: [(&Account, f64, &str); 5] = [
+            (&brl, 100.0, "order-1"),
+            (&brl, -30.0, "order-2"),
+            (&usd, 50.0, "order-2"),
+            (&usd, -500.0, "order-3"),
+            (&brl, 20.0, "order-4"),
+        ];
+        let transactions = entries
+            .iter()
+            .enumerate()
+            .map(|(i, (account, amount, reference))| {
+                let mut tx = Transaction::new(
+                    account,
+                    &format!("idemp_{}", i),
+                    &BigDecimal::from_f64(*amount).unwrap(),
+                    if *amount < 0.0 {
+                        OperationKind::Withdrawal
+                    } else {
+                        OperationKind::Deposit
+                    },
+                );
+                tx.created_at = brl.created_at + Duration::hours(i as i64 + 1);
+                tx.details = Details {
+                    external_reference: Some(reference.to_string()),
+                    metadata: vec![("channel".to_string(), format!("channel_{}", i % 2))]
+                        .into_iter()
+                        .collect(),
+                    ..Details::default()
+                };
+                tx
+            })
+            .collect();
+        let storage = Arc::new(InMemoryStorage::new());
+        storage
+            .save_transactions(transactions, vec![])
+            .await
+            .unwrap();
+        (SearchTransactionsUseCase::new(&storage), brl, usd)
+    }
+
+    fn amounts(page: &TransactionPage) -> Vec<BigDecimal> {
+        page.transactions
+            .iter()
+            .map(|tx| tx.amount.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_across_accounts() {
+        let (use_case, brl, usd) = setup().await;
+
+        let page = use_case
+            .execute(TransactionSearchDTO {
+                external_reference: Some("order-2".to_string()),
+                ..TransactionSearchDTO::default()
+            })
+            .await
+            .unwrap();
+        let accounts: Vec<Uuid> = page.transactions.iter().map(|tx| tx.account_id).collect();
+        assert_eq!(accounts, vec![brl.uuid, usd.uuid]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_combined_filters() {
+        let (use_case, brl, _) = setup().await;
+
+        let page = use_case
+            .execute(TransactionSearchDTO {
+                metadata_key: Some("channel".to_string()),
+                metadata_value: Some("channel_0".to_string()),
+                min_amount: Some(BigDecimal::from_f64(20.0).unwrap()),
+                max_amount: Some(BigDecimal::from_f64(100.0).unwrap()),
+                currency: Some("BRL".to_string()),
+                direction: Some(TransactionDirection::Credit),
+                from: Some(brl.created_at + Duration::minutes(90)),
+                ..TransactionSearchDTO::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(amounts(&page), vec![BigDecimal::from_f64(20.0).unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_kind_and_status_filters() {
+        let (use_case, _, usd) = setup().await;
+
+        let page = use_case
+            .execute(TransactionSearchDTO {
+                external_reference: Some("order-3".to_string()),
+                kind: Some(OperationKind::Withdrawal),
+                status: Some(TransactionStatus::Posted),
+                currency: Some("USD".to_string()),
+                ..TransactionSearchDTO::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.transactions.len(), 1);
+        assert_eq!(page.transactions[0].account_id, usd.uuid);
+
+        let page = use_case
+            .execute(TransactionSearchDTO {
+                account_id: Some(usd.uuid),
+                status: Some(TransactionStatus::Pending),
+                ..TransactionSearchDTO::default()
+            })
+            .await
+            .unwrap();
+        assert!(page.transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pagination() {
+        let (use_case, brl, _) = setup().await;
+        let mut query = TransactionSearchDTO {
+            account_id: Some(brl.uuid),
+            limit: Some(2),
+            ..TransactionSearchDTO::default()
+        };
+
+        let mut seen = Vec::new();
+        loop {
+            let page = use_case.execute(query.clone()).await.unwrap();
+            assert!(page.transactions.len() <= 2);
+            seen.extend(amounts(&page));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        let expected: Vec<BigDecimal> = [100.0, -30.0, 20.0]
+            .iter()
+            .map(|amount| BigDecimal::from_f64(*amount).unwrap())
+            .collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_query() {
+        let (use_case, brl, _) = setup().await;
+
+        let result = use_case
+            .execute(TransactionSearchDTO {
+                metadata_key: Some("channel".to_string()),
+                ..TransactionSearchDTO::default()
+            })
+            .await;
+        assert_eq!(
+            result.unwrap_err(),
+            "metadata_key and metadata_value must be given together"
+        );
+
+        let result = use_case
+            .execute(TransactionSearchDTO {
+                currency: Some("BRL".to_string()),
+                ..TransactionSearchDTO::default()
+            })
+            .await;
+        assert_eq!(result.unwrap_err(), SEARCH_PARTITION_REQUIRED);
+
+        let result = use_case
+            .execute(TransactionSearchDTO {
+                account_id: Some(brl.uuid),
+                cursor: Some("not-a-cursor".to_string()),
+                ..TransactionSearchDTO::default()
+            })
+            .await;
+        assert_eq!(result.unwrap_err(), "Invalid cursor");
+    }
+}
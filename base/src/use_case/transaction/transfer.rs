@@ -1,77 +1,97 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
 
 use crate::{
-    dto::transaction::TransferTransactionDTO,
-    model::{Account, Transaction},
-    storage::Storage,
-    use_case::UseCase,
+    dto::transaction::TransferTransactionDTO, lock::AccountLocks, metrics::record_volume,
+    model::Transaction, storage::Storage, use_case::UseCase,
 };
 
+use super::{replay::replayed_operation, retry::retry_on_conflict};
+
 #[derive(Debug, Clone)]
 pub struct TransferUseCase<S: Storage> {
-    storage: Arc<Mutex<S>>,
+    storage: Arc<S>,
+    locks: Arc<AccountLocks>,
 }
 
 impl<S: Storage> TransferUseCase<S> {
-    pub fn new(storage: &Arc<Mutex<S>>) -> Self {
+    pub fn new(storage: &Arc<S>, locks: &Arc<AccountLocks>) -> Self {
         TransferUseCase {
             storage: Arc::clone(storage),
+            locks: Arc::clone(locks),
         }
     }
-}
 
-#[async_trait]
-impl<S: Storage> UseCase<TransferTransactionDTO, Vec<Transaction>> for TransferUseCase<S> {
-    async fn execute(&self, input: TransferTransactionDTO) -> Result<Vec<Transaction>, String> {
-        let storage: tokio::sync::MutexGuard<'_, S> = self.storage.lock().await;
-        let from = storage
+    /// Reads the accounts and writes the operation once, under their locks,
+    /// unless its idempotency key was used already.
+    async fn attempt(&self, input: &TransferTransactionDTO) -> Result<Vec<Transaction>, String> {
+        let _guard = self
+            .locks
+            .lock(&[input.from_account_id, input.to_account_id])
+            .await;
+        let replayed = replayed_operation(
+            &*self.storage,
+            input.from_account_id,
+            &input.idempotency_key,
+            2,
+        )
+        .await?;
+        if let Some(operation) = replayed {
+            return Ok(operation);
+        }
+        let from = self
+            .storage
             .get_account(input.from_account_id.clone())
             .await?
             .ok_or("Source account not found".to_string())?;
+        from.check_version(input.expected_version)?;
         if from.balance < input.amount {
             return Err("Insufficient balance".to_string());
         }
 
-        let to = storage
+        let to = self
+            .storage
             .get_account(input.to_account_id.clone())
             .await?
             .ok_or("Destination account not found".to_string())?;
 
         let (from_tx, to_tx) = input.to_transactions(&from, &to);
-        let updated_from = Account {
-            balance: from.balance + from_tx.amount.clone(),
-            ..from
-        };
-        let updated_to = Account {
-            balance: to.balance + to_tx.amount.clone(),
-            ..to
-        };
+        let updates = vec![from.apply(&from_tx), to.apply(&to_tx)];
 
-        let transactions = storage
-            .save_transactions(
-                vec![from_tx.clone(), to_tx.clone()],
-                vec![updated_from, updated_to],
-            )
+        let transactions = self
+            .storage
+            .save_transactions(vec![from_tx.clone(), to_tx], updates)
             .await?;
+        record_volume("transfer", &from_tx, &transactions);
         Ok(transactions)
     }
 }
 
+#[async_trait]
+impl<S: Storage> UseCase<TransferTransactionDTO, Vec<Transaction>> for TransferUseCase<S> {
+    #[tracing::instrument(name = "transfer", skip_all, fields(from_account_id = %input.from_account_id, to_account_id = %input.to_account_id, amount = %input.amount, idempotency_key = %input.idempotency_key))]
+    async fn execute(&self, input: TransferTransactionDTO) -> Result<Vec<Transaction>, String> {
+        input.details.validate()?;
+        retry_on_conflict(|| self.attempt(&input)).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use bigdecimal::{BigDecimal, FromPrimitive};
     use uuid::Uuid;
 
-    use crate::storage::InMemoryStorage;
+    use crate::{
+        model::{Account, Details, OperationKind},
+        storage::InMemoryStorage,
+    };
 
     use super::*;
 
     async fn setup() -> (
-        Arc<Mutex<InMemoryStorage>>,
+        Arc<InMemoryStorage>,
         TransferUseCase<InMemoryStorage>,
         Account,
         Account,
@@ -79,25 +99,27 @@ mod tests {
         let mut from = Account::new(Uuid::new_v4(), &"BRL".to_string());
         from.balance = BigDecimal::from_f64(100.0).unwrap();
         let to = Account::new(Uuid::new_v4(), &"BRL".to_string());
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
+        let storage = Arc::new(InMemoryStorage::new());
         storage
-            .lock()
-            .await
             .set_accounts(
                 vec![(from.uuid, from.clone()), (to.uuid, to.clone())]
                     .into_iter()
                     .collect(),
             )
             .await;
-        (storage.clone(), TransferUseCase::new(&storage), from, to)
+        (
+            storage.clone(),
+            TransferUseCase::new(&storage, &Arc::default()),
+            from,
+            to,
+        )
     }
 
     async fn assert_balances(
-        storage: Arc<Mutex<InMemoryStorage>>,
+        storage: Arc<InMemoryStorage>,
         (from_uuid, to_uuid): (Uuid, Uuid),
         (from_balance, to_balance): (f64, f64),
     ) {
-        let storage = storage.lock().await;
         let final_from = storage.get_account(from_uuid).await.unwrap().unwrap();
         assert_eq!(
             final_from.balance,
@@ -117,6 +139,8 @@ mod tests {
                 from_account_id: from.uuid,
                 to_account_id: to.uuid,
                 amount: BigDecimal::from_f64(39.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_ok());
@@ -128,6 +152,11 @@ mod tests {
         let tx_to = &expected_txs[1];
         assert_eq!(tx_to.amount, BigDecimal::from_f64(39.99).unwrap());
         assert_eq!(tx_to.account_id, to.uuid);
+        assert_eq!(tx_from.kind, OperationKind::TransferOut);
+        assert_eq!(tx_to.kind, OperationKind::TransferIn);
+        assert_eq!(tx_from.operation_id, tx_to.operation_id);
+        assert_eq!(tx_from.balance_after, BigDecimal::from_f64(60.01).unwrap());
+        assert_eq!(tx_to.balance_after, BigDecimal::from_f64(39.99).unwrap());
         assert_balances(storage.clone(), (from.uuid, to.uuid), (60.01, 39.99)).await;
     }
 
@@ -141,6 +170,8 @@ mod tests {
                 from_account_id: Uuid::new_v4(),
                 to_account_id: to.uuid,
                 amount: BigDecimal::from_f64(99.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_err());
@@ -158,6 +189,8 @@ mod tests {
                 from_account_id: from.uuid,
                 to_account_id: Uuid::new_v4(),
                 amount: BigDecimal::from_f64(99.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_err());
@@ -175,10 +208,31 @@ mod tests {
                 from_account_id: from.uuid,
                 to_account_id: to.uuid,
                 amount: BigDecimal::from_f64(100.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Insufficient balance");
         assert_balances(storage.clone(), (from.uuid, to.uuid), (100.00, 0.00)).await;
     }
+
+    #[tokio::test]
+    async fn test_replay_skips_balance_check() {
+        let (storage, use_case, from, to) = setup().await;
+        let transfer = || TransferTransactionDTO {
+            idempotency_key: String::from("idemp_1"),
+            from_account_id: from.uuid,
+            to_account_id: to.uuid,
+            amount: BigDecimal::from_f64(60.0).unwrap(),
+            details: Details::default(),
+            expected_version: Some(from.version),
+        };
+
+        let first = use_case.execute(transfer()).await.unwrap();
+        let replayed = use_case.execute(transfer()).await.unwrap();
+        let ids = |legs: &[Transaction]| legs.iter().map(|tx| tx.id).collect::<Vec<_>>();
+        assert_eq!(ids(&replayed), ids(&first));
+        assert_balances(storage.clone(), (from.uuid, to.uuid), (40.00, 60.00)).await;
+    }
 }
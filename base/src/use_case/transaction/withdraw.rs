@@ -1,54 +1,69 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::sync::Mutex;
 
 use crate::{
-    dto::transaction::WithdrawalTransactionDTO,
-    model::{Account, Transaction},
-    storage::Storage,
-    use_case::UseCase,
+    dto::transaction::WithdrawalTransactionDTO, lock::AccountLocks, metrics::record_volume,
+    model::Transaction, storage::Storage, use_case::UseCase,
 };
 
+use super::{replay::replayed_operation, retry::retry_on_conflict};
+
 #[derive(Debug, Clone)]
 pub struct WithdrawalUseCase<S: Storage> {
-    storage: Arc<Mutex<S>>,
+    storage: Arc<S>,
+    locks: Arc<AccountLocks>,
 }
 
 impl<S: Storage> WithdrawalUseCase<S> {
-    pub fn new(storage: &Arc<Mutex<S>>) -> Self {
+    pub fn new(storage: &Arc<S>, locks: &Arc<AccountLocks>) -> Self {
         WithdrawalUseCase {
             storage: Arc::clone(storage),
+            locks: Arc::clone(locks),
         }
     }
-}
 
-#[async_trait]
-impl<S: Storage> UseCase<WithdrawalTransactionDTO, Vec<Transaction>> for WithdrawalUseCase<S> {
-    async fn execute(&self, input: WithdrawalTransactionDTO) -> Result<Vec<Transaction>, String> {
-        let storage = self.storage.lock().await;
-        let from = storage
+    /// Reads the accounts and writes the operation once, under their locks,
+    /// unless its idempotency key was used already.
+    async fn attempt(&self, input: &WithdrawalTransactionDTO) -> Result<Vec<Transaction>, String> {
+        let _guard = self.locks.lock(&[input.account_id]).await;
+        let replayed =
+            replayed_operation(&*self.storage, input.account_id, &input.idempotency_key, 1).await?;
+        if let Some(operation) = replayed {
+            return Ok(operation);
+        }
+        let from = self
+            .storage
             .get_account(input.account_id.clone())
             .await?
             .ok_or("Account not found".to_string())?;
+        from.check_version(input.expected_version)?;
         let tx = input.to_transaction(&from);
 
         if from.balance < input.amount {
             return Err("Insufficient balance".to_string());
         }
 
-        let updated_account = Account {
-            balance: from.balance + tx.amount.clone(),
-            ..from
-        };
+        let update = from.apply(&tx);
 
-        let response = storage
-            .save_transactions(vec![tx], vec![updated_account])
+        let response = self
+            .storage
+            .save_transactions(vec![tx.clone()], vec![update])
             .await?;
+        record_volume("withdrawal", &tx, &response);
         Ok(response)
     }
 }
 
+#[async_trait]
+impl<S: Storage> UseCase<WithdrawalTransactionDTO, Vec<Transaction>> for WithdrawalUseCase<S> {
+    #[tracing::instrument(name = "withdraw", skip_all, fields(account_id = %input.account_id, amount = %input.amount, idempotency_key = %input.idempotency_key))]
+    async fn execute(&self, input: WithdrawalTransactionDTO) -> Result<Vec<Transaction>, String> {
+        input.details.validate()?;
+        retry_on_conflict(|| self.attempt(&input)).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -56,19 +71,22 @@ mod tests {
     use bigdecimal::{BigDecimal, FromPrimitive};
     use uuid::Uuid;
 
-    use crate::storage::InMemoryStorage;
+    use crate::{
+        model::{Account, Details},
+        storage::InMemoryStorage,
+    };
 
     use super::*;
 
     async fn setup(
         initial_accounts: HashMap<Uuid, Account>,
-    ) -> (
-        Arc<Mutex<InMemoryStorage>>,
-        WithdrawalUseCase<InMemoryStorage>,
-    ) {
-        let storage = Arc::new(Mutex::new(InMemoryStorage::new()));
-        storage.lock().await.set_accounts(initial_accounts).await;
-        (storage.clone(), WithdrawalUseCase::new(&storage))
+    ) -> (Arc<InMemoryStorage>, WithdrawalUseCase<InMemoryStorage>) {
+        let storage = Arc::new(InMemoryStorage::new());
+        storage.set_accounts(initial_accounts).await;
+        (
+            storage.clone(),
+            WithdrawalUseCase::new(&storage, &Arc::default()),
+        )
     }
 
     #[tokio::test]
@@ -83,6 +101,8 @@ mod tests {
                 idempotency_key: String::from("idemp_1"),
                 account_id: account.uuid,
                 amount: BigDecimal::from_f64(39.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_ok());
@@ -92,7 +112,6 @@ mod tests {
         assert_eq!(tx.amount, BigDecimal::from_f64(-39.99).unwrap());
         assert_eq!(tx.account_id, account.uuid);
         assert_eq!(tx.idempotency_key, String::from("idemp_1"));
-        let storage = storage.lock().await;
         let account = storage.get_account(account.uuid).await.unwrap().unwrap();
         assert_eq!(account.balance, BigDecimal::from_f64(60.01).unwrap());
     }
@@ -106,6 +125,8 @@ mod tests {
                 idempotency_key: String::from("idemp_1"),
                 account_id: Uuid::new_v4(),
                 amount: BigDecimal::from_f64(99.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_err());
@@ -124,6 +145,8 @@ mod tests {
                 idempotency_key: String::from("idemp_1"),
                 account_id: account.uuid,
                 amount: BigDecimal::from_f64(100.99).unwrap(),
+                details: Details::default(),
+                expected_version: None,
             })
             .await;
         assert!(result.is_err());
@@ -149,6 +172,8 @@ mod tests {
                         idempotency_key: format!("idemp_{}", i),
                         account_id,
                         amount: BigDecimal::from_f64(25.0).unwrap(),
+                        details: Details::default(),
+                        expected_version: None,
                     })
                     .await
             });
@@ -156,7 +181,7 @@ mod tests {
         }
 
         let results = futures::future::join_all(handles).await;
-        
+
         let mut successful_count = 0;
         let mut failed_count = 0;
 
@@ -173,4 +198,26 @@ mod tests {
         assert_eq!(successful_count, 4);
         assert_eq!(failed_count, 6);
     }
+
+    #[tokio::test]
+    async fn test_replay_skips_balance_check() {
+        let mut account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        account.balance = BigDecimal::from_f64(100.0).unwrap();
+        let (storage, use_case) =
+            setup(vec![(account.uuid, account.clone())].into_iter().collect()).await;
+        let withdrawal = || WithdrawalTransactionDTO {
+            idempotency_key: String::from("idemp_1"),
+            account_id: account.uuid,
+            amount: BigDecimal::from_f64(60.0).unwrap(),
+            details: Details::default(),
+            expected_version: None,
+        };
+
+        let first = use_case.execute(withdrawal()).await.unwrap();
+        let replayed = use_case.execute(withdrawal()).await.unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].id, first[0].id);
+        let account = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(account.balance, BigDecimal::from_f64(40.0).unwrap());
+    }
 }
@@ -1,5 +1,11 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
 use chrono::{DateTime, Utc};
 use serde::Serializer;
+use uuid::Uuid;
 
 pub fn serialize_datetime<S>(dt: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -8,3 +14,10 @@ where
     let str = dt.to_rfc3339();
     serializer.serialize_str(&str)
 }
+
+/// Maps an id onto one of `count` shards.
+pub fn shard_of(id: &Uuid, count: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    (hasher.finish() % count as u64) as usize
+}
@@ -1,3 +1,6 @@
+pub const DEFAULT_KEYSPACE: &str = "mini_ledger";
+
 pub struct CassandraConfig {
     pub contact_points: String,
+    pub keyspace: String,
 }
@@ -3,7 +3,7 @@ mod connection;
 mod migrate;
 mod storage;
 
-pub use config::CassandraConfig;
+pub use config::{CassandraConfig, DEFAULT_KEYSPACE};
 pub use connection::connect;
 pub use migrate::migrate;
-pub use storage::CassandraStorage;
+pub use storage::{CassandraStorage, DEFAULT_RECOVERY_INTERVAL, PENDING_OPERATION_TIMEOUT};
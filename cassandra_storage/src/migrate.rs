@@ -1,46 +1,243 @@
 use cassandra_cpp::Session;
 
-pub async fn migrate(session: &Session) -> Result<(), String> {
+/// Tables `migrate` creates in the keyspace.
+pub(crate) const TABLES: [&str; 10] = [
+    "accounts",
+    "idempotency_keys",
+    "pending_operations",
+    "pending_transactions",
+    "pending_account_updates",
+    "transactions_by_account_time_range",
+    "transactions_by_reference",
+    "transactions_by_metadata",
+    "transactions_by_id",
+    "transactions_by_operation",
+];
+
+/// Recreates `keyspace` and its tables, dropping any existing data.
+pub async fn migrate(session: &Session, keyspace: &str) -> Result<(), String> {
     session
-        .execute("DROP KEYSPACE IF EXISTS mini_ledger;")
+        .execute(&format!("DROP KEYSPACE IF EXISTS {};", keyspace))
         .await
         .unwrap();
     session
-        .execute(
+        .execute(&format!(
             r#"
-        CREATE KEYSPACE IF NOT EXISTS mini_ledger WITH REPLICATION = 
-    { 'class' : 'SimpleStrategy', 'replication_factor' : 1 };
+        CREATE KEYSPACE IF NOT EXISTS {} WITH REPLICATION = 
+    {{ 'class' : 'SimpleStrategy', 'replication_factor' : 1 }};
     "#,
-        )
+            keyspace
+        ))
         .await
         .unwrap();
     session
-        .execute(
+        .execute(&format!(
             r#"
-        CREATE TABLE IF NOT EXISTS mini_ledger.accounts (
+        CREATE TABLE IF NOT EXISTS {}.accounts (
             id UUID PRIMARY KEY,
             currency TEXT,
             balance DECIMAL,
             created_at_in_nanos BIGINT,
             last_updated_at_in_nanos BIGINT,
-            version UUID
+            version UUID,
+            pending_operation_id UUID,
+            description TEXT,
+            external_reference TEXT,
+            metadata MAP<TEXT, TEXT>
         );
     "#,
-        )
+            keyspace
+        ))
+        .await
+        .unwrap();
+    // Claimed with IF NOT EXISTS before an operation moves any account.
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.idempotency_keys (
+                account_id UUID,
+                idempotency_key TEXT,
+                operation_id UUID,
+                PRIMARY KEY ((account_id, idempotency_key))
+            );"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    // An operation's transactions and the accounts it moves, as they were,
+    // recorded before it claims or moves anything and discarded once it
+    // commits or is rolled back.
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.pending_operations (
+                operation_id UUID PRIMARY KEY,
+                state TEXT,
+                started_at_in_nanos BIGINT
+            );"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.pending_transactions (
+                idempotency_key TEXT,
+                id UUID,
+                operation_id UUID,
+                counterparty_account_id UUID,
+                kind TEXT,
+                status TEXT,
+                account_id UUID,
+                account_version UUID,
+                amount DECIMAL,
+                balance_after DECIMAL,
+                created_at_in_nanos BIGINT,
+                currency TEXT,
+                description TEXT,
+                external_reference TEXT,
+                metadata MAP<TEXT, TEXT>,
+                PRIMARY KEY (operation_id, id)
+            );"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.pending_account_updates (
+                operation_id UUID,
+                account_id UUID,
+                balance DECIMAL,
+                last_updated_at_in_nanos BIGINT,
+                version UUID,
+                PRIMARY KEY (operation_id, account_id)
+            );"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    // `id` keeps transactions of an account sharing a timestamp apart.
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.transactions_by_account_time_range (
+                idempotency_key TEXT,
+                id UUID,
+                operation_id UUID,
+                counterparty_account_id UUID,
+                kind TEXT,
+                status TEXT,
+                account_id UUID,
+                account_version UUID,
+                amount DECIMAL,
+                balance_after DECIMAL,
+                created_at_in_nanos BIGINT,
+                currency TEXT,
+                description TEXT,
+                external_reference TEXT,
+                metadata MAP<TEXT, TEXT>,
+                PRIMARY KEY (account_id, created_at_in_nanos, id)
+            ) WITH CLUSTERING ORDER BY (created_at_in_nanos DESC, id DESC);"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    // Lookup tables for fetching and searching transactions, written in the
+    // same logged batch as transactions_by_account_time_range.
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.transactions_by_reference (
+                idempotency_key TEXT,
+                id UUID,
+                operation_id UUID,
+                counterparty_account_id UUID,
+                kind TEXT,
+                status TEXT,
+                account_id UUID,
+                account_version UUID,
+                amount DECIMAL,
+                balance_after DECIMAL,
+                created_at_in_nanos BIGINT,
+                currency TEXT,
+                description TEXT,
+                external_reference TEXT,
+                metadata MAP<TEXT, TEXT>,
+                PRIMARY KEY (external_reference, created_at_in_nanos, id)
+            );"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.transactions_by_metadata (
+                metadata_key TEXT,
+                metadata_value TEXT,
+                idempotency_key TEXT,
+                id UUID,
+                operation_id UUID,
+                counterparty_account_id UUID,
+                kind TEXT,
+                status TEXT,
+                account_id UUID,
+                account_version UUID,
+                amount DECIMAL,
+                balance_after DECIMAL,
+                created_at_in_nanos BIGINT,
+                currency TEXT,
+                description TEXT,
+                external_reference TEXT,
+                metadata MAP<TEXT, TEXT>,
+                PRIMARY KEY ((metadata_key, metadata_value), created_at_in_nanos, id)
+            );"#,
+            keyspace
+        ))
+        .await
+        .unwrap();
+    session
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.transactions_by_id (
+                idempotency_key TEXT,
+                id UUID,
+                operation_id UUID,
+                counterparty_account_id UUID,
+                kind TEXT,
+                status TEXT,
+                account_id UUID,
+                account_version UUID,
+                amount DECIMAL,
+                balance_after DECIMAL,
+                created_at_in_nanos BIGINT,
+                currency TEXT,
+                description TEXT,
+                external_reference TEXT,
+                metadata MAP<TEXT, TEXT>,
+                PRIMARY KEY (id)
+            );"#,
+            keyspace
+        ))
         .await
         .unwrap();
     session
-        .execute(
-            r#"CREATE TABLE IF NOT EXISTS mini_ledger.transactions_by_account_time_range (
+        .execute(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {}.transactions_by_operation (
                 idempotency_key TEXT,
                 id UUID,
+                operation_id UUID,
+                counterparty_account_id UUID,
+                kind TEXT,
+                status TEXT,
                 account_id UUID,
+                account_version UUID,
                 amount DECIMAL,
+                balance_after DECIMAL,
                 created_at_in_nanos BIGINT,
                 currency TEXT,
-                PRIMARY KEY (account_id, created_at_in_nanos)
-            ) WITH CLUSTERING ORDER BY (created_at_in_nanos DESC);"#,
-        )
+                description TEXT,
+                external_reference TEXT,
+                metadata MAP<TEXT, TEXT>,
+                PRIMARY KEY (operation_id, id)
+            );"#,
+            keyspace
+        ))
         .await
         .unwrap();
     // ...
@@ -1,112 +1,1417 @@
-use base::model::{Account, Transaction};
-use std::sync::Arc;
+use base::{
+    dto::search::{SearchCursor, TransactionSearchDTO, SEARCH_PARTITION_REQUIRED},
+    model::{Account, AccountUpdate, Details, Metadata, Transaction},
+    shutdown::StopSignal,
+    storage::{AccountCreation, Storage, IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT},
+};
+#[cfg(test)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{collections::BTreeSet, fmt, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use bigdecimal::BigDecimal;
-use cassandra_cpp::{AsRustType, BatchType, BindRustType, Session};
+use cassandra_cpp::{
+    AsRustType, BatchType, BindRustType, CassCollection, CassResult, Consistency, LendingIterator,
+    Map, MapIterator, Row, Session, Statement,
+};
+use chrono::{DateTime, TimeZone, Utc};
 use uuid::Uuid;
 
+use crate::migrate::TABLES;
+
+/// How long an operation may stay pending before recovery takes its writer
+/// for dead and undoes it.
+pub const PENDING_OPERATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the recovery worker looks for abandoned operations.
+pub const DEFAULT_RECOVERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// States of a pending operation. Only its writer moves it to `COMMITTING`,
+/// once every account has moved; only recovery moves it to `ABORTING`.
+const PENDING: &str = "pending";
+const COMMITTING: &str = "committing";
+const ABORTING: &str = "aborting";
+
 #[derive(Debug, Clone)]
 pub struct CassandraStorage {
     session: Arc<Session>,
+    keyspace: String,
+    /// Makes the next `insert_transactions` fail, to test recovery.
+    #[cfg(test)]
+    fail_next_insert: Arc<AtomicBool>,
+}
+
+/// An account an operation moves, as it was before, so it can be put back.
+#[derive(Debug, Clone)]
+struct PendingUpdate {
+    account_id: Uuid,
+    balance: BigDecimal,
+    last_updated_at_in_nanos: i64,
+    version: Uuid,
+}
+
+impl PendingUpdate {
+    fn of(account: &Account) -> Self {
+        PendingUpdate {
+            account_id: account.uuid,
+            balance: account.balance.clone(),
+            last_updated_at_in_nanos: account.last_updated_at.timestamp_nanos_opt().unwrap_or(0),
+            version: account.version,
+        }
+    }
+}
+
+/// What an operation writes, recorded before it claims or moves anything.
+#[derive(Debug)]
+struct PendingOperation {
+    state: String,
+    started_at: DateTime<Utc>,
+    transactions: Vec<Transaction>,
+    updates: Vec<PendingUpdate>,
+}
+
+/// How `recover_operation` left an operation.
+#[derive(Debug)]
+enum Recovery {
+    /// Its transactions are written and its accounts unlocked.
+    Committed(Vec<Transaction>),
+    /// Its accounts are back where they were and its keys are free.
+    RolledBack,
+    /// Its writer may still be running.
+    InProgress,
+}
+
+fn cql_error<E: fmt::Display>(context: &str) -> impl FnOnce(E) -> String + '_ {
+    move |e| format!("{}: {}", context, e)
+}
+
+/// Whether a lightweight transaction applied. A row that fails the
+/// condition, or is missing, reads as not applied.
+fn applied(result: &CassResult) -> bool {
+    result
+        .first_row()
+        .is_some_and(|row| row.get_by_name("[applied]").unwrap_or(false))
 }
 
 impl CassandraStorage {
-    pub fn new(session: Arc<Session>) -> Self {
-        CassandraStorage { session }
+    /// Reads and writes the tables `migrate` created in `keyspace`.
+    pub fn new(session: Arc<Session>, keyspace: &str) -> Self {
+        CassandraStorage {
+            session,
+            keyspace: keyspace.to_string(),
+            #[cfg(test)]
+            fail_next_insert: Arc::default(),
+        }
+    }
+
+    /// Writes the balance, update time and version of `account` if the
+    /// stored one is still at `expected_version` and no other operation holds
+    /// it, in a lightweight transaction on the account's partition. The
+    /// account stays locked by `operation_id` until `unlock_account` or
+    /// `restore_account`. Returns whether it applied.
+    async fn set_account_if(
+        &self,
+        operation_id: Uuid,
+        account: &Account,
+        expected_version: Uuid,
+    ) -> Result<bool, String> {
+        let context = "Failed to update account";
+        let mut stmt = self.session.statement(format!(
+            r#"UPDATE {}.accounts
+                SET balance = ?, last_updated_at_in_nanos = ?, version = ?,
+                pending_operation_id = ?
+                WHERE id = ? IF version = ? AND pending_operation_id = null;"#,
+            self.keyspace
+        ));
+        stmt.bind(0, account.balance.with_scale(2).to_string().as_str())
+            .map_err(cql_error(context))?;
+        stmt.bind(1, nanos(account.last_updated_at)?)
+            .map_err(cql_error(context))?;
+        stmt.bind(2, account.version).map_err(cql_error(context))?;
+        stmt.bind(3, operation_id).map_err(cql_error(context))?;
+        stmt.bind(4, account.uuid).map_err(cql_error(context))?;
+        stmt.bind(5, expected_version).map_err(cql_error(context))?;
+        let result = stmt.execute().await.map_err(cql_error(context))?;
+        // A missing account fails the condition too.
+        Ok(applied(&result))
+    }
+
+    /// Puts back the account `update` describes if `operation_id` still
+    /// holds it, and unlocks it. Accounts the operation never moved are left
+    /// alone.
+    async fn restore_account(
+        &self,
+        operation_id: Uuid,
+        update: &PendingUpdate,
+    ) -> Result<(), String> {
+        let context = "Failed to restore account";
+        let mut stmt = self.session.statement(format!(
+            r#"UPDATE {}.accounts
+                SET balance = ?, last_updated_at_in_nanos = ?, version = ?,
+                pending_operation_id = null
+                WHERE id = ? IF pending_operation_id = ?;"#,
+            self.keyspace
+        ));
+        stmt.bind(0, update.balance.with_scale(2).to_string().as_str())
+            .map_err(cql_error(context))?;
+        stmt.bind(1, update.last_updated_at_in_nanos)
+            .map_err(cql_error(context))?;
+        stmt.bind(2, update.version).map_err(cql_error(context))?;
+        stmt.bind(3, update.account_id)
+            .map_err(cql_error(context))?;
+        stmt.bind(4, operation_id).map_err(cql_error(context))?;
+        stmt.execute().await.map_err(cql_error(context))?;
+        Ok(())
+    }
+
+    /// Unlocks an account `operation_id` moved, once its transactions are
+    /// written.
+    async fn unlock_account(&self, operation_id: Uuid, account_id: Uuid) -> Result<(), String> {
+        let context = "Failed to unlock account";
+        let mut stmt = self.session.statement(format!(
+            "UPDATE {}.accounts SET pending_operation_id = null \
+                WHERE id = ? IF pending_operation_id = ?;",
+            self.keyspace
+        ));
+        stmt.bind(0, account_id).map_err(cql_error(context))?;
+        stmt.bind(1, operation_id).map_err(cql_error(context))?;
+        stmt.execute().await.map_err(cql_error(context))?;
+        Ok(())
+    }
+
+    /// Moves every account to its update, locked by `operation_id`. Returns
+    /// false as soon as one is stale or held by another operation, leaving
+    /// those already moved to `roll_back`.
+    async fn update_accounts(
+        &self,
+        operation_id: Uuid,
+        updates: &[AccountUpdate],
+    ) -> Result<bool, String> {
+        for update in updates {
+            let applied = self
+                .set_account_if(operation_id, &update.account, update.expected_version)
+                .await?;
+            if !applied {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Records each transaction's idempotency key against its operation,
+    /// unless another operation holds it already. Returns whether every key
+    /// was claimed; keys claimed before a taken one are left to the caller.
+    async fn claim_idempotency_keys(&self, transactions: &[Transaction]) -> Result<bool, String> {
+        let context = "Failed to claim idempotency key";
+        for transaction in transactions {
+            let mut stmt = self.session.statement(format!(
+                "INSERT INTO {}.idempotency_keys (account_id, idempotency_key, operation_id) \
+                    VALUES (?, ?, ?) IF NOT EXISTS;",
+                self.keyspace
+            ));
+            stmt.bind(0, transaction.account_id)
+                .map_err(cql_error(context))?;
+            stmt.bind(1, transaction.idempotency_key.as_str())
+                .map_err(cql_error(context))?;
+            stmt.bind(2, transaction.operation_id)
+                .map_err(cql_error(context))?;
+            let result = stmt.execute().await.map_err(cql_error(context))?;
+            if !applied(&result) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Frees the keys of `transactions` that `operation_id` holds, leaving
+    /// those held by other operations alone.
+    async fn release_idempotency_keys(
+        &self,
+        operation_id: Uuid,
+        transactions: &[Transaction],
+    ) -> Result<(), String> {
+        let context = "Failed to release idempotency key";
+        for transaction in transactions {
+            let mut stmt = self.session.statement(format!(
+                "DELETE FROM {}.idempotency_keys \
+                    WHERE account_id = ? AND idempotency_key = ? IF operation_id = ?;",
+                self.keyspace
+            ));
+            stmt.bind(0, transaction.account_id)
+                .map_err(cql_error(context))?;
+            stmt.bind(1, transaction.idempotency_key.as_str())
+                .map_err(cql_error(context))?;
+            stmt.bind(2, operation_id).map_err(cql_error(context))?;
+            stmt.execute().await.map_err(cql_error(context))?;
+        }
+        Ok(())
+    }
+
+    /// When every key that is taken belongs to one earlier operation of the
+    /// same shape, that operation is returned instead of writing again;
+    /// otherwise the keys were reused. An operation holding its keys without
+    /// transactions is recovered first: one that committed is rolled forward
+    /// and returned, and one rolled back or still in progress is reported as
+    /// a conflict so the caller retries. Every claim is made after its
+    /// operation is recorded and freed before the record is discarded, so a
+    /// claim with neither a record nor transactions is left over and freed.
+    async fn replayed_operation(
+        &self,
+        transactions: &[Transaction],
+    ) -> Result<Vec<Transaction>, String> {
+        let mut recorded = BTreeSet::new();
+        for transaction in transactions {
+            recorded.extend(
+                self.get_idempotency_key(transaction.account_id, &transaction.idempotency_key)
+                    .await?,
+            );
+        }
+        let operation_id = match recorded.into_iter().collect::<Vec<_>>().as_slice() {
+            [] => return Err(VERSION_CONFLICT.to_string()),
+            [operation_id] => *operation_id,
+            _ => return Err(IDEMPOTENCY_KEY_REUSED.to_string()),
+        };
+        let mut operation = self.get_operation_transactions(operation_id).await?;
+        if operation.is_empty() {
+            operation = match self
+                .recover_operation(operation_id, PENDING_OPERATION_TIMEOUT)
+                .await?
+            {
+                Recovery::Committed(transactions) => transactions,
+                Recovery::RolledBack => {
+                    // Frees a claim no operation record stands behind, so
+                    // the retry can take it.
+                    self.release_idempotency_keys(operation_id, transactions)
+                        .await?;
+                    return Err(VERSION_CONFLICT.to_string());
+                }
+                Recovery::InProgress => return Err(VERSION_CONFLICT.to_string()),
+            };
+        }
+        if operation.len() != transactions.len() {
+            return Err(IDEMPOTENCY_KEY_REUSED.to_string());
+        }
+        Ok(operation)
+    }
+
+    /// Writes the transactions and their lookup rows in one logged batch,
+    /// so they are all eventually written once any of them is. Writing them
+    /// again writes the same rows.
+    async fn insert_transactions(&self, transactions: &[Transaction]) -> Result<(), String> {
+        #[cfg(test)]
+        if self.fail_next_insert.swap(false, Ordering::SeqCst) {
+            return Err("Failed to save transactions: injected fault".to_string());
+        }
+        let context = "Failed to save transactions";
+        let mut batch = self.session.batch(BatchType::LOGGED);
+        for transaction in transactions {
+            for table in TRANSACTION_TABLES {
+                let mut stmt = self.session.statement(format!(
+                    "INSERT INTO {}.{} ({}) VALUES ({});",
+                    self.keyspace,
+                    table,
+                    TRANSACTION_COLUMNS,
+                    placeholders(TRANSACTION_COLUMN_COUNT)
+                ));
+                bind_transaction(&mut stmt, 0, transaction).map_err(cql_error(context))?;
+                batch.add_statement(stmt).map_err(cql_error(context))?;
+            }
+            if transaction.details.external_reference.is_some() {
+                let mut stmt = self.session.statement(format!(
+                    "INSERT INTO {}.transactions_by_reference ({}) VALUES ({});",
+                    self.keyspace,
+                    TRANSACTION_COLUMNS,
+                    placeholders(TRANSACTION_COLUMN_COUNT)
+                ));
+                bind_transaction(&mut stmt, 0, transaction).map_err(cql_error(context))?;
+                batch.add_statement(stmt).map_err(cql_error(context))?;
+            }
+            for (key, value) in &transaction.details.metadata {
+                let mut stmt = self.session.statement(format!(
+                    "INSERT INTO {}.transactions_by_metadata \
+                        (metadata_key, metadata_value, {}) VALUES ({});",
+                    self.keyspace,
+                    TRANSACTION_COLUMNS,
+                    placeholders(TRANSACTION_COLUMN_COUNT + 2)
+                ));
+                stmt.bind(0, key.as_str()).map_err(cql_error(context))?;
+                stmt.bind(1, value.as_str()).map_err(cql_error(context))?;
+                bind_transaction(&mut stmt, 2, transaction).map_err(cql_error(context))?;
+                batch.add_statement(stmt).map_err(cql_error(context))?;
+            }
+        }
+        batch.execute().await.map_err(cql_error(context))?;
+        Ok(())
+    }
+
+    /// Records the operation, with its transactions and the state of each
+    /// account before it moves, in one logged batch.
+    async fn write_pending(
+        &self,
+        operation_id: Uuid,
+        transactions: &[Transaction],
+        updates: &[PendingUpdate],
+    ) -> Result<(), String> {
+        let context = "Failed to record pending operation";
+        let mut batch = self.session.batch(BatchType::LOGGED);
+        let mut stmt = self.session.statement(format!(
+            "INSERT INTO {}.pending_operations (operation_id, state, started_at_in_nanos) \
+                VALUES (?, ?, ?);",
+            self.keyspace
+        ));
+        stmt.bind(0, operation_id).map_err(cql_error(context))?;
+        stmt.bind(1, PENDING).map_err(cql_error(context))?;
+        stmt.bind(2, nanos(Utc::now())?)
+            .map_err(cql_error(context))?;
+        batch.add_statement(stmt).map_err(cql_error(context))?;
+        for transaction in transactions {
+            let mut stmt = self.session.statement(format!(
+                "INSERT INTO {}.pending_transactions ({}) VALUES ({});",
+                self.keyspace,
+                TRANSACTION_COLUMNS,
+                placeholders(TRANSACTION_COLUMN_COUNT)
+            ));
+            bind_transaction(&mut stmt, 0, transaction).map_err(cql_error(context))?;
+            batch.add_statement(stmt).map_err(cql_error(context))?;
+        }
+        for update in updates {
+            let mut stmt = self.session.statement(format!(
+                "INSERT INTO {}.pending_account_updates \
+                    (operation_id, account_id, balance, last_updated_at_in_nanos, version) \
+                    VALUES (?, ?, ?, ?, ?);",
+                self.keyspace
+            ));
+            stmt.bind(0, operation_id).map_err(cql_error(context))?;
+            stmt.bind(1, update.account_id)
+                .map_err(cql_error(context))?;
+            stmt.bind(2, update.balance.with_scale(2).to_string().as_str())
+                .map_err(cql_error(context))?;
+            stmt.bind(3, update.last_updated_at_in_nanos)
+                .map_err(cql_error(context))?;
+            stmt.bind(4, update.version).map_err(cql_error(context))?;
+            batch.add_statement(stmt).map_err(cql_error(context))?;
+        }
+        batch
+            .set_consistency(Consistency::QUORUM)
+            .map_err(cql_error(context))?;
+        batch.execute().await.map_err(cql_error(context))?;
+        Ok(())
+    }
+
+    async fn read_pending(&self, operation_id: Uuid) -> Result<Option<PendingOperation>, String> {
+        let context = "Failed to read pending operation";
+        let mut stmt = self.session.statement(format!(
+            "SELECT state, started_at_in_nanos FROM {}.pending_operations \
+                WHERE operation_id = ?",
+            self.keyspace
+        ));
+        stmt.bind(0, operation_id).map_err(cql_error(context))?;
+        // The state is only changed by lightweight transactions.
+        stmt.set_consistency(Consistency::SERIAL)
+            .map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        let Some(row) = rows.first_row() else {
+            return Ok(None);
+        };
+        let state: String = row.get_by_name("state").map_err(cql_error(context))?;
+        let started_at_in_nanos: i64 = row
+            .get_by_name("started_at_in_nanos")
+            .map_err(cql_error(context))?;
+
+        let mut stmt = self.session.statement(format!(
+            "SELECT {} FROM {}.pending_transactions WHERE operation_id = ?",
+            TRANSACTION_COLUMNS, self.keyspace
+        ));
+        stmt.bind(0, operation_id).map_err(cql_error(context))?;
+        stmt.set_consistency(Consistency::QUORUM)
+            .map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        let mut transactions = Vec::with_capacity(rows.row_count() as usize);
+        let mut iter = rows.iter();
+        while let Some(row) = iter.next() {
+            transactions.push(get_transaction(&row)?);
+        }
+
+        let mut stmt = self.session.statement(format!(
+            "SELECT account_id, balance, last_updated_at_in_nanos, version \
+                FROM {}.pending_account_updates WHERE operation_id = ?",
+            self.keyspace
+        ));
+        stmt.bind(0, operation_id).map_err(cql_error(context))?;
+        stmt.set_consistency(Consistency::QUORUM)
+            .map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        let mut updates = Vec::with_capacity(rows.row_count() as usize);
+        let mut iter = rows.iter();
+        while let Some(row) = iter.next() {
+            let balance: String = row.get_by_name("balance").map_err(cql_error(context))?;
+            updates.push(PendingUpdate {
+                account_id: row.get_by_name("account_id").map_err(cql_error(context))?,
+                balance: balance.parse().map_err(cql_error(context))?,
+                last_updated_at_in_nanos: row
+                    .get_by_name("last_updated_at_in_nanos")
+                    .map_err(cql_error(context))?,
+                version: row.get_by_name("version").map_err(cql_error(context))?,
+            });
+        }
+
+        Ok(Some(PendingOperation {
+            state,
+            started_at: Utc.timestamp_nanos(started_at_in_nanos),
+            transactions,
+            updates,
+        }))
+    }
+
+    /// Moves a pending operation out of `PENDING`. Returns the state it is
+    /// in afterwards: `state` if this call moved it, whatever another caller
+    /// moved it to otherwise, or an empty string once it is discarded.
+    async fn decide(&self, operation_id: Uuid, state: &str) -> Result<String, String> {
+        let context = "Failed to update pending operation";
+        let mut stmt = self.session.statement(format!(
+            "UPDATE {}.pending_operations SET state = ? WHERE operation_id = ? IF state = ?;",
+            self.keyspace
+        ));
+        stmt.bind(0, state).map_err(cql_error(context))?;
+        stmt.bind(1, operation_id).map_err(cql_error(context))?;
+        stmt.bind(2, PENDING).map_err(cql_error(context))?;
+        let result = stmt.execute().await.map_err(cql_error(context))?;
+        if applied(&result) {
+            return Ok(state.to_string());
+        }
+        Ok(result
+            .first_row()
+            .and_then(|row| row.get_by_name("state").ok())
+            .unwrap_or_default())
+    }
+
+    async fn discard_pending(&self, operation_id: Uuid) -> Result<(), String> {
+        let context = "Failed to discard pending operation";
+        let mut batch = self.session.batch(BatchType::LOGGED);
+        for table in [
+            "pending_transactions",
+            "pending_account_updates",
+            "pending_operations",
+        ] {
+            let mut stmt = self.session.statement(format!(
+                "DELETE FROM {}.{} WHERE operation_id = ?;",
+                self.keyspace, table
+            ));
+            stmt.bind(0, operation_id).map_err(cql_error(context))?;
+            batch.add_statement(stmt).map_err(cql_error(context))?;
+        }
+        batch
+            .set_consistency(Consistency::QUORUM)
+            .map_err(cql_error(context))?;
+        batch.execute().await.map_err(cql_error(context))?;
+        Ok(())
+    }
+
+    /// Finishes a committing operation: writes its transactions, unlocks its
+    /// accounts and discards its record. Safe to run again, or alongside the
+    /// writer.
+    async fn roll_forward(
+        &self,
+        operation_id: Uuid,
+        transactions: &[Transaction],
+        updates: &[PendingUpdate],
+    ) -> Result<(), String> {
+        self.insert_transactions(transactions).await?;
+        for update in updates {
+            self.unlock_account(operation_id, update.account_id).await?;
+        }
+        self.discard_pending(operation_id).await
+    }
+
+    /// Undoes an operation that will not commit: puts back the accounts it
+    /// moved, frees its keys and discards its record, in that order, so a
+    /// failure halfway leaves the record for recovery. Safe to run again.
+    async fn roll_back(
+        &self,
+        operation_id: Uuid,
+        transactions: &[Transaction],
+        updates: &[PendingUpdate],
+    ) -> Result<(), String> {
+        for update in updates {
+            self.restore_account(operation_id, update).await?;
+        }
+        self.release_idempotency_keys(operation_id, transactions)
+            .await?;
+        self.discard_pending(operation_id).await
+    }
+
+    /// The outcome of an operation whose record is gone.
+    async fn finished(&self, operation_id: Uuid) -> Result<Recovery, String> {
+        let transactions = self.get_operation_transactions(operation_id).await?;
+        if transactions.is_empty() {
+            return Ok(Recovery::RolledBack);
+        }
+        Ok(Recovery::Committed(transactions))
+    }
+
+    /// Brings a pending operation to an end from its record. A committing
+    /// operation is rolled forward. A pending one is rolled back once it is
+    /// older than `patience`; a younger one may still have a live writer,
+    /// which would go on moving accounts after they were put back.
+    async fn recover_operation(
+        &self,
+        operation_id: Uuid,
+        patience: Duration,
+    ) -> Result<Recovery, String> {
+        let Some(pending) = self.read_pending(operation_id).await? else {
+            return self.finished(operation_id).await;
+        };
+        let mut state = pending.state.clone();
+        if state == PENDING {
+            let age = (Utc::now() - pending.started_at)
+                .to_std()
+                .unwrap_or_default();
+            if age < patience {
+                return Ok(Recovery::InProgress);
+            }
+            state = self.decide(operation_id, ABORTING).await?;
+        }
+        match state.as_str() {
+            COMMITTING => {
+                self.roll_forward(operation_id, &pending.transactions, &pending.updates)
+                    .await?;
+                Ok(Recovery::Committed(pending.transactions))
+            }
+            ABORTING => {
+                self.roll_back(operation_id, &pending.transactions, &pending.updates)
+                    .await?;
+                Ok(Recovery::RolledBack)
+            }
+            _ => self.finished(operation_id).await,
+        }
+    }
+
+    /// Ends every operation left pending for longer than
+    /// `PENDING_OPERATION_TIMEOUT`, e.g. by a writer that crashed. Returns
+    /// how many were rolled forward or back.
+    pub async fn recover_pending_operations(&self) -> Result<usize, String> {
+        let context = "Failed to list pending operations";
+        let stmt = self.session.statement(format!(
+            "SELECT operation_id FROM {}.pending_operations",
+            self.keyspace
+        ));
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        let mut operation_ids: Vec<Uuid> = Vec::with_capacity(rows.row_count() as usize);
+        let mut iter = rows.iter();
+        while let Some(row) = iter.next() {
+            operation_ids.push(
+                row.get_by_name("operation_id")
+                    .map_err(cql_error(context))?,
+            );
+        }
+
+        let mut recovered = 0;
+        for operation_id in operation_ids {
+            match self
+                .recover_operation(operation_id, PENDING_OPERATION_TIMEOUT)
+                .await
+            {
+                Ok(Recovery::InProgress) => {}
+                Ok(_) => recovered += 1,
+                Err(error) => {
+                    tracing::warn!(%operation_id, %error, "Failed to recover operation")
+                }
+            }
+        }
+        Ok(recovered)
+    }
+
+    /// Runs `recover_pending_operations` every `interval` until `stop` flips.
+    pub async fn recover_every(&self, interval: Duration, mut stop: StopSignal) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = stop.stopped() => return,
+            }
+            match self.recover_pending_operations().await {
+                Ok(0) => {}
+                Ok(recovered) => tracing::info!(recovered, "Recovered pending operations"),
+                Err(error) => tracing::warn!(%error, "Failed to recover pending operations"),
+            }
+        }
+    }
+}
+
+fn nanos(at: DateTime<Utc>) -> Result<i64, String> {
+    at.timestamp_nanos_opt()
+        .ok_or_else(|| format!("Timestamp {} is out of range", at))
+}
+
+/// Binds `description`, `external_reference` and `metadata` starting at `index`.
+fn bind_details(
+    stmt: &mut Statement,
+    index: usize,
+    details: &Details,
+) -> Result<(), Box<cassandra_cpp::Error>> {
+    for (offset, value) in [&details.description, &details.external_reference]
+        .into_iter()
+        .enumerate()
+    {
+        match value {
+            Some(value) => {
+                stmt.bind(index + offset, value.as_str())?;
+            }
+            None => {
+                stmt.bind_null(index + offset)?;
+            }
+        }
+    }
+    let mut metadata = Map::with_capacity(details.metadata.len());
+    for (key, value) in &details.metadata {
+        metadata.append_string(key)?;
+        metadata.append_string(value)?;
+    }
+    stmt.bind_map(index + 2, metadata)?;
+    Ok(())
+}
+
+fn get_details(row: &Row) -> Details {
+    let mut metadata = Metadata::new();
+    let entries: cassandra_cpp::Result<MapIterator> = row.get_by_name("metadata");
+    if let Ok(mut entries) = entries {
+        while let Some((key, value)) = entries.next() {
+            if let (Ok(key), Ok(value)) = (key.get_string(), value.get_string()) {
+                metadata.insert(key, value);
+            }
+        }
+    }
+    Details {
+        description: row.get_by_name("description").ok(),
+        external_reference: row.get_by_name("external_reference").ok(),
+        metadata,
+    }
+}
+
+const TRANSACTION_COLUMNS: &str = "idempotency_key, id, operation_id, counterparty_account_id, \
+    kind, status, account_id, account_version, amount, balance_after, created_at_in_nanos, \
+    currency, description, external_reference, metadata";
+
+/// Number of values bound by `bind_transaction`.
+const TRANSACTION_COLUMN_COUNT: usize = 15;
+
+/// Tables holding a full copy of every transaction.
+const TRANSACTION_TABLES: [&str; 3] = [
+    "transactions_by_account_time_range",
+    "transactions_by_id",
+    "transactions_by_operation",
+];
+
+const SEARCH_PAGE_SIZE: usize = 500;
+
+/// Binds the columns listed in `TRANSACTION_COLUMNS` starting at `index`.
+fn bind_transaction(
+    stmt: &mut Statement,
+    index: usize,
+    transaction: &Transaction,
+) -> Result<(), Box<cassandra_cpp::Error>> {
+    stmt.bind(index, transaction.idempotency_key.as_str())?;
+    stmt.bind(index + 1, transaction.id)?;
+    stmt.bind(index + 2, transaction.operation_id)?;
+    match transaction.counterparty_account_id {
+        Some(counterparty) => {
+            stmt.bind(index + 3, counterparty)?;
+        }
+        None => {
+            stmt.bind_null(index + 3)?;
+        }
+    }
+    stmt.bind(index + 4, transaction.kind.as_str())?;
+    stmt.bind(index + 5, transaction.status.as_str())?;
+    stmt.bind(index + 6, transaction.account_id)?;
+    stmt.bind(index + 7, transaction.account_version)?;
+    stmt.bind(
+        index + 8,
+        transaction.amount.with_scale(2).to_string().as_str(),
+    )?;
+    stmt.bind(
+        index + 9,
+        transaction.balance_after.with_scale(2).to_string().as_str(),
+    )?;
+    let created_at_in_nanos = nanos(transaction.created_at).map_err(cassandra_cpp::Error::from)?;
+    stmt.bind(index + 10, created_at_in_nanos)?;
+    stmt.bind(index + 11, transaction.currency.as_str())?;
+    bind_details(stmt, index + 12, &transaction.details)
+}
+
+fn get_transaction(row: &Row) -> Result<Transaction, String> {
+    let context = "Failed to read transaction";
+    let amount: String = row.get_by_name("amount").map_err(cql_error(context))?;
+    let balance_after: String = row
+        .get_by_name("balance_after")
+        .map_err(cql_error(context))?;
+    let kind: String = row.get_by_name("kind").map_err(cql_error(context))?;
+    let status: String = row.get_by_name("status").map_err(cql_error(context))?;
+    let created_at_in_nanos: i64 = row
+        .get_by_name("created_at_in_nanos")
+        .map_err(cql_error(context))?;
+    Ok(Transaction {
+        id: row.get_by_name("id").map_err(cql_error(context))?,
+        operation_id: row
+            .get_by_name("operation_id")
+            .map_err(cql_error(context))?,
+        counterparty_account_id: row.get_by_name("counterparty_account_id").ok(),
+        kind: kind.parse().map_err(cql_error(context))?,
+        status: status.parse().map_err(cql_error(context))?,
+        idempotency_key: row
+            .get_by_name("idempotency_key")
+            .map_err(cql_error(context))?,
+        account_version: row
+            .get_by_name("account_version")
+            .map_err(cql_error(context))?,
+        account_id: row.get_by_name("account_id").map_err(cql_error(context))?,
+        amount: amount
+            .parse::<BigDecimal>()
+            .map_err(cql_error(context))?
+            .with_scale(2),
+        balance_after: balance_after
+            .parse::<BigDecimal>()
+            .map_err(cql_error(context))?
+            .with_scale(2),
+        created_at: Utc.timestamp_nanos(created_at_in_nanos),
+        currency: row.get_by_name("currency").map_err(cql_error(context))?,
+        details: get_details(row),
+    })
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+/// Where a search page starts: at a time, inclusive, or just past a row.
+/// Paging on the full clustering key keeps every page moving forward, even
+/// through more rows created in one nanosecond than a page holds.
+enum PageStart {
+    At(i64),
+    After(SearchCursor),
+}
+
+/// The partition a search reads from; remaining filters are applied to the
+/// rows it returns.
+enum SearchPartition<'a> {
+    Reference(&'a str),
+    Metadata(&'a str, &'a str),
+    Account(Uuid),
+}
+
+impl<'a> SearchPartition<'a> {
+    fn of(query: &'a TransactionSearchDTO) -> Result<Self, String> {
+        if let Some(reference) = &query.external_reference {
+            return Ok(SearchPartition::Reference(reference));
+        }
+        if let Some((key, value)) = query.metadata() {
+            return Ok(SearchPartition::Metadata(key, value));
+        }
+        if let Some(account_id) = query.account_id {
+            return Ok(SearchPartition::Account(account_id));
+        }
+        Err(SEARCH_PARTITION_REQUIRED.to_string())
+    }
+
+    fn statement(
+        &self,
+        session: &Session,
+        keyspace: &str,
+        start: &PageStart,
+        to: i64,
+    ) -> Result<Statement, Box<cassandra_cpp::Error>> {
+        let (table, key) = match self {
+            SearchPartition::Reference(_) => {
+                ("transactions_by_reference", "external_reference = ?")
+            }
+            SearchPartition::Metadata(..) => (
+                "transactions_by_metadata",
+                "metadata_key = ? AND metadata_value = ?",
+            ),
+            SearchPartition::Account(_) => ("transactions_by_account_time_range", "account_id = ?"),
+        };
+        let range = match start {
+            PageStart::At(_) => "created_at_in_nanos >= ? AND created_at_in_nanos < ?",
+            PageStart::After(_) => {
+                "(created_at_in_nanos, id) > (?, ?) AND (created_at_in_nanos) < (?)"
+            }
+        };
+        let mut stmt = session.statement(format!(
+            "SELECT {} FROM {}.{} WHERE {} AND {} \
+                ORDER BY created_at_in_nanos ASC LIMIT {}",
+            TRANSACTION_COLUMNS, keyspace, table, key, range, SEARCH_PAGE_SIZE
+        ));
+        let index = match self {
+            SearchPartition::Reference(reference) => {
+                stmt.bind(0, *reference)?;
+                1
+            }
+            SearchPartition::Metadata(key, value) => {
+                stmt.bind(0, *key)?;
+                stmt.bind(1, *value)?;
+                2
+            }
+            SearchPartition::Account(account_id) => {
+                stmt.bind(0, *account_id)?;
+                1
+            }
+        };
+        let index = match start {
+            PageStart::At(from) => {
+                stmt.bind(index, *from)?;
+                index + 1
+            }
+            PageStart::After(cursor) => {
+                stmt.bind(index, cursor.created_at_in_nanos)?;
+                stmt.bind(index + 1, cursor.id)?;
+                index + 2
+            }
+        };
+        stmt.bind(index, to)?;
+        Ok(stmt)
     }
 }
 
 #[async_trait]
-impl base::storage::Storage for CassandraStorage {
-    async fn save_account(&self, account: Account) -> Result<(), String> {
-        let mut stmt = self.session.statement(
-            r#"INSERT INTO mini_ledger.accounts
+impl Storage for CassandraStorage {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        let mut stmt = self.session.statement(format!(
+            r#"INSERT INTO {}.accounts
                 (id, currency, balance, created_at_in_nanos,
-                last_updated_at_in_nanos, version)
-                VALUES (?, ?, ?, ?, ?, ?) IF NOT EXISTS"#,
-        );
-        stmt.bind(0, account.uuid).unwrap();
-        stmt.bind(1, account.currency.as_str()).unwrap();
+                last_updated_at_in_nanos, version, description,
+                external_reference, metadata)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS"#,
+            self.keyspace
+        ));
+        let context = "Failed to save account";
+        stmt.bind(0, account.uuid).map_err(cql_error(context))?;
+        stmt.bind(1, account.currency.as_str())
+            .map_err(cql_error(context))?;
         stmt.bind(2, account.balance.with_scale(2).to_string().as_str())
-            .unwrap();
-        stmt.bind(3, account.created_at.timestamp_nanos_opt().unwrap())
-            .unwrap();
-        stmt.bind(4, account.last_updated_at.timestamp_nanos_opt().unwrap())
-            .unwrap();
-        stmt.bind(5, Uuid::new_v4()).unwrap();
-        stmt.execute().await.unwrap();
-        Ok(())
+            .map_err(cql_error(context))?;
+        stmt.bind(3, nanos(account.created_at)?)
+            .map_err(cql_error(context))?;
+        stmt.bind(4, nanos(account.last_updated_at)?)
+            .map_err(cql_error(context))?;
+        stmt.bind(5, account.version).map_err(cql_error(context))?;
+        bind_details(&mut stmt, 6, &account.details).map_err(cql_error(context))?;
+        let result = stmt.execute().await.map_err(cql_error(context))?;
+        let applied = result
+            .first_row()
+            .is_none_or(|row| row.get_by_name("[applied]").unwrap_or(true));
+        if applied {
+            return Ok(AccountCreation::Created);
+        }
+        let stored = self
+            .get_account(account.uuid)
+            .await?
+            .ok_or("Account not found after a conflicting insert")?;
+        Ok(AccountCreation::AlreadyExists(stored))
     }
 
     async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
-        let query = r#"SELECT id, currency, balance, created_at_in_nanos,
-            last_updated_at_in_nanos, version FROM mini_ledger.accounts WHERE id = ?
-            "#;
+        let query = format!(
+            r#"SELECT id, currency, balance, created_at_in_nanos,
+            last_updated_at_in_nanos, version, description, external_reference,
+            metadata FROM {}.accounts WHERE id = ?
+            "#,
+            self.keyspace
+        );
+        let context = "Failed to get account";
         let mut stmt = self.session.statement(query);
-        stmt.bind(0, uuid).unwrap();
-        let rows = stmt.execute().await.unwrap();
+        stmt.bind(0, uuid).map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
 
-        match rows.first_row() {
-            None => return Ok(None),
-            Some(row) => {
-                let balance_as_str: String = row.get_by_name("balance").unwrap();
-                let balance = balance_as_str.parse::<BigDecimal>().unwrap().with_scale(2);
-                Ok(Some(Account::from_storage(
-                    row.get_by_name("id").unwrap(),
-                    row.get_by_name("currency").unwrap(),
-                    balance,
-                    row.get_by_name("created_at_in_nanos").unwrap(),
-                    row.get_by_name("last_updated_at_in_nanos").unwrap(),
-                    row.get_by_name("version").unwrap(),
-                )))
-            }
-        }
+        let Some(row) = rows.first_row() else {
+            return Ok(None);
+        };
+        let balance: String = row.get_by_name("balance").map_err(cql_error(context))?;
+        let balance = balance
+            .parse::<BigDecimal>()
+            .map_err(cql_error(context))?
+            .with_scale(2);
+        Ok(Some(Account {
+            details: get_details(&row),
+            ..Account::from_storage(
+                row.get_by_name("id").map_err(cql_error(context))?,
+                row.get_by_name("currency").map_err(cql_error(context))?,
+                balance,
+                row.get_by_name("created_at_in_nanos")
+                    .map_err(cql_error(context))?,
+                row.get_by_name("last_updated_at_in_nanos")
+                    .map_err(cql_error(context))?,
+                row.get_by_name("version").map_err(cql_error(context))?,
+            )
+        }))
     }
 
+    /// Cassandra only honours conditions within a single partition, so an
+    /// operation is spread over several steps, recorded first so recovery can
+    /// end it whichever step it stopped at. It records its transactions and
+    /// the accounts as they are, claims its idempotency keys, then
+    /// compare-and-sets and locks each account on its own. Once every account
+    /// has moved it is marked committing, and only then are its transactions
+    /// written and its accounts unlocked. Until the end, readers can see
+    /// accounts moved without their transactions; other writers are kept off
+    /// them by the lock.
+    ///
+    /// A stale account or a taken key rolls the operation back. A failure
+    /// after it is committing is rolled forward by recovery, either when the
+    /// key is replayed or by `recover_every`. A failure before leaves it
+    /// pending, and recovery rolls it back after `PENDING_OPERATION_TIMEOUT`;
+    /// a writer still alive past that may lock an account recovery already
+    /// put back, which then stays locked.
     async fn save_transactions(
         &self,
         created_transactions: Vec<Transaction>,
-        updated_accounts: Vec<Account>,
+        updated_accounts: Vec<AccountUpdate>,
     ) -> Result<Vec<Transaction>, String> {
-        let mut changes = self.session.batch(BatchType::LOGGED);
-
-        let mut txs: Vec<Transaction> = Vec::new();
-        for transaction in created_transactions {
-            let mut stmt = self.session.statement(
-                r#"INSERT INTO mini_ledger.transactions_by_account_time_range
-                    (idempotency_key, id, account_id, amount, created_at_in_nanos, currency)
-                    VALUES (?, ?, ?, ?, ?, ?) IF NOT EXISTS;"#,
-            );
-            stmt.bind(0, transaction.idempotency_key.as_str()).unwrap();
-            stmt.bind(1, transaction.id).unwrap();
-            stmt.bind(2, transaction.account_id).unwrap();
-            stmt.bind(3, transaction.amount.with_scale(2).to_string().as_str())
-                .unwrap();
-            stmt.bind(4, transaction.created_at.timestamp_nanos_opt().unwrap())
-                .unwrap();
-            stmt.bind(5, transaction.currency.as_str()).unwrap();
-            changes.add_statement(stmt).unwrap();
-            txs.push(transaction);
+        let operation_id = created_transactions
+            .first()
+            .map(|transaction| transaction.operation_id)
+            .ok_or("An operation writes at least one transaction")?;
+        let mut updates = Vec::with_capacity(updated_accounts.len());
+        for update in &updated_accounts {
+            match self.get_account(update.account.uuid).await? {
+                Some(account) if account.version == update.expected_version => {
+                    updates.push(PendingUpdate::of(&account))
+                }
+                _ => return Err(VERSION_CONFLICT.to_string()),
+            }
+        }
+
+        self.write_pending(operation_id, &created_transactions, &updates)
+            .await?;
+        let claimed = match self.claim_idempotency_keys(&created_transactions).await {
+            Ok(claimed) => claimed,
+            Err(error) => {
+                self.roll_back(operation_id, &created_transactions, &updates)
+                    .await?;
+                return Err(error);
+            }
+        };
+        if !claimed {
+            self.roll_back(operation_id, &created_transactions, &updates)
+                .await?;
+            return self.replayed_operation(&created_transactions).await;
+        }
+        let committing = self
+            .update_accounts(operation_id, &updated_accounts)
+            .await?
+            && self.decide(operation_id, COMMITTING).await? == COMMITTING;
+        if !committing {
+            self.roll_back(operation_id, &created_transactions, &updates)
+                .await?;
+            return Err(VERSION_CONFLICT.to_string());
         }
+        self.roll_forward(operation_id, &created_transactions, &updates)
+            .await?;
+        Ok(created_transactions)
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let query = format!(
+            "SELECT {} FROM {}.transactions_by_account_time_range \
+                WHERE account_id = ? AND created_at_in_nanos >= ? AND created_at_in_nanos < ? \
+                ORDER BY created_at_in_nanos ASC LIMIT ?",
+            TRANSACTION_COLUMNS, self.keyspace
+        );
+        let context = "Failed to get transactions";
+        let mut stmt = self.session.statement(query);
+        stmt.bind(0, account_id).map_err(cql_error(context))?;
+        stmt.bind(1, nanos(from)?).map_err(cql_error(context))?;
+        stmt.bind(2, nanos(to)?).map_err(cql_error(context))?;
+        stmt.bind(3, limit.min(i32::MAX as usize) as i32)
+            .map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+
+        let mut transactions = Vec::with_capacity(rows.row_count() as usize);
+        let mut iter = rows.iter();
+        while let Some(row) = iter.next() {
+            transactions.push(get_transaction(&row)?);
+        }
+        Ok(transactions)
+    }
 
-        for account in updated_accounts {
-            let mut stmt = self.session.statement(
-                r#"UPDATE mini_ledger.accounts
-                    SET balance = ?, last_updated_at_in_nanos = ?, version = ?
-                    WHERE id = ? IF version = ?;"#,
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        let mut stmt = self.session.statement(format!(
+            "SELECT {} FROM {}.transactions_by_id WHERE id = ?",
+            TRANSACTION_COLUMNS, self.keyspace
+        ));
+        let context = "Failed to get transaction";
+        stmt.bind(0, id).map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        rows.first_row()
+            .map(|row| get_transaction(&row))
+            .transpose()
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        let mut stmt = self.session.statement(format!(
+            "SELECT {} FROM {}.transactions_by_operation WHERE operation_id = ?",
+            TRANSACTION_COLUMNS, self.keyspace
+        ));
+        let context = "Failed to get operation transactions";
+        stmt.bind(0, operation_id).map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        let mut legs = Vec::with_capacity(rows.row_count() as usize);
+        let mut iter = rows.iter();
+        while let Some(row) = iter.next() {
+            legs.push(get_transaction(&row)?);
+        }
+        legs.sort_by_key(|tx| (tx.created_at, tx.id));
+        Ok(legs)
+    }
+
+    /// A key may be claimed by an operation still being written, whose
+    /// transactions are not there yet.
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        let context = "Failed to read idempotency key";
+        let mut stmt = self.session.statement(format!(
+            "SELECT operation_id FROM {}.idempotency_keys \
+                WHERE account_id = ? AND idempotency_key = ?",
+            self.keyspace
+        ));
+        stmt.bind(0, account_id).map_err(cql_error(context))?;
+        stmt.bind(1, idempotency_key).map_err(cql_error(context))?;
+        // Reads the latest claim even if it is still being replicated.
+        stmt.set_consistency(Consistency::SERIAL)
+            .map_err(cql_error(context))?;
+        let rows = stmt.execute().await.map_err(cql_error(context))?;
+        rows.first_row()
+            .map(|row| row.get_by_name("operation_id").map_err(cql_error(context)))
+            .transpose()
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let partition = SearchPartition::of(query)?;
+        let to = query.to.map(nanos).transpose()?.unwrap_or(i64::MAX);
+        let from = query.from.map(nanos).transpose()?.unwrap_or(i64::MIN);
+        let mut start = match after {
+            Some(cursor) if cursor.created_at_in_nanos >= from => PageStart::After(cursor),
+            _ => PageStart::At(from),
+        };
+
+        let mut transactions = Vec::new();
+        loop {
+            let stmt = partition
+                .statement(&self.session, &self.keyspace, &start, to)
+                .map_err(|e| format!("Failed to search transactions: {}", e))?;
+            let rows = stmt
+                .execute()
+                .await
+                .map_err(|e| format!("Failed to search transactions: {}", e))?;
+
+            let mut scanned = 0;
+            let mut position = None;
+            let mut iter = rows.iter();
+            while let Some(row) = iter.next() {
+                scanned += 1;
+                let transaction = get_transaction(&row)?;
+                position = Some(SearchCursor::of(&transaction));
+                if query.matches(&transaction) {
+                    transactions.push(transaction);
+                    if transactions.len() == limit {
+                        return Ok(transactions);
+                    }
+                }
+            }
+            match position {
+                Some(position) if scanned == SEARCH_PAGE_SIZE => {
+                    start = PageStart::After(position);
+                }
+                _ => return Ok(transactions),
+            }
+        }
+    }
+
+    /// Reachable once `system.local` answers, and migrated once every table
+    /// of the keyspace exists.
+    async fn health_check(&self) -> Result<(), String> {
+        self.session
+            .execute("SELECT now() FROM system.local")
+            .await
+            .map_err(|e| format!("Cassandra is unreachable: {}", e))?;
+        let mut stmt = self
+            .session
+            .statement("SELECT table_name FROM system_schema.tables WHERE keyspace_name = ?");
+        stmt.bind(0, self.keyspace.as_str())
+            .map_err(|e| format!("Failed to list Cassandra tables: {}", e))?;
+        let rows = stmt
+            .execute()
+            .await
+            .map_err(|e| format!("Failed to list Cassandra tables: {}", e))?;
+        let mut tables: Vec<String> = Vec::with_capacity(rows.row_count() as usize);
+        let mut iter = rows.iter();
+        while let Some(row) = iter.next() {
+            if let Ok(name) = row.get_by_name("table_name") {
+                tables.push(name);
+            }
+        }
+        let missing: Vec<&str> = TABLES
+            .into_iter()
+            .filter(|table| !tables.iter().any(|name| name == table))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Cassandra migrations pending, missing tables: {:?}",
+                missing
+            ));
+        }
+        Ok(())
+    }
+
+    /// A no-op: cassandra-cpp does not expose `cass_session_close`, and the
+    /// session is shared by the statements in flight. The driver closes it,
+    /// waiting for pending requests, when the last handle is freed, which
+    /// happens once the storage is dropped after this call.
+    async fn close(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base::{dto::transaction::TransferTransactionDTO, model::OperationKind};
+
+    use crate::{connect, migrate, CassandraConfig, DEFAULT_KEYSPACE};
+
+    use super::*;
+
+    /// Connects to the cluster in `CASSANDRA_CONTACT_POINTS`, e.g.
+    /// `CASSANDRA_CONTACT_POINTS=127.0.0.1 cargo test -p cassandra_storage -- --ignored --test-threads 1`.
+    /// The migration recreates the keyspace, so use a scratch cluster.
+    async fn setup() -> CassandraStorage {
+        let contact_points = std::env::var("CASSANDRA_CONTACT_POINTS")
+            .expect("CASSANDRA_CONTACT_POINTS must be set");
+        let config = CassandraConfig {
+            contact_points,
+            keyspace: DEFAULT_KEYSPACE.to_string(),
+        };
+        let session = connect(&config).await.unwrap();
+        migrate(&session, &config.keyspace).await.unwrap();
+        CassandraStorage::new(Arc::new(session), &config.keyspace)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Cassandra in CASSANDRA_CONTACT_POINTS"]
+    async fn test_conformance() {
+        let storage = setup().await;
+        base::storage::conformance::run(&storage).await;
+    }
+
+    /// Transactions that fail to write once every account moved are still
+    /// committed: the account stays locked until replaying the key rolls the
+    /// operation forward and returns it.
+    #[tokio::test]
+    #[ignore = "requires a local Cassandra in CASSANDRA_CONTACT_POINTS"]
+    async fn test_failed_insert_is_rolled_forward() {
+        let storage = setup().await;
+        let account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        let deposit = Transaction::new(
+            &account,
+            "deposit",
+            &BigDecimal::from(100),
+            OperationKind::Deposit,
+        );
+        let update = account.apply(&deposit);
+
+        storage.fail_next_insert.store(true, Ordering::SeqCst);
+        let result = storage
+            .save_transactions(vec![deposit.clone()], vec![update.clone()])
+            .await;
+        assert!(result
+            .unwrap_err()
+            .starts_with("Failed to save transactions"));
+        assert!(storage.get_transaction(deposit.id).await.unwrap().is_none());
+
+        let other = Transaction::new(
+            &update.account,
+            "other",
+            &BigDecimal::from(1),
+            OperationKind::Deposit,
+        );
+        let result = storage
+            .save_transactions(vec![other.clone()], vec![update.account.apply(&other)])
+            .await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+
+        let replay = Transaction::new(
+            &update.account,
+            "deposit",
+            &BigDecimal::from(100),
+            OperationKind::Deposit,
+        );
+        let saved = storage
+            .save_transactions(vec![replay.clone()], vec![update.account.apply(&replay)])
+            .await
+            .unwrap();
+        assert_eq!(saved[0].id, deposit.id);
+        assert!(storage.get_transaction(deposit.id).await.unwrap().is_some());
+        assert!(storage.get_transaction(replay.id).await.unwrap().is_none());
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored, update.account);
+        assert_eq!(storage.recover_pending_operations().await.unwrap(), 0);
+
+        let result = storage
+            .save_transactions(vec![other.clone()], vec![update.account.apply(&other)])
+            .await;
+        assert!(result.is_ok());
+    }
+
+    /// More rows created in one nanosecond than a search page holds are
+    /// still read once each, across pages and across cursors.
+    #[tokio::test]
+    #[ignore = "requires a local Cassandra in CASSANDRA_CONTACT_POINTS"]
+    async fn test_search_pages_through_one_nanosecond() {
+        let storage = setup().await;
+        let mut account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        let created_at = Utc::now();
+        for i in 0..SEARCH_PAGE_SIZE + 1 {
+            let mut deposit = Transaction::new(
+                &account,
+                &format!("deposit_{}", i),
+                &BigDecimal::from(1),
+                OperationKind::Deposit,
             );
-            stmt.bind(0, account.balance.with_scale(2).to_string().as_str())
+            deposit.created_at = created_at;
+            let update = account.apply(&deposit);
+            storage
+                .save_transactions(vec![deposit], vec![update.clone()])
+                .await
                 .unwrap();
-            stmt.bind(1, account.last_updated_at.timestamp_nanos_opt().unwrap())
-                .unwrap();
-            stmt.bind(2, Uuid::new_v4()).unwrap();
-            stmt.bind(3, account.uuid).unwrap();
-            stmt.bind(4, account.version).unwrap();
-            changes.add_statement(stmt).unwrap();
+            account = update.account;
         }
 
-        changes.execute().await.unwrap();
-        Ok(txs)
+        let query = TransactionSearchDTO {
+            account_id: Some(account.uuid),
+            ..Default::default()
+        };
+        let found = storage
+            .search_transactions(&query, None, SEARCH_PAGE_SIZE * 2)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), SEARCH_PAGE_SIZE + 1);
+
+        let first = storage.search_transactions(&query, None, 10).await.unwrap();
+        let rest = storage
+            .search_transactions(
+                &query,
+                Some(SearchCursor::of(&first[9])),
+                SEARCH_PAGE_SIZE * 2,
+            )
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), SEARCH_PAGE_SIZE - 9);
+        assert_eq!(rest[0].id, found[10].id);
+    }
+
+    /// Transfers racing from the same read of both accounts: exactly one
+    /// commits, and the others leave neither account nor any transaction.
+    #[tokio::test]
+    #[ignore = "requires a local Cassandra in CASSANDRA_CONTACT_POINTS"]
+    async fn test_concurrent_transfers() {
+        let storage = setup().await;
+        let mut from = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        let to = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        storage.save_account(from.clone()).await.unwrap();
+        storage.save_account(to.clone()).await.unwrap();
+        let deposit = Transaction::new(
+            &from,
+            "deposit",
+            &BigDecimal::from(100),
+            OperationKind::Deposit,
+        );
+        let update = from.apply(&deposit);
+        storage
+            .save_transactions(vec![deposit], vec![update.clone()])
+            .await
+            .unwrap();
+        from = update.account;
+
+        let attempts = (0..8).map(|i| {
+            let dto = TransferTransactionDTO {
+                idempotency_key: format!("transfer_{}", i),
+                from_account_id: from.uuid,
+                to_account_id: to.uuid,
+                amount: BigDecimal::from(10),
+                details: Details::default(),
+                expected_version: None,
+            };
+            let (from_tx, to_tx) = dto.to_transactions(&from, &to);
+            let updates = vec![from.apply(&from_tx), to.apply(&to_tx)];
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                let result = storage
+                    .save_transactions(vec![from_tx.clone(), to_tx.clone()], updates.clone())
+                    .await;
+                (result, vec![from_tx, to_tx], updates)
+            })
+        });
+        let mut committed = Vec::new();
+        let mut committed_key = Vec::new();
+        for attempt in attempts.collect::<Vec<_>>() {
+            let (result, legs, updates) = attempt.await.unwrap();
+            match result {
+                Ok(_) => {
+                    committed_key.push(legs[0].idempotency_key.clone());
+                    committed.push(updates)
+                }
+                Err(error) => {
+                    assert_eq!(error, VERSION_CONFLICT);
+                    for leg in &legs {
+                        assert!(storage.get_transaction(leg.id).await.unwrap().is_none());
+                    }
+                }
+            }
+        }
+
+        assert_eq!(committed.len(), 1);
+        let stored_from = storage.get_account(from.uuid).await.unwrap().unwrap();
+        let stored_to = storage.get_account(to.uuid).await.unwrap().unwrap();
+        assert_eq!(stored_from, committed[0][0].account);
+        assert_eq!(stored_to, committed[0][1].account);
+        assert_eq!(stored_from.balance, BigDecimal::from(90));
+        assert_eq!(stored_to.balance, BigDecimal::from(10));
+
+        // A loser that claimed its key before failing freed it again.
+        let loser = (0..8)
+            .map(|i| format!("transfer_{}", i))
+            .find(|key| *key != committed_key[0])
+            .unwrap();
+        let dto = TransferTransactionDTO {
+            idempotency_key: loser,
+            from_account_id: from.uuid,
+            to_account_id: to.uuid,
+            amount: BigDecimal::from(10),
+            details: Details::default(),
+            expected_version: None,
+        };
+        let (from_tx, to_tx) = dto.to_transactions(&stored_from, &stored_to);
+        let updates = vec![stored_from.apply(&from_tx), stored_to.apply(&to_tx)];
+        let saved = storage
+            .save_transactions(vec![from_tx.clone(), to_tx], updates)
+            .await
+            .unwrap();
+        assert_eq!(saved[0].operation_id, from_tx.operation_id);
     }
 }
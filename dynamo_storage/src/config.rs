@@ -0,0 +1,22 @@
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:8000";
+pub const DEFAULT_REGION: &str = "us-east-1";
+pub const DEFAULT_TABLE: &str = "mini_ledger";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamoConfig {
+    /// `None` uses the regional AWS endpoint.
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub table: String,
+}
+
+/// DynamoDB Local, as started by `docker compose up dynamodb-local`.
+impl Default for DynamoConfig {
+    fn default() -> Self {
+        DynamoConfig {
+            endpoint: Some(DEFAULT_ENDPOINT.to_string()),
+            region: DEFAULT_REGION.to_string(),
+            table: DEFAULT_TABLE.to_string(),
+        }
+    }
+}
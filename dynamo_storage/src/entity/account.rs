@@ -1,20 +1,21 @@
 use std::collections::HashMap;
 
 use aws_sdk_dynamodb::types::AttributeValue;
-use base::model::Account;
+use base::model::{Account, Details};
 use bigdecimal::BigDecimal;
 use chrono::TimeZone;
 use uuid::Uuid;
 
-use super::utils::{get_bigdecimal, get_i64, get_string, get_uuid};
+use super::utils::{get_bigdecimal, get_details, get_nanos, get_string, get_uuid};
 
 pub struct AccountEntity {
     pub uuid: Uuid,
     pub currency: String,
     pub balance: BigDecimal,
-    pub created_at_in_millis: i64,
-    pub last_updated_at_in_millis: i64,
+    pub created_at_in_nanos: i64,
+    pub last_updated_at_in_nanos: i64,
     pub version: Uuid,
+    pub details: Details,
 }
 
 impl Into<Account> for &AccountEntity {
@@ -23,27 +24,26 @@ impl Into<Account> for &AccountEntity {
             uuid: self.uuid,
             currency: self.currency.clone(),
             balance: self.balance.clone(),
-            created_at: chrono::Utc
-                .timestamp_millis_opt(self.created_at_in_millis)
-                .unwrap(),
-            last_updated_at: chrono::Utc
-                .timestamp_millis_opt(self.last_updated_at_in_millis)
-                .unwrap(),
+            created_at: chrono::Utc.timestamp_nanos(self.created_at_in_nanos),
+            last_updated_at: chrono::Utc.timestamp_nanos(self.last_updated_at_in_nanos),
             version: self.version,
+            details: self.details.clone(),
         }
     }
 }
 
-impl From<&HashMap<String, AttributeValue>> for AccountEntity {
-    fn from(value: &HashMap<String, AttributeValue>) -> Self {
-        let account = AccountEntity {
-            uuid: get_uuid(value, "uuid"),
+impl TryFrom<&HashMap<String, AttributeValue>> for AccountEntity {
+    type Error = String;
+
+    fn try_from(value: &HashMap<String, AttributeValue>) -> Result<Self, Self::Error> {
+        Ok(AccountEntity {
+            uuid: get_uuid(value, "uuid")?,
             currency: get_string(value, "currency"),
-            balance: get_bigdecimal(value, "balance"),
-            created_at_in_millis: get_i64(value, "created_at_in_millis"),
-            last_updated_at_in_millis: get_i64(value, "last_updated_at_in_millis"),
-            version: get_uuid(value, "version"),
-        };
-        account
+            balance: get_bigdecimal(value, "balance")?,
+            created_at_in_nanos: get_nanos(value, "created_at"),
+            last_updated_at_in_nanos: get_nanos(value, "last_updated_at"),
+            version: get_uuid(value, "version")?,
+            details: get_details(value),
+        })
     }
 }
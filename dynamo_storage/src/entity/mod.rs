@@ -3,3 +3,5 @@ pub mod transaction;
 mod utils;
 
 pub use account::AccountEntity;
+pub use transaction::TransactionEntity;
+pub(crate) use utils::{get_bigdecimal, get_nanos, get_string, get_uuid};
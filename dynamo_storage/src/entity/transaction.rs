@@ -1,48 +1,71 @@
 use std::collections::HashMap;
 
 use aws_sdk_dynamodb::types::AttributeValue;
-use base::model::Transaction;
+use base::model::{Details, OperationKind, Transaction, TransactionStatus};
 use bigdecimal::BigDecimal;
 use chrono::TimeZone;
 use uuid::Uuid;
 
-use super::utils::{get_bigdecimal, get_i64, get_string, get_uuid};
+use super::utils::{
+    get_bigdecimal, get_details, get_nanos, get_optional_uuid, get_string, get_uuid,
+};
 
 pub struct TransactionEntity {
     pub id: Uuid,
+    pub operation_id: Uuid,
+    pub counterparty_account_id: Option<Uuid>,
+    pub kind: OperationKind,
+    pub status: TransactionStatus,
     pub idempotency_key: String,
     pub account_version: Uuid,
     pub account_id: Uuid,
     pub amount: BigDecimal,
+    pub balance_after: BigDecimal,
     pub created_at_in_nanos: i64,
     pub currency: String,
+    pub details: Details,
 }
 
 impl Into<Transaction> for &TransactionEntity {
     fn into(self) -> Transaction {
         Transaction {
             id: self.id,
+            operation_id: self.operation_id,
+            counterparty_account_id: self.counterparty_account_id,
+            kind: self.kind,
+            status: self.status,
             idempotency_key: self.idempotency_key.clone(),
             account_version: self.account_version,
             account_id: self.account_id,
             amount: self.amount.clone(),
+            balance_after: self.balance_after.clone(),
             created_at: chrono::Utc.timestamp_nanos(self.created_at_in_nanos),
             currency: self.currency.clone(),
+            details: self.details.clone(),
         }
     }
 }
 
-impl From<&HashMap<String, AttributeValue>> for TransactionEntity {
-    fn from(value: &HashMap<String, AttributeValue>) -> Self {
-        let transaction = TransactionEntity {
-            id: get_uuid(value, "id"),
+/// Fails on items missing an attribute the model requires, e.g. ones written
+/// before `utils::migrate` backfilled them.
+impl TryFrom<&HashMap<String, AttributeValue>> for TransactionEntity {
+    type Error = String;
+
+    fn try_from(value: &HashMap<String, AttributeValue>) -> Result<Self, Self::Error> {
+        Ok(TransactionEntity {
+            id: get_uuid(value, "id")?,
+            operation_id: get_uuid(value, "operation_id")?,
+            counterparty_account_id: get_optional_uuid(value, "counterparty_account_id"),
+            kind: get_string(value, "kind").parse()?,
+            status: get_string(value, "status").parse()?,
             idempotency_key: get_string(value, "idempotency_key"),
-            account_version: get_uuid(value, "account_version"),
-            account_id: get_uuid(value, "account_id"),
-            amount: get_bigdecimal(value, "amount"),
-            created_at_in_nanos: get_i64(value, "created_at_in_nanos"),
+            account_version: get_uuid(value, "account_version")?,
+            account_id: get_uuid(value, "account_id")?,
+            amount: get_bigdecimal(value, "amount")?,
+            balance_after: get_bigdecimal(value, "balance_after")?,
+            created_at_in_nanos: get_nanos(value, "created_at"),
             currency: get_string(value, "currency"),
-        };
-        transaction
+            details: get_details(value),
+        })
     }
 }
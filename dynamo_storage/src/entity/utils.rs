@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
 use aws_sdk_dynamodb::types::AttributeValue;
-use bigdecimal::{BigDecimal, Zero};
+use base::model::{Details, Metadata};
+use bigdecimal::BigDecimal;
 use uuid::Uuid;
 
 pub fn get_string(value: &HashMap<String, AttributeValue>, key: &str) -> String {
@@ -12,16 +13,19 @@ pub fn get_string(value: &HashMap<String, AttributeValue>, key: &str) -> String
         .unwrap_or_default()
 }
 
-pub fn get_uuid(value: &HashMap<String, AttributeValue>, key: &str) -> Uuid {
-    Uuid::parse_str(&get_string(value, key)).unwrap()
+pub fn get_uuid(value: &HashMap<String, AttributeValue>, key: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(&get_string(value, key)).map_err(|e| format!("Invalid {}: {}", key, e))
 }
 
-pub fn get_bigdecimal(value: &HashMap<String, AttributeValue>, key: &str) -> BigDecimal {
+pub fn get_bigdecimal(
+    value: &HashMap<String, AttributeValue>,
+    key: &str,
+) -> Result<BigDecimal, String> {
     value
         .get(key)
         .and_then(|attr| attr.as_n().ok())
         .and_then(|num| BigDecimal::parse_bytes(num.as_bytes(), 10))
-        .unwrap_or_else(BigDecimal::zero)
+        .ok_or(format!("Missing or invalid {}", key))
 }
 
 pub fn get_i64(value: &HashMap<String, AttributeValue>, key: &str) -> i64 {
@@ -31,3 +35,45 @@ pub fn get_i64(value: &HashMap<String, AttributeValue>, key: &str) -> i64 {
         .and_then(|num| num.parse::<i64>().ok())
         .unwrap_or(0)
 }
+
+/// Items saved before timestamps were kept in nanoseconds only have the
+/// `_in_millis` attribute.
+pub fn get_nanos(value: &HashMap<String, AttributeValue>, name: &str) -> i64 {
+    let nanos = format!("{}_in_nanos", name);
+    if value.contains_key(&nanos) {
+        return get_i64(value, &nanos);
+    }
+    get_i64(value, &format!("{}_in_millis", name)) * 1_000_000
+}
+
+pub fn get_optional_string(value: &HashMap<String, AttributeValue>, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|attr| attr.as_s().ok())
+        .map(|s| s.to_string())
+}
+
+pub fn get_optional_uuid(value: &HashMap<String, AttributeValue>, key: &str) -> Option<Uuid> {
+    get_optional_string(value, key).and_then(|s| Uuid::parse_str(&s).ok())
+}
+
+pub fn get_string_map(value: &HashMap<String, AttributeValue>, key: &str) -> Metadata {
+    value
+        .get(key)
+        .and_then(|attr| attr.as_m().ok())
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|(k, v)| v.as_s().ok().map(|v| (k.clone(), v.clone())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn get_details(value: &HashMap<String, AttributeValue>) -> Details {
+    Details {
+        description: get_optional_string(value, "description"),
+        external_reference: get_optional_string(value, "external_reference"),
+        metadata: get_string_map(value, "metadata"),
+    }
+}
@@ -1,5 +1,7 @@
+mod config;
 mod entity;
 mod storage;
 pub mod utils;
 
+pub use config::{DynamoConfig, DEFAULT_ENDPOINT, DEFAULT_REGION, DEFAULT_TABLE};
 pub use storage::DynamoStorage;
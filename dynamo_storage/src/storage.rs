@@ -1,28 +1,43 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use aws_sdk_dynamodb::{
-    types::{AttributeValue, Put, TransactWriteItem, Update},
+    error::SdkError,
+    operation::{
+        describe_table::DescribeTableError, put_item::PutItemError,
+        transact_write_items::TransactWriteItemsError,
+    },
+    types::{AttributeValue, Put, TableStatus, TransactWriteItem, Update},
     Client,
 };
 use base::{
-    model::{Account, Transaction},
-    storage::Storage,
+    dto::search::{SearchCursor, TransactionSearchDTO, SEARCH_PARTITION_REQUIRED},
+    model::{Account, AccountUpdate, Details, Transaction},
+    storage::{AccountCreation, Storage, IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT},
 };
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::entity::AccountEntity;
+use crate::entity::{get_uuid, AccountEntity, TransactionEntity};
 
-const TABLE_NAME: &str = "mini_ledger";
+const SEARCH_PAGE_SIZE: i32 = 500;
 
 #[derive(Debug, Clone)]
 pub struct DynamoStorage {
     client: Arc<Client>,
+    table: String,
 }
 
 impl DynamoStorage {
-    pub fn new(client: Arc<Client>) -> Self {
-        DynamoStorage { client }
+    /// Reads and writes `table`, as created by `utils::create_table`.
+    pub fn new(client: Arc<Client>, table: &str) -> Self {
+        DynamoStorage {
+            client,
+            table: table.to_string(),
+        }
     }
 
     fn create_attr_value<T: ToString>(value: &T) -> AttributeValue {
@@ -33,40 +48,239 @@ impl DynamoStorage {
         AttributeValue::N(value.to_string())
     }
 
-    fn format_pk(prefix: &str, id: &Uuid) -> String {
+    /// Optional fields are omitted rather than stored as empty attributes.
+    fn details_attrs(details: &Details) -> HashMap<String, AttributeValue> {
+        let mut attrs = HashMap::new();
+        if let Some(description) = &details.description {
+            attrs.insert(
+                "description".to_string(),
+                Self::create_attr_value(description),
+            );
+        }
+        if let Some(reference) = &details.external_reference {
+            attrs.insert(
+                "external_reference".to_string(),
+                Self::create_attr_value(reference),
+            );
+        }
+        let metadata = details
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), Self::create_attr_value(value)))
+            .collect();
+        attrs.insert("metadata".to_string(), AttributeValue::M(metadata));
+        attrs
+    }
+
+    /// Positions of the items whose condition cancelled the transaction.
+    fn failed_conditions<R>(error: &SdkError<TransactWriteItemsError, R>) -> Vec<usize> {
+        match error.as_service_error() {
+            Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) => cancelled
+                .cancellation_reasons()
+                .iter()
+                .enumerate()
+                .filter(|(_, reason)| reason.code() == Some("ConditionalCheckFailed"))
+                .map(|(index, _)| index)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Idempotency keys are recorded per account, as items naming the
+    /// operation that used them.
+    fn idempotency_key(account_id: &Uuid, key: &str) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                "pk".to_string(),
+                AttributeValue::S(Self::format_pk("idem#", account_id)),
+            ),
+            ("sk".to_string(), AttributeValue::S(key.to_string())),
+        ])
+    }
+
+    fn idempotency_put(&self, tx: &Transaction) -> Result<TransactWriteItem, String> {
+        let put = Put::builder()
+            .table_name(&self.table)
+            .set_item(Some(Self::idempotency_key(
+                &tx.account_id,
+                &tx.idempotency_key,
+            )))
+            .item("operation_id", Self::create_attr_value(&tx.operation_id))
+            .condition_expression("attribute_not_exists(pk)")
+            .build()
+            .map_err(|e| format!("Failed to build put expression: {:?}", e))?;
+        Ok(TransactWriteItem::builder().put(put).build())
+    }
+
+    /// The operation that already recorded the idempotency keys of
+    /// `created_transactions`, when it is the same operation being replayed.
+    async fn replayed_operation(
+        &self,
+        created_transactions: &[Transaction],
+    ) -> Result<Option<Vec<Transaction>>, String> {
+        let mut recorded = BTreeSet::new();
+        for tx in created_transactions {
+            recorded.extend(
+                self.get_idempotency_key(tx.account_id, &tx.idempotency_key)
+                    .await?,
+            );
+        }
+        let Some(operation_id) = recorded.first() else {
+            return Ok(None);
+        };
+        let operation = self.get_operation_transactions(*operation_id).await?;
+        if recorded.len() == 1 && operation.len() == created_transactions.len() {
+            Ok(Some(operation))
+        } else {
+            Err(IDEMPOTENCY_KEY_REUSED.to_string())
+        }
+    }
+
+    pub(crate) fn format_pk(prefix: &str, id: &Uuid) -> String {
         format!("{}{}", prefix, id)
     }
+
+    /// Transaction sort keys lead with the zero-padded creation time so that
+    /// an account's history can be range-queried in chronological order.
+    pub(crate) fn format_tx_sk(created_at_in_nanos: i64, id: &Uuid) -> String {
+        format!("tx#{:020}#{}", created_at_in_nanos, id)
+    }
+
+    /// Lookup items copy the transaction under these partitions, with the
+    /// same sort key as the account history item.
+    fn format_reference_pk(reference: &str) -> String {
+        format!("tx#ref#{}", reference)
+    }
+
+    /// Metadata keys cannot contain '=', so the pair is unambiguous.
+    fn format_metadata_pk(key: &str, value: &str) -> String {
+        format!("tx#meta#{}={}", key, value)
+    }
+
+    fn search_pk(query: &TransactionSearchDTO) -> Result<String, String> {
+        if let Some(reference) = &query.external_reference {
+            return Ok(Self::format_reference_pk(reference));
+        }
+        if let Some((key, value)) = query.metadata() {
+            return Ok(Self::format_metadata_pk(key, value));
+        }
+        if let Some(account_id) = &query.account_id {
+            return Ok(Self::format_pk("tx#acc#", account_id));
+        }
+        Err(SEARCH_PARTITION_REQUIRED.to_string())
+    }
+
+    /// Reads every transaction item of a lookup partition, oldest first.
+    async fn query_transactions(&self, pk: String) -> Result<Vec<Transaction>, String> {
+        let results = self
+            .client
+            .query()
+            .table_name(&self.table)
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", "pk")
+            .expression_attribute_values(":pk", AttributeValue::S(pk))
+            .scan_index_forward(true)
+            .send()
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+
+        results
+            .items
+            .unwrap_or_default()
+            .iter()
+            .map(|item| TransactionEntity::try_from(item).map(|entity| (&entity).into()))
+            .collect()
+    }
+
+    fn transaction_put(&self, pk: String, tx: &Transaction) -> Result<TransactWriteItem, String> {
+        let created_at_in_nanos = tx.created_at.timestamp_nanos_opt().unwrap();
+        let sk = Self::format_tx_sk(created_at_in_nanos, &tx.id);
+        let mut put = Put::builder()
+            .table_name(&self.table)
+            .item("pk", AttributeValue::S(pk))
+            .item("sk", AttributeValue::S(sk))
+            .item("account_id", Self::create_attr_value(&tx.account_id))
+            .item(
+                "account_version",
+                Self::create_attr_value(&tx.account_version),
+            )
+            .item("amount", Self::create_number_attr(&tx.amount.with_prec(32)))
+            .item(
+                "balance_after",
+                Self::create_number_attr(&tx.balance_after.with_prec(32)),
+            )
+            .item(
+                "created_at_in_nanos",
+                Self::create_number_attr(&created_at_in_nanos),
+            )
+            .item("currency", Self::create_attr_value(&tx.currency))
+            .item("id", Self::create_attr_value(&tx.id))
+            .item("operation_id", Self::create_attr_value(&tx.operation_id))
+            .item("kind", Self::create_attr_value(&tx.kind))
+            .item("status", Self::create_attr_value(&tx.status))
+            .item(
+                "idempotency_key",
+                Self::create_attr_value(&tx.idempotency_key),
+            );
+        if let Some(counterparty) = &tx.counterparty_account_id {
+            put = put.item(
+                "counterparty_account_id",
+                Self::create_attr_value(counterparty),
+            );
+        }
+        for (name, value) in Self::details_attrs(&tx.details) {
+            put = put.item(name, value);
+        }
+        let put = put
+            .build()
+            .map_err(|e| format!("Failed to build put expression: {:?}", e))?;
+        Ok(TransactWriteItem::builder().put(put).build())
+    }
 }
 
 #[async_trait]
 impl Storage for DynamoStorage {
-    async fn save_account(&self, account: Account) -> Result<(), String> {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
         let pk = Self::format_pk("acc#", &account.uuid);
-        let request = self
+        let mut request = self
             .client
             .put_item()
-            .table_name(TABLE_NAME)
+            .table_name(&self.table)
             .item("pk", Self::create_attr_value(&pk))
             .item("sk", Self::create_attr_value(&pk))
             .item("uuid", Self::create_attr_value(&account.uuid))
             .item("currency", Self::create_attr_value(&account.currency))
             .item("balance", Self::create_number_attr(&account.balance))
             .item(
-                "created_at_in_millis",
-                Self::create_number_attr(&account.created_at.timestamp_millis()),
+                "created_at_in_nanos",
+                Self::create_number_attr(&account.created_at.timestamp_nanos_opt().unwrap()),
             )
             .item(
-                "last_updated_at_in_millis",
-                Self::create_number_attr(&account.last_updated_at.timestamp_millis()),
+                "last_updated_at_in_nanos",
+                Self::create_number_attr(&account.last_updated_at.timestamp_nanos_opt().unwrap()),
             )
-            .item("version", Self::create_attr_value(&account.version));
-
-        request
-            .send()
-            .await
-            .map_err(|e| format!("Failed to save account: {:?}", e))?;
+            .item("version", Self::create_attr_value(&account.version))
+            .condition_expression("attribute_not_exists(pk)");
+        for (name, value) in Self::details_attrs(&account.details) {
+            request = request.item(name, value);
+        }
 
-        Ok(())
+        match request.send().await {
+            Ok(_) => Ok(AccountCreation::Created),
+            Err(e)
+                if matches!(
+                    e.as_service_error(),
+                    Some(PutItemError::ConditionalCheckFailedException(_))
+                ) =>
+            {
+                let stored = self
+                    .get_account(account.uuid)
+                    .await?
+                    .ok_or("Account not found after a conflicting insert")?;
+                Ok(AccountCreation::AlreadyExists(stored))
+            }
+            Err(e) => Err(format!("Failed to save account: {:?}", e)),
+        }
     }
 
     async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
@@ -75,7 +289,7 @@ impl Storage for DynamoStorage {
         let results = self
             .client
             .query()
-            .table_name(TABLE_NAME)
+            .table_name(&self.table)
             .key_condition_expression("#pk = :pk")
             .expression_attribute_names("#pk", "pk")
             .expression_attribute_values(":pk", AttributeValue::S(pk))
@@ -83,68 +297,67 @@ impl Storage for DynamoStorage {
             .await
             .map_err(|e| format!("Failed to get account: {:?}", e))?;
 
-        if let Some(items) = results.items {
-            let accounts: Vec<AccountEntity> = items.iter().map(|v| v.into()).collect();
-            let account: Account = accounts.first().ok_or("Account not found")?.into();
-            Ok(Some(account))
-        } else {
-            Ok(None)
-        }
+        results
+            .items
+            .unwrap_or_default()
+            .first()
+            .map(|item| AccountEntity::try_from(item).map(|entity| (&entity).into()))
+            .transpose()
     }
 
     async fn save_transactions(
         &self,
         created_transactions: Vec<Transaction>,
-        updated_accounts: Vec<Account>,
+        updated_accounts: Vec<AccountUpdate>,
     ) -> Result<Vec<Transaction>, String> {
         let mut builder = self.client.transact_write_items();
 
+        // Key items come first, so their positions tell a reused key apart
+        // from a stale account when the transaction is cancelled.
         for tx in &created_transactions {
-            let pk = Self::format_pk("tx#acc#", &tx.account_id);
-            let sk = Self::format_pk("tx#", &tx.id);
-            let put = Put::builder()
-                .table_name(TABLE_NAME)
-                .item("pk", AttributeValue::S(pk))
-                .item("sk", AttributeValue::S(sk))
-                .item("account_id", Self::create_attr_value(&tx.account_id))
-                .item(
-                    "account_version",
-                    Self::create_attr_value(&tx.account_version),
-                )
-                .item("amount", Self::create_number_attr(&tx.amount.with_prec(32)))
-                .item(
-                    "created_at_in_millis",
-                    Self::create_number_attr(&tx.created_at.timestamp_millis()),
-                )
-                .item("currency", Self::create_attr_value(&tx.currency))
-                .item("id", Self::create_attr_value(&tx.id))
-                .item(
-                    "idempotency_key",
-                    Self::create_attr_value(&tx.idempotency_key),
-                )
-                .build()
-                .map_err(|e| format!("Failed to build put expression: {:?}", e))?;
-            let op = TransactWriteItem::builder().put(put).build();
-            builder = builder.transact_items(op);
+            builder = builder.transact_items(self.idempotency_put(tx)?);
+        }
+        for tx in &created_transactions {
+            let mut pks = vec![
+                Self::format_pk("tx#acc#", &tx.account_id),
+                Self::format_pk("tx#id#", &tx.id),
+                Self::format_pk("tx#op#", &tx.operation_id),
+            ];
+            if let Some(reference) = &tx.details.external_reference {
+                pks.push(Self::format_reference_pk(reference));
+            }
+            for (key, value) in &tx.details.metadata {
+                pks.push(Self::format_metadata_pk(key, value));
+            }
+            for pk in pks {
+                builder = builder.transact_items(self.transaction_put(pk, tx)?);
+            }
         }
 
-        for acc in &updated_accounts {
+        for account_update in &updated_accounts {
+            let acc = &account_update.account;
             let pk = Self::format_pk("acc#", &acc.uuid);
-            let new_version = Uuid::new_v4();
             let update = Update::builder()
-                .table_name(TABLE_NAME)
+                .table_name(&self.table)
                 .key("pk", Self::create_attr_value(&pk))
                 .key("sk", Self::create_attr_value(&pk))
-                .update_expression("SET balance = :balance, version = :newVersion")
+                .update_expression(
+                    "SET balance = :balance, version = :newVersion, \
+                    last_updated_at_in_nanos = :lastUpdatedAt",
+                )
                 .condition_expression("version = :expectedVersion")
                 .expression_attribute_values(
                     ":balance",
                     Self::create_number_attr(&acc.balance.with_prec(32)),
                 )
-                .expression_attribute_values(":newVersion", Self::create_attr_value(&new_version))
+                .expression_attribute_values(":newVersion", Self::create_attr_value(&acc.version))
+                .expression_attribute_values(
+                    ":lastUpdatedAt",
+                    Self::create_number_attr(&acc.last_updated_at.timestamp_nanos_opt().unwrap()),
+                )
                 .expression_attribute_values(
                     ":expectedVersion",
-                    Self::create_attr_value(&acc.version),
+                    Self::create_attr_value(&account_update.expected_version),
                 )
                 .build()
                 .map_err(|e| format!("Failed to build update expression: {:?}", e))?;
@@ -152,11 +365,313 @@ impl Storage for DynamoStorage {
             builder = builder.transact_items(op);
         }
 
-        builder
+        if let Err(e) = builder.send().await {
+            let failed = Self::failed_conditions(&e);
+            if failed
+                .iter()
+                .any(|index| *index < created_transactions.len())
+            {
+                return self
+                    .replayed_operation(&created_transactions)
+                    .await?
+                    .ok_or(IDEMPOTENCY_KEY_REUSED.to_string());
+            }
+            if !failed.is_empty() {
+                return Err(VERSION_CONFLICT.to_string());
+            }
+            return Err(format!("Failed to save transactions: {:?}", e));
+        }
+
+        Ok(created_transactions)
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let pk = Self::format_pk("tx#acc#", &account_id);
+        let lower = format!("tx#{:020}", from.timestamp_nanos_opt().unwrap());
+        let upper = format!("tx#{:020}#~", to.timestamp_nanos_opt().unwrap() - 1);
+
+        // A query page stops at `limit` items or 1 MB, whichever comes first.
+        let mut transactions = Vec::new();
+        let mut start_key = None;
+        while transactions.len() < limit {
+            let results = self
+                .client
+                .query()
+                .table_name(&self.table)
+                .key_condition_expression("#pk = :pk AND #sk BETWEEN :lower AND :upper")
+                .expression_attribute_names("#pk", "pk")
+                .expression_attribute_names("#sk", "sk")
+                .expression_attribute_values(":pk", AttributeValue::S(pk.clone()))
+                .expression_attribute_values(":lower", AttributeValue::S(lower.clone()))
+                .expression_attribute_values(":upper", AttributeValue::S(upper.clone()))
+                .scan_index_forward(true)
+                .limit((limit - transactions.len()) as i32)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to get transactions: {:?}", e))?;
+
+            for item in results.items.unwrap_or_default().iter() {
+                transactions.push((&TransactionEntity::try_from(item)?).into());
+            }
+            start_key = results.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+        Ok(transactions)
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        let transactions = self
+            .query_transactions(Self::format_pk("tx#id#", &id))
+            .await
+            .map_err(|e| format!("Failed to get transaction: {}", e))?;
+        Ok(transactions.into_iter().next())
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        self.query_transactions(Self::format_pk("tx#op#", &operation_id))
+            .await
+            .map_err(|e| format!("Failed to get operation transactions: {}", e))
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        let result = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .set_key(Some(Self::idempotency_key(&account_id, idempotency_key)))
+            .consistent_read(true)
             .send()
             .await
-            .map_err(|e| format!("Failed to save transactions: {:?}", e))?;
+            .map_err(|e| format!("Failed to look up idempotency key: {:?}", e))?;
+        result
+            .item
+            .map(|item| get_uuid(&item, "operation_id"))
+            .transpose()
+    }
 
-        Ok(created_transactions)
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let pk = Self::search_pk(query)?;
+        let mut lower = match query.from {
+            Some(from) => format!("tx#{:020}", from.timestamp_nanos_opt().unwrap()),
+            None => "tx#".to_string(),
+        };
+        if let Some(cursor) = after {
+            // '~' sorts after every character of a sort key, so this excludes
+            // the cursor item itself.
+            let after_cursor = format!(
+                "{}~",
+                Self::format_tx_sk(cursor.created_at_in_nanos, &cursor.id)
+            );
+            lower = lower.max(after_cursor);
+        }
+        let upper = match query.to {
+            Some(to) => format!("tx#{:020}#~", to.timestamp_nanos_opt().unwrap() - 1),
+            None => "tx#~".to_string(),
+        };
+
+        let mut transactions = Vec::new();
+        let mut start_key = None;
+        loop {
+            let results = self
+                .client
+                .query()
+                .table_name(&self.table)
+                .key_condition_expression("#pk = :pk AND #sk BETWEEN :lower AND :upper")
+                .expression_attribute_names("#pk", "pk")
+                .expression_attribute_names("#sk", "sk")
+                .expression_attribute_values(":pk", AttributeValue::S(pk.clone()))
+                .expression_attribute_values(":lower", AttributeValue::S(lower.clone()))
+                .expression_attribute_values(":upper", AttributeValue::S(upper.clone()))
+                .scan_index_forward(true)
+                .limit(SEARCH_PAGE_SIZE)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to search transactions: {:?}", e))?;
+
+            for item in results.items.unwrap_or_default().iter() {
+                let transaction: Transaction = (&TransactionEntity::try_from(item)?).into();
+                if query.matches(&transaction) {
+                    transactions.push(transaction);
+                    if transactions.len() == limit {
+                        return Ok(transactions);
+                    }
+                }
+            }
+            start_key = results.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(transactions);
+            }
+        }
+    }
+
+    /// The table counts as the schema: it must exist and be active.
+    async fn health_check(&self) -> Result<(), String> {
+        let output = self
+            .client
+            .describe_table()
+            .table_name(&self.table)
+            .send()
+            .await
+            .map_err(|e| match e.as_service_error() {
+                Some(DescribeTableError::ResourceNotFoundException(_)) => {
+                    format!("DynamoDB table {} does not exist yet", self.table)
+                }
+                _ => format!("DynamoDB is unreachable: {:?}", e),
+            })?;
+        match output.table.and_then(|table| table.table_status) {
+            Some(TableStatus::Active) | Some(TableStatus::Updating) => Ok(()),
+            status => Err(format!(
+                "DynamoDB table {} is not active: {:?}",
+                self.table, status
+            )),
+        }
+    }
+
+    /// A no-op: every write is a request that completes before its call
+    /// returns, and the SDK client has no session to end or close method.
+    /// Its pooled HTTP connections are released once the storage is dropped
+    /// after this call.
+    async fn close(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base::model::{OperationKind, TransactionStatus};
+    use bigdecimal::BigDecimal;
+    use chrono::{Duration, TimeZone};
+
+    use crate::{
+        utils::{connect, create_table, migrate},
+        DynamoConfig,
+    };
+
+    use super::*;
+
+    /// Runs against DynamoDB Local on `localhost:8000`, e.g. after
+    /// `docker compose up dynamodb-local`, with
+    /// `cargo test -p dynamo_storage -- --ignored`.
+    #[tokio::test]
+    #[ignore = "requires DynamoDB Local on localhost:8000"]
+    async fn test_conformance() {
+        let config = DynamoConfig::default();
+        let client = connect(&config).await.unwrap();
+        // The table outlives the test; creating it again fails harmlessly.
+        let _ = create_table(&client, &config.table).await;
+        let storage = DynamoStorage::new(Arc::new(client), &config.table);
+        base::storage::conformance::run(&storage).await;
+    }
+
+    /// Items written before statements were keyed `tx#<id>`, with a
+    /// millisecond timestamp and only the attributes below; after `migrate`
+    /// they read like any other transaction and their keys are claimed.
+    #[tokio::test]
+    #[ignore = "requires DynamoDB Local on localhost:8000"]
+    async fn test_migrates_legacy_transactions() {
+        let config = DynamoConfig::default();
+        let client = connect(&config).await.unwrap();
+        migrate(&client, &config.table).await.unwrap();
+        let storage = DynamoStorage::new(Arc::new(client.clone()), &config.table);
+
+        let account_id = Uuid::new_v4();
+        let created_at = Utc.timestamp_millis_opt(1_700_000_000_123).unwrap();
+        let legacy = [
+            (Uuid::new_v4(), "10", created_at),
+            (Uuid::new_v4(), "-3", created_at + Duration::milliseconds(1)),
+        ];
+        for (id, amount, created_at) in legacy {
+            client
+                .put_item()
+                .table_name(&config.table)
+                .item("pk", AttributeValue::S(format!("tx#acc#{}", account_id)))
+                .item("sk", AttributeValue::S(format!("tx#{}", id)))
+                .item("id", AttributeValue::S(id.to_string()))
+                .item("idempotency_key", AttributeValue::S(id.to_string()))
+                .item(
+                    "account_version",
+                    AttributeValue::S(Uuid::new_v4().to_string()),
+                )
+                .item("account_id", AttributeValue::S(account_id.to_string()))
+                .item("amount", AttributeValue::N(amount.to_string()))
+                .item(
+                    "created_at_in_millis",
+                    AttributeValue::N(created_at.timestamp_millis().to_string()),
+                )
+                .item("currency", AttributeValue::S("BRL".to_string()))
+                .send()
+                .await
+                .unwrap();
+        }
+
+        migrate(&client, &config.table).await.unwrap();
+        migrate(&client, &config.table).await.unwrap();
+        let found = storage
+            .get_transactions(
+                account_id,
+                created_at,
+                created_at + Duration::seconds(1),
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].id, legacy[0].0);
+        assert_eq!(found[0].created_at, created_at);
+        assert_eq!(found[0].operation_id, legacy[0].0);
+        assert_eq!(found[0].kind, OperationKind::Deposit);
+        assert_eq!(found[0].status, TransactionStatus::Posted);
+        assert_eq!(found[0].balance_after, BigDecimal::from(10));
+        assert_eq!(found[1].kind, OperationKind::Withdrawal);
+        assert_eq!(found[1].balance_after, BigDecimal::from(7));
+
+        let by_id = storage.get_transaction(legacy[1].0).await.unwrap().unwrap();
+        assert_eq!(by_id.amount, BigDecimal::from(-3));
+        let operation = storage
+            .get_operation_transactions(legacy[1].0)
+            .await
+            .unwrap();
+        assert_eq!(operation.len(), 1);
+
+        // Replaying a legacy key returns the migrated transaction.
+        let account = Account::new(account_id, &"BRL".to_string());
+        storage.save_account(account.clone()).await.unwrap();
+        let replay = Transaction::new(
+            &account,
+            &legacy[0].0.to_string(),
+            &BigDecimal::from(10),
+            OperationKind::Deposit,
+        );
+        let saved = storage
+            .save_transactions(vec![replay.clone()], vec![account.apply(&replay)])
+            .await
+            .unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].id, legacy[0].0);
+        assert!(storage.get_transaction(replay.id).await.unwrap().is_none());
+        let stored = storage.get_account(account_id).await.unwrap().unwrap();
+        assert_eq!(stored, account);
     }
 }
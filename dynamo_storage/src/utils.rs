@@ -1,26 +1,41 @@
-use aws_config::meta::region::RegionProviderChain;
+use std::collections::{BTreeSet, HashMap};
+
+use aws_config::Region;
 use aws_sdk_dynamodb::{
-    operation::create_table::CreateTableOutput,
+    operation::{create_table::CreateTableOutput, describe_table::DescribeTableError},
     types::{
-        AttributeDefinition, KeySchemaElement, KeyType, ProvisionedThroughput, ScalarAttributeType,
+        AttributeDefinition, AttributeValue, Delete, KeySchemaElement, KeyType,
+        ProvisionedThroughput, Put, ScalarAttributeType, TransactWriteItem,
     },
     Client,
 };
 
-pub async fn connect() -> Result<aws_sdk_dynamodb::Client, String> {
-    let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
-        .test_credentials()
-        .endpoint_url("http://localhost:8000")
-        .region(RegionProviderChain::default_provider().or_else("us-east-1"))
-        .load()
-        .await;
+use base::model::{OperationKind, TransactionStatus};
+use bigdecimal::{BigDecimal, Zero};
+
+use crate::{
+    entity::{get_bigdecimal, get_nanos, get_string, get_uuid},
+    DynamoConfig, DynamoStorage,
+};
+
+/// With an `endpoint`, connects to it using test credentials, as DynamoDB
+/// Local expects. Without one, uses the regional AWS endpoint and the default
+/// credential chain.
+pub async fn connect(config: &DynamoConfig) -> Result<aws_sdk_dynamodb::Client, String> {
+    let loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .region(Region::new(config.region.clone()));
+    let loader = match &config.endpoint {
+        Some(endpoint) => loader.test_credentials().endpoint_url(endpoint),
+        None => loader,
+    };
+    let config = loader.load().await;
 
     let dynamodb_local_config = aws_sdk_dynamodb::config::Builder::from(&config).build();
     let client = aws_sdk_dynamodb::Client::from_conf(dynamodb_local_config);
     Ok(client)
 }
 
-pub async fn create_table(client: &Client) -> Result<CreateTableOutput, String> {
+pub async fn create_table(client: &Client, table: &str) -> Result<CreateTableOutput, String> {
     let attribute_definitions = vec![
         AttributeDefinition::builder()
             .attribute_name("pk")
@@ -55,7 +70,7 @@ pub async fn create_table(client: &Client) -> Result<CreateTableOutput, String>
 
     return client
         .create_table()
-        .table_name("mini_ledger")
+        .table_name(table)
         .set_attribute_definitions(Some(attribute_definitions))
         .set_key_schema(Some(key_schema))
         .provisioned_throughput(pt)
@@ -63,3 +78,195 @@ pub async fn create_table(client: &Client) -> Result<CreateTableOutput, String>
         .await
         .map_err(|e| format!("Failed to create table: {:?}", e));
 }
+
+/// Creates `table` unless it exists, then brings items written by older
+/// versions up to the current format. Safe to run again.
+pub async fn migrate(client: &Client, table: &str) -> Result<(), String> {
+    let described = client.describe_table().table_name(table).send().await;
+    match described {
+        Ok(_) => {}
+        Err(e) => match e.as_service_error() {
+            Some(DescribeTableError::ResourceNotFoundException(_)) => {
+                create_table(client, table).await?;
+            }
+            _ => return Err(format!("Failed to describe table: {:?}", e)),
+        },
+    }
+    migrate_transactions(client, table).await?;
+    Ok(())
+}
+
+/// Account history items written before statements are keyed `tx#<id>`,
+/// carry `created_at_in_millis` and lack `operation_id`, `kind`, `status`
+/// and `balance_after`. Every account holding such items has its history
+/// rewritten. Returns how many items were migrated.
+pub async fn migrate_transactions(client: &Client, table: &str) -> Result<usize, String> {
+    let mut accounts = BTreeSet::new();
+    let mut start_key = None;
+    loop {
+        let page = client
+            .scan()
+            .table_name(table)
+            .filter_expression(
+                "begins_with(pk, :history) AND attribute_exists(created_at_in_millis)",
+            )
+            .expression_attribute_values(":history", AttributeValue::S("tx#acc#".to_string()))
+            .projection_expression("pk")
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to scan transactions: {:?}", e))?;
+        for item in page.items.unwrap_or_default() {
+            accounts.insert(get_string(&item, "pk"));
+        }
+        start_key = page.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+
+    let mut migrated = 0;
+    for pk in accounts {
+        migrated += migrate_account_transactions(client, table, &pk).await?;
+    }
+    Ok(migrated)
+}
+
+/// Legacy items get `balance_after` from the running sum of the account's
+/// history, so the whole partition is read, oldest first, even when part of
+/// it was migrated already.
+async fn migrate_account_transactions(
+    client: &Client,
+    table: &str,
+    pk: &str,
+) -> Result<usize, String> {
+    let mut items = Vec::new();
+    let mut start_key = None;
+    loop {
+        let page = client
+            .query()
+            .table_name(table)
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", "pk")
+            .expression_attribute_values(":pk", AttributeValue::S(pk.to_string()))
+            .consistent_read(true)
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to read account history: {:?}", e))?;
+        items.extend(page.items.unwrap_or_default());
+        start_key = page.last_evaluated_key;
+        if start_key.is_none() {
+            break;
+        }
+    }
+    items.sort_by_key(|item| (get_nanos(item, "created_at"), get_string(item, "id")));
+
+    let mut balance = BigDecimal::zero();
+    let mut migrated = 0;
+    for item in items {
+        let amount = get_bigdecimal(&item, "amount")?;
+        balance += &amount;
+        if item.contains_key("created_at_in_millis") {
+            migrate_transaction(client, table, item, &amount, &balance).await?;
+            migrated += 1;
+        }
+    }
+    Ok(migrated)
+}
+
+/// Writes the item under its new key, with the attributes it was missing,
+/// copies for the id and operation lookups and the `idem#` item that claims
+/// its idempotency key, and deletes the old one in a single transaction, so
+/// an interrupted migration never leaves both or neither. The claim is only
+/// written where none exists, so a key used again since the upgrade fails
+/// the migration rather than pointing at two operations. Legacy items are
+/// single-leg operations, told apart by the sign of their amount.
+async fn migrate_transaction(
+    client: &Client,
+    table: &str,
+    mut item: HashMap<String, AttributeValue>,
+    amount: &BigDecimal,
+    balance_after: &BigDecimal,
+) -> Result<(), String> {
+    let id = get_uuid(&item, "id")?;
+    let created_at_in_nanos = get_nanos(&item, "created_at");
+    let legacy_key: HashMap<String, AttributeValue> = ["pk", "sk"]
+        .into_iter()
+        .filter_map(|name| Some((name.to_string(), item.get(name)?.clone())))
+        .collect();
+    let kind = if amount < &BigDecimal::zero() {
+        OperationKind::Withdrawal
+    } else {
+        OperationKind::Deposit
+    };
+
+    item.remove("created_at_in_millis");
+    item.insert(
+        "created_at_in_nanos".to_string(),
+        AttributeValue::N(created_at_in_nanos.to_string()),
+    );
+    item.insert(
+        "sk".to_string(),
+        AttributeValue::S(DynamoStorage::format_tx_sk(created_at_in_nanos, &id)),
+    );
+    item.entry("operation_id".to_string())
+        .or_insert_with(|| AttributeValue::S(id.to_string()));
+    item.entry("kind".to_string())
+        .or_insert_with(|| AttributeValue::S(kind.to_string()));
+    item.entry("status".to_string())
+        .or_insert_with(|| AttributeValue::S(TransactionStatus::Posted.to_string()));
+    item.insert(
+        "balance_after".to_string(),
+        AttributeValue::N(balance_after.with_prec(32).to_string()),
+    );
+
+    let account_id = get_uuid(&item, "account_id")?;
+    let claim = Put::builder()
+        .table_name(table)
+        .item(
+            "pk",
+            AttributeValue::S(DynamoStorage::format_pk("idem#", &account_id)),
+        )
+        .item(
+            "sk",
+            AttributeValue::S(get_string(&item, "idempotency_key")),
+        )
+        .item("operation_id", item["operation_id"].clone())
+        .condition_expression("attribute_not_exists(pk)")
+        .build()
+        .map_err(|e| format!("Failed to build put expression: {:?}", e))?;
+
+    let mut builder = client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().put(claim).build());
+    for prefix in ["tx#id#", "tx#op#"] {
+        let mut copy = item.clone();
+        copy.insert(
+            "pk".to_string(),
+            AttributeValue::S(DynamoStorage::format_pk(prefix, &id)),
+        );
+        builder = builder.transact_items(put(table, copy)?);
+    }
+    builder = builder.transact_items(put(table, item)?);
+    let delete = Delete::builder()
+        .table_name(table)
+        .set_key(Some(legacy_key))
+        .build()
+        .map_err(|e| format!("Failed to build delete expression: {:?}", e))?;
+    builder
+        .transact_items(TransactWriteItem::builder().delete(delete).build())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to migrate transaction {}: {:?}", id, e))?;
+    Ok(())
+}
+
+fn put(table: &str, item: HashMap<String, AttributeValue>) -> Result<TransactWriteItem, String> {
+    let put = Put::builder()
+        .table_name(table)
+        .set_item(Some(item))
+        .build()
+        .map_err(|e| format!("Failed to build put expression: {:?}", e))?;
+    Ok(TransactWriteItem::builder().put(put).build())
+}
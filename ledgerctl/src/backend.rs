@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use base::storage::{wal::DEFAULT_COMPACT_AFTER_BYTES, InMemoryStorage, WalConfig, WalStorage};
+use clap::ValueEnum;
+use futures::FutureExt;
+
+use crate::client::{DirectClient, MigrateFn};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Backend {
+    Memory,
+    Cassandra,
+    Dynamo,
+    Postgres,
+    Sqlite,
+    Wal,
+}
+
+pub async fn connect(backend: Backend) -> Result<DirectClient, String> {
+    match backend {
+        Backend::Memory => {
+            let storage = Arc::new(InMemoryStorage::new());
+            let migrate: MigrateFn = Box::new(|| async { Ok(()) }.boxed());
+            Ok(DirectClient::new(&storage, migrate))
+        }
+        Backend::Cassandra => connect_cassandra().await,
+        Backend::Dynamo => connect_dynamo().await,
+        Backend::Postgres => connect_postgres().await,
+        Backend::Sqlite => connect_sqlite().await,
+        Backend::Wal => connect_wal().await,
+    }
+}
+
+async fn connect_wal() -> Result<DirectClient, String> {
+    let dir = std::env::var("WAL_DIR").map_err(|_| "WAL_DIR must be set".to_string())?;
+    let storage = WalStorage::open(WalConfig {
+        dir: dir.into(),
+        compact_after_bytes: DEFAULT_COMPACT_AFTER_BYTES,
+    })
+    .await?;
+    let storage = Arc::new(storage);
+    let migrate: MigrateFn = Box::new(|| async { Ok(()) }.boxed());
+    Ok(DirectClient::new(&storage, migrate))
+}
+
+#[cfg(feature = "cassandra_storage")]
+async fn connect_cassandra() -> Result<DirectClient, String> {
+    use cassandra_storage::{CassandraConfig, CassandraStorage, DEFAULT_KEYSPACE};
+
+    let contact_points = std::env::var("CASSANDRA_CONTACT_POINTS")
+        .map_err(|_| "CASSANDRA_CONTACT_POINTS must be set".to_string())?;
+    let keyspace =
+        std::env::var("CASSANDRA_KEYSPACE").unwrap_or_else(|_| DEFAULT_KEYSPACE.to_string());
+    let config = CassandraConfig {
+        contact_points,
+        keyspace,
+    };
+    let session = cassandra_storage::connect(&config)
+        .await
+        .map_err(|err| format!("Failed to connect to Cassandra: {}", err))?;
+    let session = Arc::new(session);
+
+    let storage = Arc::new(CassandraStorage::new(
+        Arc::clone(&session),
+        &config.keyspace,
+    ));
+    let migrate: MigrateFn = Box::new(move || {
+        let session = Arc::clone(&session);
+        let keyspace = config.keyspace.clone();
+        async move { cassandra_storage::migrate(&session, &keyspace).await }.boxed()
+    });
+    Ok(DirectClient::new(&storage, migrate))
+}
+
+#[cfg(not(feature = "cassandra_storage"))]
+async fn connect_cassandra() -> Result<DirectClient, String> {
+    Err("ledgerctl was built without the cassandra_storage feature".to_string())
+}
+
+#[cfg(feature = "dynamo_storage")]
+async fn connect_dynamo() -> Result<DirectClient, String> {
+    use dynamo_storage::{DynamoConfig, DynamoStorage};
+
+    let default = DynamoConfig::default();
+    let config = DynamoConfig {
+        endpoint: std::env::var("DYNAMO_ENDPOINT").ok().or(default.endpoint),
+        region: std::env::var("DYNAMO_REGION").unwrap_or(default.region),
+        table: std::env::var("DYNAMO_TABLE").unwrap_or(default.table),
+    };
+    let client = dynamo_storage::utils::connect(&config)
+        .await
+        .map_err(|err| format!("Failed to connect to DynamoDB: {}", err))?;
+    let client = Arc::new(client);
+
+    let storage = Arc::new(DynamoStorage::new(Arc::clone(&client), &config.table));
+    let migrate: MigrateFn = Box::new(move || {
+        let client = Arc::clone(&client);
+        let table = config.table.clone();
+        async move { dynamo_storage::utils::migrate(&client, &table).await }.boxed()
+    });
+    Ok(DirectClient::new(&storage, migrate))
+}
+
+#[cfg(not(feature = "dynamo_storage"))]
+async fn connect_dynamo() -> Result<DirectClient, String> {
+    Err("ledgerctl was built without the dynamo_storage feature".to_string())
+}
+
+#[cfg(feature = "postgres_storage")]
+async fn connect_postgres() -> Result<DirectClient, String> {
+    use postgres_storage::{PostgresConfig, PostgresStorage};
+
+    let url = std::env::var("POSTGRES_URL").map_err(|_| "POSTGRES_URL must be set".to_string())?;
+    let pool = postgres_storage::connect(PostgresConfig {
+        url,
+        max_connections: 4,
+    })
+    .await
+    .map_err(|err| format!("Failed to connect to Postgres: {}", err))?;
+
+    let storage = Arc::new(PostgresStorage::new(pool.clone()));
+    let migrate: MigrateFn = Box::new(move || {
+        let pool = pool.clone();
+        async move { postgres_storage::migrate(&pool).await }.boxed()
+    });
+    Ok(DirectClient::new(&storage, migrate))
+}
+
+#[cfg(not(feature = "postgres_storage"))]
+async fn connect_postgres() -> Result<DirectClient, String> {
+    Err("ledgerctl was built without the postgres_storage feature".to_string())
+}
+
+#[cfg(feature = "sqlite_storage")]
+async fn connect_sqlite() -> Result<DirectClient, String> {
+    use sqlite_storage::{SqliteConfig, SqliteStorage};
+
+    let url = std::env::var("SQLITE_URL").map_err(|_| "SQLITE_URL must be set".to_string())?;
+    let pool = sqlite_storage::connect(SqliteConfig { url })
+        .await
+        .map_err(|err| format!("Failed to connect to SQLite: {}", err))?;
+
+    let storage = Arc::new(SqliteStorage::new(pool.clone()));
+    let migrate: MigrateFn = Box::new(move || {
+        let pool = pool.clone();
+        async move { sqlite_storage::migrate(&pool).await }.boxed()
+    });
+    Ok(DirectClient::new(&storage, migrate))
+}
+
+#[cfg(not(feature = "sqlite_storage"))]
+async fn connect_sqlite() -> Result<DirectClient, String> {
+    Err("ledgerctl was built without the sqlite_storage feature".to_string())
+}
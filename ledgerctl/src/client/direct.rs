@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base::{
+    dto::{
+        account::{AccountCreationDTO, AccountCreationResultDTO},
+        import::BulkImportDTO,
+        statement::StatementRequestDTO,
+        transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+    },
+    lock::AccountLocks,
+    model::{Account, ImportReport, Statement, StatementEntry, Transaction},
+    storage::Storage,
+    use_case::{
+        BulkImportUseCase, CreateAccountUseCase, DepositUseCase, GenerateStatementUseCase,
+        GetAccountByUuidUseCase, TransferUseCase, UseCase, WithdrawalUseCase,
+        DEFAULT_IMPORT_CONCURRENCY,
+    },
+};
+use futures::{future::BoxFuture, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::LedgerClient;
+
+pub type MigrateFn = Box<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+pub struct DirectClient {
+    create_account_uc: Arc<dyn UseCase<AccountCreationDTO, AccountCreationResultDTO>>,
+    get_account_by_id_uc: Arc<dyn UseCase<Uuid, Option<Account>>>,
+    deposit_uc: Arc<dyn UseCase<DepositTransactionDTO, Vec<Transaction>>>,
+    withdrawal_uc: Arc<dyn UseCase<WithdrawalTransactionDTO, Vec<Transaction>>>,
+    transfer_uc: Arc<dyn UseCase<TransferTransactionDTO, Vec<Transaction>>>,
+    statement_uc: Arc<dyn UseCase<StatementRequestDTO, Statement>>,
+    import_uc: Arc<dyn UseCase<BulkImportDTO, ImportReport>>,
+    migrate: MigrateFn,
+}
+
+impl DirectClient {
+    pub fn new<S: Storage + 'static>(storage: &Arc<S>, migrate: MigrateFn) -> Self {
+        let locks = Arc::new(AccountLocks::default());
+        let create_account_uc = Arc::new(CreateAccountUseCase::new(storage));
+        let deposit_uc = Arc::new(DepositUseCase::new(storage, &locks));
+        let withdrawal_uc = Arc::new(WithdrawalUseCase::new(storage, &locks));
+        let transfer_uc = Arc::new(TransferUseCase::new(storage, &locks));
+        let import_uc = Arc::new(BulkImportUseCase::new(
+            create_account_uc.clone(),
+            deposit_uc.clone(),
+            withdrawal_uc.clone(),
+            transfer_uc.clone(),
+            DEFAULT_IMPORT_CONCURRENCY,
+        ));
+
+        DirectClient {
+            create_account_uc,
+            get_account_by_id_uc: Arc::new(GetAccountByUuidUseCase::new(storage)),
+            deposit_uc,
+            withdrawal_uc,
+            transfer_uc,
+            statement_uc: Arc::new(GenerateStatementUseCase::new(storage)),
+            import_uc,
+            migrate,
+        }
+    }
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
+#[async_trait]
+impl LedgerClient for DirectClient {
+    async fn create_account(&self, input: AccountCreationDTO) -> Result<Value, String> {
+        to_value(self.create_account_uc.execute(input).await?.account)
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Value, String> {
+        let account = self
+            .get_account_by_id_uc
+            .execute(uuid)
+            .await?
+            .ok_or("Account not found".to_string())?;
+        to_value(account)
+    }
+
+    async fn deposit(&self, input: DepositTransactionDTO) -> Result<Value, String> {
+        to_value(self.deposit_uc.execute(input).await?)
+    }
+
+    async fn withdraw(&self, input: WithdrawalTransactionDTO) -> Result<Value, String> {
+        to_value(self.withdrawal_uc.execute(input).await?)
+    }
+
+    async fn transfer(&self, input: TransferTransactionDTO) -> Result<Value, String> {
+        to_value(self.transfer_uc.execute(input).await?)
+    }
+
+    async fn statement(&self, input: StatementRequestDTO) -> Result<Value, String> {
+        let mut statement = self.statement_uc.execute(input).await?;
+        let mut document = to_value(&statement.header)?;
+        let mut transactions = Vec::new();
+        while let Some(entry) = statement.entries.next().await {
+            match entry? {
+                StatementEntry::Line(line) => transactions.push(to_value(line)?),
+                StatementEntry::Summary(summary) => {
+                    if let (Value::Object(document), Value::Object(summary)) =
+                        (&mut document, to_value(summary)?)
+                    {
+                        document.extend(summary);
+                    }
+                }
+            }
+        }
+        document["transactions"] = Value::Array(transactions);
+        Ok(document)
+    }
+
+    async fn import(&self, input: BulkImportDTO) -> Result<Vec<Value>, String> {
+        let report = self.import_uc.execute(input).await?;
+        report
+            .map(to_value)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    async fn migrate(&self) -> Result<(), String> {
+        (self.migrate)().await
+    }
+}
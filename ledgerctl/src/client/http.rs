@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use base::dto::{
+    account::AccountCreationDTO,
+    import::{BulkImportDTO, ImportFormat},
+    statement::StatementRequestDTO,
+    transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+};
+use reqwest::RequestBuilder;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::LedgerClient;
+
+pub struct HttpClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl HttpClient {
+    pub fn new(base_url: &str) -> Self {
+        HttpClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Sends the request and returns the body, turning non-2xx responses into
+    /// errors carrying the server's message.
+    async fn send(&self, request: RequestBuilder) -> Result<String, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach server: {}", e))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+        if status.is_success() {
+            Ok(body)
+        } else if body.is_empty() {
+            Err(status.to_string())
+        } else {
+            Err(body)
+        }
+    }
+
+    async fn send_json(&self, request: RequestBuilder) -> Result<Value, String> {
+        let body = self.send(request).await?;
+        serde_json::from_str(&body).map_err(|e| format!("Invalid response: {}", e))
+    }
+}
+
+#[async_trait]
+impl LedgerClient for HttpClient {
+    async fn create_account(&self, input: AccountCreationDTO) -> Result<Value, String> {
+        self.send_json(self.client.post(self.url("/accounts")).json(&input))
+            .await
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Value, String> {
+        let url = self.url(&format!("/accounts/{}", uuid));
+        let account = self.send_json(self.client.get(url)).await?;
+        // The server answers unknown accounts with `200 null`.
+        if account.is_null() {
+            return Err("Account not found".to_string());
+        }
+        Ok(account)
+    }
+
+    async fn deposit(&self, input: DepositTransactionDTO) -> Result<Value, String> {
+        self.send_json(self.client.post(self.url("/deposits")).json(&input))
+            .await
+    }
+
+    async fn withdraw(&self, input: WithdrawalTransactionDTO) -> Result<Value, String> {
+        self.send_json(self.client.post(self.url("/withdrawals")).json(&input))
+            .await
+    }
+
+    async fn transfer(&self, input: TransferTransactionDTO) -> Result<Value, String> {
+        self.send_json(self.client.post(self.url("/transfers")).json(&input))
+            .await
+    }
+
+    async fn statement(&self, input: StatementRequestDTO) -> Result<Value, String> {
+        let url = self.url(&format!("/accounts/{}/statement", input.account_id));
+        let query = [
+            ("from", input.from.to_rfc3339()),
+            ("to", input.to.to_rfc3339()),
+            ("format", "json".to_string()),
+        ];
+        self.send_json(self.client.get(url).query(&query)).await
+    }
+
+    async fn import(&self, input: BulkImportDTO) -> Result<Vec<Value>, String> {
+        let format = match input.format {
+            ImportFormat::Jsonl => "jsonl",
+            ImportFormat::Csv => "csv",
+        };
+        let query = [
+            ("format", format.to_string()),
+            ("resume_from", input.resume_from.to_string()),
+        ];
+        let request = self
+            .client
+            .post(self.url("/imports"))
+            .query(&query)
+            .body(input.content);
+        let body = self.send(request).await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("Invalid response: {}", e)))
+            .collect()
+    }
+
+    async fn migrate(&self) -> Result<(), String> {
+        Err("Migrations can only run against a storage backend, not a server".to_string())
+    }
+}
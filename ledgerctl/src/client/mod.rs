@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+use base::dto::{
+    account::AccountCreationDTO,
+    import::BulkImportDTO,
+    statement::StatementRequestDTO,
+    transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+mod direct;
+mod http;
+
+pub use direct::{DirectClient, MigrateFn};
+pub use http::HttpClient;
+
+/// Operations `ledgerctl` can run, either in-process against a storage backend
+/// or against a running server. Results are returned in the JSON shape of the
+/// HTTP API so both modes render identically.
+#[async_trait]
+pub trait LedgerClient: Send + Sync {
+    async fn create_account(&self, input: AccountCreationDTO) -> Result<Value, String>;
+    async fn get_account(&self, uuid: Uuid) -> Result<Value, String>;
+    async fn deposit(&self, input: DepositTransactionDTO) -> Result<Value, String>;
+    async fn withdraw(&self, input: WithdrawalTransactionDTO) -> Result<Value, String>;
+    async fn transfer(&self, input: TransferTransactionDTO) -> Result<Value, String>;
+    async fn statement(&self, input: StatementRequestDTO) -> Result<Value, String>;
+    async fn import(&self, input: BulkImportDTO) -> Result<Vec<Value>, String>;
+    async fn migrate(&self) -> Result<(), String>;
+}
@@ -0,0 +1,373 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use backend::Backend;
+use base::{
+    dto::{
+        account::AccountCreationDTO,
+        import::{BulkImportDTO, ImportFormat},
+        statement::StatementRequestDTO,
+        transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+    },
+    model::Details,
+};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, Parser, Subcommand};
+use client::{HttpClient, LedgerClient};
+use output::Output;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod backend;
+mod client;
+mod output;
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_UNAVAILABLE: u8 = 3;
+
+/// Administer a mini ledger, either in-process against a storage backend or
+/// through a running server.
+#[derive(Parser)]
+#[command(name = "ledgerctl")]
+struct Cli {
+    /// Storage backend used when no server is given
+    #[arg(
+        long,
+        env = "LEDGER_BACKEND",
+        value_enum,
+        default_value = "memory",
+        global = true
+    )]
+    backend: Backend,
+    /// Base URL of a running server, e.g. http://localhost:8080
+    #[arg(long, env = "LEDGER_SERVER", global = true)]
+    server: Option<String>,
+    #[arg(long, value_enum, default_value = "table", global = true)]
+    output: Output,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct DetailsArgs {
+    #[arg(long)]
+    description: Option<String>,
+    #[arg(long)]
+    external_reference: Option<String>,
+    /// Metadata entry as key=value; repeatable
+    #[arg(long = "metadata", value_parser = parse_metadata_entry)]
+    metadata: Vec<(String, String)>,
+}
+
+impl From<DetailsArgs> for Details {
+    fn from(args: DetailsArgs) -> Self {
+        Details {
+            description: args.description,
+            external_reference: args.external_reference,
+            metadata: args.metadata.into_iter().collect(),
+        }
+    }
+}
+
+fn parse_metadata_entry(entry: &str) -> Result<(String, String), String> {
+    entry
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected key=value, got {}", entry))
+}
+
+#[derive(Subcommand)]
+enum AccountCommand {
+    /// Create an account
+    Create {
+        #[arg(long)]
+        currency: String,
+        /// Defaults to a random id
+        #[arg(long)]
+        uuid: Option<Uuid>,
+        #[command(flatten)]
+        details: DetailsArgs,
+    },
+    /// Show an account
+    Get { uuid: Uuid },
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(subcommand)]
+    Account(AccountCommand),
+    /// Deposit into an account
+    Deposit {
+        account_id: Uuid,
+        amount: BigDecimal,
+        /// Defaults to a random key
+        #[arg(long)]
+        idempotency_key: Option<String>,
+        #[command(flatten)]
+        details: DetailsArgs,
+    },
+    /// Withdraw from an account
+    Withdraw {
+        account_id: Uuid,
+        amount: BigDecimal,
+        /// Defaults to a random key
+        #[arg(long)]
+        idempotency_key: Option<String>,
+        #[command(flatten)]
+        details: DetailsArgs,
+    },
+    /// Transfer between two accounts
+    Transfer {
+        from_account_id: Uuid,
+        to_account_id: Uuid,
+        amount: BigDecimal,
+        /// Defaults to a random key
+        #[arg(long)]
+        idempotency_key: Option<String>,
+        #[command(flatten)]
+        details: DetailsArgs,
+    },
+    /// Create the storage schema. On Cassandra this drops the existing keyspace.
+    Migrate {
+        /// Confirm that existing data may be lost
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Check that account balances match the sum of their transactions
+    Reconcile {
+        #[arg(required = true)]
+        account_ids: Vec<Uuid>,
+    },
+    /// Export an account's transactions with running balances
+    Export {
+        account_id: Uuid,
+        /// Defaults to the account creation time
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        /// Defaults to now
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Import operations from a JSONL or CSV file
+    Import {
+        file: PathBuf,
+        /// Inferred from the file extension when omitted
+        #[arg(long, value_parser = ["jsonl", "csv"])]
+        format: Option<String>,
+        #[arg(long, default_value_t = 0)]
+        resume_from: usize,
+    },
+}
+
+enum Outcome {
+    Done(Value),
+    /// The command ran but found problems, e.g. failed import lines.
+    Failed(Value),
+}
+
+fn idempotency_key(key: Option<String>) -> String {
+    key.unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+fn parse_field<T: std::str::FromStr>(value: &Value, field: &str) -> Result<T, String> {
+    value[field]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| format!("Missing or invalid {} in response", field))
+}
+
+async fn reconcile(client: &dyn LedgerClient, account_id: Uuid) -> Result<Value, String> {
+    let account = client.get_account(account_id).await?;
+    let balance: BigDecimal = parse_field(&account, "balance")?;
+    let statement = client
+        .statement(StatementRequestDTO {
+            account_id,
+            from: parse_field(&account, "created_at")?,
+            to: Utc::now() + Duration::seconds(1),
+        })
+        .await?;
+    let ledger_balance: BigDecimal = parse_field(&statement, "closing_balance")?;
+    let status = if balance == ledger_balance {
+        "ok"
+    } else {
+        "mismatch"
+    };
+    Ok(json!({
+        "account_id": account_id,
+        "balance": balance.to_string(),
+        "ledger_balance": ledger_balance.to_string(),
+        "difference": (&balance - &ledger_balance).to_string(),
+        "status": status,
+    }))
+}
+
+async fn run(client: &dyn LedgerClient, command: Command) -> Result<Outcome, String> {
+    let value = match command {
+        Command::Account(AccountCommand::Create {
+            currency,
+            uuid,
+            details,
+        }) => {
+            client
+                .create_account(AccountCreationDTO {
+                    uuid: uuid.unwrap_or_else(Uuid::new_v4),
+                    currency,
+                    details: details.into(),
+                })
+                .await?
+        }
+        Command::Account(AccountCommand::Get { uuid }) => client.get_account(uuid).await?,
+        Command::Deposit {
+            account_id,
+            amount,
+            idempotency_key: key,
+            details,
+        } => {
+            client
+                .deposit(DepositTransactionDTO {
+                    idempotency_key: idempotency_key(key),
+                    account_id,
+                    amount,
+                    details: details.into(),
+                    expected_version: None,
+                })
+                .await?
+        }
+        Command::Withdraw {
+            account_id,
+            amount,
+            idempotency_key: key,
+            details,
+        } => {
+            client
+                .withdraw(WithdrawalTransactionDTO {
+                    idempotency_key: idempotency_key(key),
+                    account_id,
+                    amount,
+                    details: details.into(),
+                    expected_version: None,
+                })
+                .await?
+        }
+        Command::Transfer {
+            from_account_id,
+            to_account_id,
+            amount,
+            idempotency_key: key,
+            details,
+        } => {
+            client
+                .transfer(TransferTransactionDTO {
+                    idempotency_key: idempotency_key(key),
+                    from_account_id,
+                    to_account_id,
+                    amount,
+                    details: details.into(),
+                    expected_version: None,
+                })
+                .await?
+        }
+        Command::Migrate { yes } => {
+            if !yes {
+                return Err("Refusing to migrate without --yes".to_string());
+            }
+            client.migrate().await?;
+            json!({ "status": "migrated" })
+        }
+        Command::Reconcile { account_ids } => {
+            let mut rows = Vec::new();
+            for account_id in account_ids {
+                let row = reconcile(client, account_id).await.unwrap_or_else(
+                    |error| json!({ "account_id": account_id, "status": "error", "error": error }),
+                );
+                rows.push(row);
+            }
+            let consistent = rows.iter().all(|row| row["status"] == "ok");
+            let rows = Value::Array(rows);
+            return Ok(if consistent {
+                Outcome::Done(rows)
+            } else {
+                Outcome::Failed(rows)
+            });
+        }
+        Command::Export {
+            account_id,
+            from,
+            to,
+        } => {
+            let from = match from {
+                Some(from) => from,
+                None => parse_field(&client.get_account(account_id).await?, "created_at")?,
+            };
+            client
+                .statement(StatementRequestDTO {
+                    account_id,
+                    from,
+                    to: to.unwrap_or_else(|| Utc::now() + Duration::seconds(1)),
+                })
+                .await?
+        }
+        Command::Import {
+            file,
+            format,
+            resume_from,
+        } => {
+            let csv = match format.as_deref() {
+                Some(format) => format == "csv",
+                None => file.extension().is_some_and(|ext| ext == "csv"),
+            };
+            let content = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+            let results = client
+                .import(BulkImportDTO {
+                    content,
+                    format: if csv {
+                        ImportFormat::Csv
+                    } else {
+                        ImportFormat::Jsonl
+                    },
+                    resume_from,
+                })
+                .await?;
+            let succeeded = results.iter().all(|result| result["status"] == "ok");
+            let results = Value::Array(results);
+            return Ok(if succeeded {
+                Outcome::Done(results)
+            } else {
+                Outcome::Failed(results)
+            });
+        }
+    };
+    Ok(Outcome::Done(value))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let client: Box<dyn LedgerClient> = match &cli.server {
+        Some(server) => Box::new(HttpClient::new(server)),
+        None => match backend::connect(cli.backend).await {
+            Ok(client) => Box::new(client),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                return ExitCode::from(EXIT_UNAVAILABLE);
+            }
+        },
+    };
+
+    match run(client.as_ref(), cli.command).await {
+        Ok(Outcome::Done(value)) => {
+            output::print(cli.output, &value);
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::Failed(value)) => {
+            output::print(cli.output, &value);
+            ExitCode::from(EXIT_FAILURE)
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use clap::ValueEnum;
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Output {
+    Table,
+    Json,
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn print_rows(header: &[String], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain(std::iter::once(name.len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let line = |fields: &[String]| {
+        let padded: Vec<String> = fields
+            .iter()
+            .zip(&widths)
+            .map(|(field, width)| format!("{:<width$}", field, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(header);
+    for row in rows {
+        line(row);
+    }
+}
+
+fn print_list(items: &[Value]) {
+    let mut columns: Vec<String> = Vec::new();
+    for item in items {
+        if let Value::Object(fields) = item {
+            for key in fields.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    if columns.is_empty() {
+        items.iter().for_each(|item| println!("{}", cell(item)));
+        return;
+    }
+    let rows: Vec<Vec<String>> = items
+        .iter()
+        .map(|item| columns.iter().map(|column| cell(&item[column])).collect())
+        .collect();
+    print_rows(&columns, &rows);
+}
+
+/// Objects print as a key/value table, arrays as one row per element with the
+/// union of their keys as columns. Arrays nested in an object follow as their
+/// own table.
+fn print_table(value: &Value) {
+    match value {
+        Value::Array(items) => print_list(items),
+        Value::Object(fields) => {
+            let rows: Vec<Vec<String>> = fields
+                .iter()
+                .filter(|(_, value)| !value.is_array())
+                .map(|(key, value)| vec![key.clone(), cell(value)])
+                .collect();
+            print_rows(&["field".to_string(), "value".to_string()], &rows);
+            for (key, value) in fields {
+                if let Value::Array(items) = value {
+                    println!("\n{}:", key);
+                    print_list(items);
+                }
+            }
+        }
+        other => println!("{}", cell(other)),
+    }
+}
+
+pub fn print(output: Output, value: &Value) {
+    match output {
+        Output::Table => print_table(value),
+        Output::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        ),
+    }
+}
@@ -0,0 +1,40 @@
+CREATE TABLE accounts (
+    id UUID PRIMARY KEY,
+    currency TEXT NOT NULL,
+    balance NUMERIC NOT NULL,
+    created_at_in_nanos BIGINT NOT NULL,
+    last_updated_at_in_nanos BIGINT NOT NULL,
+    version UUID NOT NULL,
+    description TEXT,
+    external_reference TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE transactions (
+    id UUID PRIMARY KEY,
+    operation_id UUID NOT NULL,
+    counterparty_account_id UUID,
+    kind TEXT NOT NULL,
+    status TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    account_id UUID NOT NULL REFERENCES accounts (id),
+    account_version UUID NOT NULL,
+    amount NUMERIC NOT NULL,
+    balance_after NUMERIC NOT NULL,
+    -- Nanoseconds, since TIMESTAMPTZ only keeps microseconds.
+    created_at_in_nanos BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    description TEXT,
+    external_reference TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    CONSTRAINT transactions_idempotency_key UNIQUE (account_id, idempotency_key)
+);
+
+CREATE INDEX transactions_by_account_time
+    ON transactions (account_id, created_at_in_nanos, id);
+CREATE INDEX transactions_by_time ON transactions (created_at_in_nanos, id);
+CREATE INDEX transactions_by_operation ON transactions (operation_id);
+CREATE INDEX transactions_by_reference
+    ON transactions (external_reference, created_at_in_nanos, id)
+    WHERE external_reference IS NOT NULL;
+CREATE INDEX transactions_by_metadata ON transactions USING GIN (metadata jsonb_path_ops);
@@ -0,0 +1,10 @@
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+use super::config::PostgresConfig;
+
+pub async fn connect(config: PostgresConfig) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .connect(&config.url)
+        .await
+}
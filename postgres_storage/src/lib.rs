@@ -0,0 +1,9 @@
+mod config;
+mod connection;
+mod migrate;
+mod storage;
+
+pub use config::PostgresConfig;
+pub use connection::connect;
+pub use migrate::{migrate, pending_migrations};
+pub use storage::PostgresStorage;
@@ -0,0 +1,33 @@
+use sqlx::{migrate::Migrator, PgPool};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Applies the migrations embedded from `migrations/` that the database has
+/// not seen yet. Unlike the Cassandra migration, existing data is kept.
+pub async fn migrate(pool: &PgPool) -> Result<(), String> {
+    MIGRATOR
+        .run(pool)
+        .await
+        .map_err(|e| format!("Failed to migrate Postgres: {}", e))
+}
+
+/// Versions of the embedded migrations the database has not applied yet.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<i64>, String> {
+    let tracked: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Postgres is unreachable: {}", e))?;
+    let applied: Vec<i64> = if tracked {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to read applied migrations: {}", e))?
+    } else {
+        vec![]
+    };
+    Ok(MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
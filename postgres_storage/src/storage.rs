@@ -0,0 +1,569 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use base::{
+    dto::search::{SearchCursor, TransactionDirection, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Details, Metadata, Transaction},
+    storage::{AccountCreation, Storage, IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    types::Json,
+    PgPool, QueryBuilder, Row,
+};
+use uuid::Uuid;
+
+use crate::migrate::pending_migrations;
+
+const ACCOUNT_COLUMNS: &str = "id, currency, balance, created_at_in_nanos, \
+    last_updated_at_in_nanos, version, description, external_reference, metadata";
+
+const TRANSACTION_COLUMNS: &str = "id, operation_id, counterparty_account_id, kind, status, \
+    idempotency_key, account_id, account_version, amount, balance_after, created_at_in_nanos, \
+    currency, description, external_reference, metadata";
+
+#[derive(Debug, Clone)]
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresStorage { pool }
+    }
+}
+
+fn nanos(at: &DateTime<Utc>) -> i64 {
+    at.timestamp_nanos_opt().unwrap()
+}
+
+fn column<'r, T>(row: &'r PgRow, name: &str) -> Result<T, String>
+where
+    T: sqlx::Decode<'r, Postgres> + sqlx::Type<Postgres>,
+{
+    row.try_get(name)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))
+}
+
+fn parse<T: std::str::FromStr>(row: &PgRow, name: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    let value: String = column(row, name)?;
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {} {:?}: {}", name, value, e))
+}
+
+fn get_details(row: &PgRow) -> Result<Details, String> {
+    let Json(metadata): Json<Metadata> = column(row, "metadata")?;
+    Ok(Details {
+        description: column(row, "description")?,
+        external_reference: column(row, "external_reference")?,
+        metadata,
+    })
+}
+
+fn get_account(row: &PgRow) -> Result<Account, String> {
+    let mut account = Account::from_storage(
+        column(row, "id")?,
+        column(row, "currency")?,
+        column::<bigdecimal::BigDecimal>(row, "balance")?.with_scale(2),
+        column(row, "created_at_in_nanos")?,
+        column(row, "last_updated_at_in_nanos")?,
+        column(row, "version")?,
+    );
+    account.details = get_details(row)?;
+    Ok(account)
+}
+
+fn get_transaction(row: &PgRow) -> Result<Transaction, String> {
+    Ok(Transaction {
+        id: column(row, "id")?,
+        operation_id: column(row, "operation_id")?,
+        counterparty_account_id: column(row, "counterparty_account_id")?,
+        kind: parse(row, "kind")?,
+        status: parse(row, "status")?,
+        idempotency_key: column(row, "idempotency_key")?,
+        account_version: column(row, "account_version")?,
+        account_id: column(row, "account_id")?,
+        amount: column::<bigdecimal::BigDecimal>(row, "amount")?.with_scale(2),
+        balance_after: column::<bigdecimal::BigDecimal>(row, "balance_after")?.with_scale(2),
+        created_at: Utc.timestamp_nanos(column(row, "created_at_in_nanos")?),
+        currency: column(row, "currency")?,
+        details: get_details(row)?,
+    })
+}
+
+impl PostgresStorage {
+    /// Writes everything in one SQL transaction. Account rows are updated in
+    /// id order, so concurrent transfers in opposite directions lock them in
+    /// the same order instead of deadlocking.
+    async fn commit(
+        &self,
+        created_transactions: &[Transaction],
+        updated_accounts: &[AccountUpdate],
+    ) -> Result<(), String> {
+        let mut sql_tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+        let mut updates: Vec<&AccountUpdate> = updated_accounts.iter().collect();
+        updates.sort_by_key(|update| update.account.uuid);
+        for update in updates {
+            let account = &update.account;
+            let result = sqlx::query(
+                "UPDATE accounts SET balance = $1, last_updated_at_in_nanos = $2, version = $3 \
+                    WHERE id = $4 AND version = $5",
+            )
+            .bind(&account.balance)
+            .bind(nanos(&account.last_updated_at))
+            .bind(account.version)
+            .bind(account.uuid)
+            .bind(update.expected_version)
+            .execute(&mut *sql_tx)
+            .await
+            .map_err(|e| format!("Failed to update account: {}", e))?;
+            if result.rows_affected() == 0 {
+                return Err(VERSION_CONFLICT.to_string());
+            }
+        }
+
+        let insert_transaction = format!(
+            "INSERT INTO transactions ({}) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+            TRANSACTION_COLUMNS
+        );
+        for tx in created_transactions {
+            sqlx::query(&insert_transaction)
+                .bind(tx.id)
+                .bind(tx.operation_id)
+                .bind(tx.counterparty_account_id)
+                .bind(tx.kind.as_str())
+                .bind(tx.status.as_str())
+                .bind(&tx.idempotency_key)
+                .bind(tx.account_id)
+                .bind(tx.account_version)
+                .bind(&tx.amount)
+                .bind(&tx.balance_after)
+                .bind(nanos(&tx.created_at))
+                .bind(&tx.currency)
+                .bind(&tx.details.description)
+                .bind(&tx.details.external_reference)
+                .bind(Json(&tx.details.metadata))
+                .execute(&mut *sql_tx)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::Database(e) if e.is_unique_violation() => {
+                        IDEMPOTENCY_KEY_REUSED.to_string()
+                    }
+                    e => format!("Failed to insert transaction: {}", e),
+                })?;
+        }
+
+        sql_tx
+            .commit()
+            .await
+            .map_err(|e| format!("Failed to commit transaction: {}", e))
+    }
+
+    /// The operation that already recorded the idempotency keys of
+    /// `created_transactions`, when it is the same operation being replayed.
+    async fn replayed_operation(
+        &self,
+        created_transactions: &[Transaction],
+    ) -> Result<Option<Vec<Transaction>>, String> {
+        let mut recorded = BTreeSet::new();
+        for tx in created_transactions {
+            recorded.extend(
+                self.get_idempotency_key(tx.account_id, &tx.idempotency_key)
+                    .await?,
+            );
+        }
+        let Some(operation_id) = recorded.first() else {
+            return Ok(None);
+        };
+        let operation = self.get_operation_transactions(*operation_id).await?;
+        if recorded.len() == 1 && operation.len() == created_transactions.len() {
+            Ok(Some(operation))
+        } else {
+            Err(IDEMPOTENCY_KEY_REUSED.to_string())
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        let result = sqlx::query(&format!(
+            "INSERT INTO accounts ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+                ON CONFLICT (id) DO NOTHING",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(account.uuid)
+        .bind(&account.currency)
+        .bind(&account.balance)
+        .bind(nanos(&account.created_at))
+        .bind(nanos(&account.last_updated_at))
+        .bind(account.version)
+        .bind(&account.details.description)
+        .bind(&account.details.external_reference)
+        .bind(Json(&account.details.metadata))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to save account: {}", e))?;
+        if result.rows_affected() == 1 {
+            return Ok(AccountCreation::Created);
+        }
+        let stored = self
+            .get_account(account.uuid)
+            .await?
+            .ok_or("Account not found after a conflicting insert")?;
+        Ok(AccountCreation::AlreadyExists(stored))
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM accounts WHERE id = $1",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(uuid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get account: {}", e))?;
+        row.as_ref().map(get_account).transpose()
+    }
+
+    /// Replaying transactions whose idempotency keys were already recorded
+    /// returns the original operation's transactions and writes nothing.
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        match self.commit(&created_transactions, &updated_accounts).await {
+            Ok(()) => Ok(created_transactions),
+            Err(error) => match self.replayed_operation(&created_transactions).await? {
+                Some(operation) => Ok(operation),
+                None => Err(error),
+            },
+        }
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM transactions \
+                WHERE account_id = $1 AND created_at_in_nanos >= $2 AND created_at_in_nanos < $3 \
+                ORDER BY created_at_in_nanos, id LIMIT $4",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(account_id)
+        .bind(nanos(&from))
+        .bind(nanos(&to))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get transactions: {}", e))?;
+        rows.iter().map(get_transaction).collect()
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM transactions WHERE id = $1",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get transaction: {}", e))?;
+        row.as_ref().map(get_transaction).transpose()
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM transactions WHERE operation_id = $1 \
+                ORDER BY created_at_in_nanos, id",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(operation_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get operation transactions: {}", e))?;
+        rows.iter().map(get_transaction).collect()
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        sqlx::query_scalar(
+            "SELECT operation_id FROM transactions \
+                WHERE account_id = $1 AND idempotency_key = $2",
+        )
+        .bind(account_id)
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to look up idempotency key: {}", e))
+    }
+
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let mut builder = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {} FROM transactions WHERE TRUE",
+            TRANSACTION_COLUMNS
+        ));
+        if let Some(account_id) = query.account_id {
+            builder.push(" AND account_id = ").push_bind(account_id);
+        }
+        if let Some(reference) = &query.external_reference {
+            builder
+                .push(" AND external_reference = ")
+                .push_bind(reference.clone());
+        }
+        if let Some((key, value)) = query.metadata() {
+            let entry: Metadata = [(key.to_string(), value.to_string())].into();
+            builder.push(" AND metadata @> ").push_bind(Json(entry));
+        }
+        if let Some(min) = &query.min_amount {
+            builder.push(" AND abs(amount) >= ").push_bind(min.clone());
+        }
+        if let Some(max) = &query.max_amount {
+            builder.push(" AND abs(amount) <= ").push_bind(max.clone());
+        }
+        if let Some(currency) = &query.currency {
+            builder.push(" AND currency = ").push_bind(currency.clone());
+        }
+        if let Some(from) = &query.from {
+            builder
+                .push(" AND created_at_in_nanos >= ")
+                .push_bind(nanos(from));
+        }
+        if let Some(to) = &query.to {
+            builder
+                .push(" AND created_at_in_nanos < ")
+                .push_bind(nanos(to));
+        }
+        match query.direction {
+            Some(TransactionDirection::Credit) => builder.push(" AND amount >= 0"),
+            Some(TransactionDirection::Debit) => builder.push(" AND amount < 0"),
+            None => &mut builder,
+        };
+        if let Some(kind) = query.kind {
+            builder.push(" AND kind = ").push_bind(kind.as_str());
+        }
+        if let Some(status) = query.status {
+            builder.push(" AND status = ").push_bind(status.as_str());
+        }
+        if let Some(cursor) = after {
+            builder
+                .push(" AND (created_at_in_nanos, id) > (")
+                .push_bind(cursor.created_at_in_nanos)
+                .push(", ")
+                .push_bind(cursor.id)
+                .push(")");
+        }
+        builder
+            .push(" ORDER BY created_at_in_nanos, id LIMIT ")
+            .push_bind(limit as i64);
+
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to search transactions: {}", e))?;
+        rows.iter().map(get_transaction).collect()
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        let pending = pending_migrations(&self.pool).await?;
+        if !pending.is_empty() {
+            return Err(format!("Postgres migrations pending: {:?}", pending));
+        }
+        Ok(())
+    }
+
+    /// Waits for connections in use to be returned, then closes them all.
+    async fn close(&self) -> Result<(), String> {
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base::model::OperationKind;
+    use bigdecimal::BigDecimal;
+
+    use crate::{connect, migrate, PostgresConfig};
+
+    use super::*;
+
+    /// Runs against the database in `POSTGRES_URL`, e.g.
+    /// `POSTGRES_URL=postgres://localhost/ledger_test cargo test -p postgres_storage -- --ignored`.
+    async fn setup() -> (PostgresStorage, Account) {
+        let url = std::env::var("POSTGRES_URL").expect("POSTGRES_URL must be set");
+        let pool = connect(PostgresConfig {
+            url,
+            max_connections: 4,
+        })
+        .await
+        .unwrap();
+        migrate(&pool).await.unwrap();
+        let storage = PostgresStorage::new(pool);
+        let mut account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        account.details.metadata.insert("team".into(), "ops".into());
+        storage.save_account(account.clone()).await.unwrap();
+        (storage, account)
+    }
+
+    fn deposit(account: &Account, key: &str, amount: i32) -> (Transaction, AccountUpdate) {
+        let mut tx = Transaction::new(
+            account,
+            key,
+            &BigDecimal::from(amount),
+            OperationKind::Deposit,
+        );
+        tx.details
+            .metadata
+            .insert("order".into(), tx.id.to_string());
+        let update = account.apply(&tx);
+        (tx, update)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Postgres in POSTGRES_URL"]
+    async fn test_conformance() {
+        let (storage, _) = setup().await;
+        base::storage::conformance::run(&storage).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Postgres in POSTGRES_URL"]
+    async fn test_account_round_trip() {
+        let (storage, account) = setup().await;
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.uuid, account.uuid);
+        assert_eq!(stored.version, account.version);
+        assert_eq!(stored.details, account.details);
+        assert!(storage.get_account(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Postgres in POSTGRES_URL"]
+    async fn test_save_and_read_transactions() {
+        let (storage, account) = setup().await;
+        let (tx, update) = deposit(&account, "idemp_1", 10);
+        storage
+            .save_transactions(vec![tx.clone()], vec![update])
+            .await
+            .unwrap();
+
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+        assert_eq!(stored.version, tx.account_version);
+        let history = storage
+            .get_transactions(account.uuid, account.created_at, Utc::now(), 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, tx.id);
+        assert_eq!(history[0].created_at, tx.created_at);
+        assert_eq!(history[0].details, tx.details);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Postgres in POSTGRES_URL"]
+    async fn test_stale_version_writes_nothing() {
+        let (storage, account) = setup().await;
+        let (first, update) = deposit(&account, "idemp_1", 10);
+        storage
+            .save_transactions(vec![first], vec![update])
+            .await
+            .unwrap();
+
+        let (stale, update) = deposit(&account, "idemp_2", 10);
+        let result = storage
+            .save_transactions(vec![stale.clone()], vec![update])
+            .await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+        assert!(storage.get_transaction(stale.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Postgres in POSTGRES_URL"]
+    async fn test_replay_returns_original_operation() {
+        let (storage, account) = setup().await;
+        let (tx, update) = deposit(&account, "idemp_1", 10);
+        storage
+            .save_transactions(vec![tx.clone()], vec![update])
+            .await
+            .unwrap();
+
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        let (replay, update) = deposit(&stored, "idemp_1", 10);
+        let result = storage
+            .save_transactions(vec![replay], vec![update])
+            .await
+            .unwrap();
+        assert_eq!(result[0].id, tx.id);
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Postgres in POSTGRES_URL"]
+    async fn test_search() {
+        let (storage, mut account) = setup().await;
+        let mut saved = vec![];
+        for (i, amount) in [5, 50, 500].into_iter().enumerate() {
+            let (tx, update) = deposit(&account, &format!("idemp_{}", i), amount);
+            storage
+                .save_transactions(vec![tx.clone()], vec![update.clone()])
+                .await
+                .unwrap();
+            account = update.account;
+            saved.push(tx);
+        }
+
+        let query = TransactionSearchDTO {
+            metadata_key: Some("order".to_string()),
+            metadata_value: Some(saved[1].id.to_string()),
+            ..TransactionSearchDTO::default()
+        };
+        let found = storage.search_transactions(&query, None, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, saved[1].id);
+
+        let query = TransactionSearchDTO {
+            account_id: Some(account.uuid),
+            min_amount: Some(BigDecimal::from(10)),
+            ..TransactionSearchDTO::default()
+        };
+        let first = storage.search_transactions(&query, None, 1).await.unwrap();
+        assert_eq!(first[0].id, saved[1].id);
+        let rest = storage
+            .search_transactions(&query, Some(SearchCursor::of(&first[0])), 10)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].id, saved[2].id);
+    }
+}
@@ -0,0 +1,49 @@
+CREATE TABLE accounts (
+    id TEXT PRIMARY KEY,
+    currency TEXT NOT NULL,
+    -- Decimals are kept as text to avoid floating point rounding.
+    balance TEXT NOT NULL,
+    created_at_in_nanos INTEGER NOT NULL,
+    last_updated_at_in_nanos INTEGER NOT NULL,
+    version TEXT NOT NULL,
+    description TEXT,
+    external_reference TEXT,
+    metadata TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE transactions (
+    id TEXT PRIMARY KEY,
+    operation_id TEXT NOT NULL,
+    counterparty_account_id TEXT,
+    kind TEXT NOT NULL,
+    status TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    account_version TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    balance_after TEXT NOT NULL,
+    created_at_in_nanos INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    description TEXT,
+    external_reference TEXT,
+    metadata TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX transactions_by_account_time
+    ON transactions (account_id, created_at_in_nanos, id);
+CREATE INDEX transactions_by_time ON transactions (created_at_in_nanos, id);
+CREATE INDEX transactions_by_operation ON transactions (operation_id);
+CREATE INDEX transactions_by_reference
+    ON transactions (external_reference, created_at_in_nanos, id)
+    WHERE external_reference IS NOT NULL;
+CREATE UNIQUE INDEX transactions_by_idempotency_key
+    ON transactions (account_id, idempotency_key);
+
+CREATE TABLE transaction_metadata (
+    transaction_id TEXT NOT NULL REFERENCES transactions (id),
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (transaction_id, key)
+);
+
+CREATE INDEX transaction_metadata_by_entry ON transaction_metadata (key, value);
@@ -0,0 +1,4 @@
+pub struct SqliteConfig {
+    /// e.g. `sqlite://ledger.db` or `sqlite::memory:`
+    pub url: String,
+}
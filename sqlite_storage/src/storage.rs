@@ -0,0 +1,629 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use base::{
+    dto::search::{SearchCursor, TransactionSearchDTO},
+    model::{Account, AccountUpdate, Details, Metadata, Transaction},
+    storage::{AccountCreation, Storage, IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT},
+};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::{
+    sqlite::{Sqlite, SqliteRow},
+    QueryBuilder, Row, SqlitePool,
+};
+use uuid::Uuid;
+
+use crate::migrate::pending_migrations;
+
+const ACCOUNT_COLUMNS: &str = "id, currency, balance, created_at_in_nanos, \
+    last_updated_at_in_nanos, version, description, external_reference, metadata";
+
+const TRANSACTION_COLUMNS: &str = "id, operation_id, counterparty_account_id, kind, status, \
+    idempotency_key, account_id, account_version, amount, balance_after, created_at_in_nanos, \
+    currency, description, external_reference, metadata";
+
+const SEARCH_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteStorage { pool }
+    }
+}
+
+fn nanos(at: &DateTime<Utc>) -> i64 {
+    at.timestamp_nanos_opt().unwrap()
+}
+
+fn parse<T: std::str::FromStr>(row: &SqliteRow, column: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    let value: String = row
+        .try_get(column)
+        .map_err(|e| format!("Failed to read {}: {}", column, e))?;
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {} {:?}: {}", column, value, e))
+}
+
+fn metadata_json(metadata: &Metadata) -> String {
+    serde_json::to_string(metadata).unwrap()
+}
+
+fn get_details(row: &SqliteRow) -> Result<Details, String> {
+    let metadata: String = row.try_get("metadata").map_err(|e| e.to_string())?;
+    Ok(Details {
+        description: row.try_get("description").map_err(|e| e.to_string())?,
+        external_reference: row
+            .try_get("external_reference")
+            .map_err(|e| e.to_string())?,
+        metadata: serde_json::from_str(&metadata).map_err(|e| e.to_string())?,
+    })
+}
+
+fn get_account(row: &SqliteRow) -> Result<Account, String> {
+    let balance: BigDecimal = parse(row, "balance")?;
+    let mut account = Account::from_storage(
+        parse(row, "id")?,
+        row.try_get("currency").map_err(|e| e.to_string())?,
+        balance.with_scale(2),
+        row.try_get("created_at_in_nanos")
+            .map_err(|e| e.to_string())?,
+        row.try_get("last_updated_at_in_nanos")
+            .map_err(|e| e.to_string())?,
+        parse(row, "version")?,
+    );
+    account.details = get_details(row)?;
+    Ok(account)
+}
+
+fn get_transaction(row: &SqliteRow) -> Result<Transaction, String> {
+    let counterparty: Option<String> = row
+        .try_get("counterparty_account_id")
+        .map_err(|e| e.to_string())?;
+    let amount: BigDecimal = parse(row, "amount")?;
+    let balance_after: BigDecimal = parse(row, "balance_after")?;
+    Ok(Transaction {
+        id: parse(row, "id")?,
+        operation_id: parse(row, "operation_id")?,
+        counterparty_account_id: counterparty
+            .map(|id| {
+                id.parse()
+                    .map_err(|e| format!("Invalid counterparty: {}", e))
+            })
+            .transpose()?,
+        kind: parse(row, "kind")?,
+        status: parse(row, "status")?,
+        idempotency_key: row.try_get("idempotency_key").map_err(|e| e.to_string())?,
+        account_version: parse(row, "account_version")?,
+        account_id: parse(row, "account_id")?,
+        amount: amount.with_scale(2),
+        balance_after: balance_after.with_scale(2),
+        created_at: Utc.timestamp_nanos(
+            row.try_get("created_at_in_nanos")
+                .map_err(|e| e.to_string())?,
+        ),
+        currency: row.try_get("currency").map_err(|e| e.to_string())?,
+        details: get_details(row)?,
+    })
+}
+
+impl SqliteStorage {
+    /// Writes everything in one SQL transaction, updates first: the first
+    /// statement takes SQLite's write lock, so a concurrent writer waits
+    /// instead of failing when it upgrades from a read.
+    async fn commit(
+        &self,
+        created_transactions: &[Transaction],
+        updated_accounts: &[AccountUpdate],
+    ) -> Result<(), String> {
+        let mut sql_tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+        for update in updated_accounts {
+            let account = &update.account;
+            let result = sqlx::query(
+                "UPDATE accounts SET balance = ?, last_updated_at_in_nanos = ?, version = ? \
+                    WHERE id = ? AND version = ?",
+            )
+            .bind(account.balance.with_scale(2).to_string())
+            .bind(nanos(&account.last_updated_at))
+            .bind(account.version.to_string())
+            .bind(account.uuid.to_string())
+            .bind(update.expected_version.to_string())
+            .execute(&mut *sql_tx)
+            .await
+            .map_err(|e| format!("Failed to update account: {}", e))?;
+            if result.rows_affected() == 0 {
+                return Err(VERSION_CONFLICT.to_string());
+            }
+        }
+
+        let insert_transaction = format!(
+            "INSERT INTO transactions ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            TRANSACTION_COLUMNS
+        );
+        for tx in created_transactions {
+            sqlx::query(&insert_transaction)
+                .bind(tx.id.to_string())
+                .bind(tx.operation_id.to_string())
+                .bind(tx.counterparty_account_id.map(|id| id.to_string()))
+                .bind(tx.kind.as_str())
+                .bind(tx.status.as_str())
+                .bind(&tx.idempotency_key)
+                .bind(tx.account_id.to_string())
+                .bind(tx.account_version.to_string())
+                .bind(tx.amount.with_scale(2).to_string())
+                .bind(tx.balance_after.with_scale(2).to_string())
+                .bind(nanos(&tx.created_at))
+                .bind(&tx.currency)
+                .bind(&tx.details.description)
+                .bind(&tx.details.external_reference)
+                .bind(metadata_json(&tx.details.metadata))
+                .execute(&mut *sql_tx)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::Database(e) if e.is_unique_violation() => {
+                        IDEMPOTENCY_KEY_REUSED.to_string()
+                    }
+                    e => format!("Failed to insert transaction: {}", e),
+                })?;
+
+            for (key, value) in &tx.details.metadata {
+                sqlx::query(
+                    "INSERT INTO transaction_metadata (transaction_id, key, value) \
+                        VALUES (?, ?, ?)",
+                )
+                .bind(tx.id.to_string())
+                .bind(key)
+                .bind(value)
+                .execute(&mut *sql_tx)
+                .await
+                .map_err(|e| format!("Failed to insert transaction metadata: {}", e))?;
+            }
+        }
+
+        sql_tx
+            .commit()
+            .await
+            .map_err(|e| format!("Failed to commit transaction: {}", e))
+    }
+
+    /// The operation that already recorded the idempotency keys of
+    /// `created_transactions`, when it is the same operation being replayed.
+    async fn replayed_operation(
+        &self,
+        created_transactions: &[Transaction],
+    ) -> Result<Option<Vec<Transaction>>, String> {
+        let mut recorded = BTreeSet::new();
+        for tx in created_transactions {
+            recorded.extend(
+                self.get_idempotency_key(tx.account_id, &tx.idempotency_key)
+                    .await?,
+            );
+        }
+        let Some(operation_id) = recorded.first() else {
+            return Ok(None);
+        };
+        let operation = self.get_operation_transactions(*operation_id).await?;
+        if recorded.len() == 1 && operation.len() == created_transactions.len() {
+            Ok(Some(operation))
+        } else {
+            Err(IDEMPOTENCY_KEY_REUSED.to_string())
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn save_account(&self, account: Account) -> Result<AccountCreation, String> {
+        let result = sqlx::query(&format!(
+            "INSERT INTO accounts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+                ON CONFLICT (id) DO NOTHING",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(account.uuid.to_string())
+        .bind(&account.currency)
+        .bind(account.balance.with_scale(2).to_string())
+        .bind(nanos(&account.created_at))
+        .bind(nanos(&account.last_updated_at))
+        .bind(account.version.to_string())
+        .bind(&account.details.description)
+        .bind(&account.details.external_reference)
+        .bind(metadata_json(&account.details.metadata))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to save account: {}", e))?;
+        if result.rows_affected() == 1 {
+            return Ok(AccountCreation::Created);
+        }
+        let stored = self
+            .get_account(account.uuid)
+            .await?
+            .ok_or("Account not found after a conflicting insert")?;
+        Ok(AccountCreation::AlreadyExists(stored))
+    }
+
+    async fn get_account(&self, uuid: Uuid) -> Result<Option<Account>, String> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM accounts WHERE id = ?",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(uuid.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get account: {}", e))?;
+        row.as_ref().map(get_account).transpose()
+    }
+
+    /// Replaying transactions whose idempotency keys were already recorded
+    /// returns the original operation's transactions and writes nothing.
+    async fn save_transactions(
+        &self,
+        created_transactions: Vec<Transaction>,
+        updated_accounts: Vec<AccountUpdate>,
+    ) -> Result<Vec<Transaction>, String> {
+        match self.commit(&created_transactions, &updated_accounts).await {
+            Ok(()) => Ok(created_transactions),
+            Err(error) => match self.replayed_operation(&created_transactions).await? {
+                Some(operation) => Ok(operation),
+                None => Err(error),
+            },
+        }
+    }
+
+    async fn get_transactions(
+        &self,
+        account_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM transactions \
+                WHERE account_id = ? AND created_at_in_nanos >= ? AND created_at_in_nanos < ? \
+                ORDER BY created_at_in_nanos, id LIMIT ?",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(account_id.to_string())
+        .bind(nanos(&from))
+        .bind(nanos(&to))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get transactions: {}", e))?;
+        rows.iter().map(get_transaction).collect()
+    }
+
+    async fn get_transaction(&self, id: Uuid) -> Result<Option<Transaction>, String> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM transactions WHERE id = ?",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get transaction: {}", e))?;
+        row.as_ref().map(get_transaction).transpose()
+    }
+
+    async fn get_operation_transactions(
+        &self,
+        operation_id: Uuid,
+    ) -> Result<Vec<Transaction>, String> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM transactions WHERE operation_id = ? \
+                ORDER BY created_at_in_nanos, id",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(operation_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to get operation transactions: {}", e))?;
+        rows.iter().map(get_transaction).collect()
+    }
+
+    async fn get_idempotency_key(
+        &self,
+        account_id: Uuid,
+        idempotency_key: &str,
+    ) -> Result<Option<Uuid>, String> {
+        let row = sqlx::query(
+            "SELECT operation_id FROM transactions \
+                WHERE account_id = ? AND idempotency_key = ?",
+        )
+        .bind(account_id.to_string())
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to look up idempotency key: {}", e))?;
+        row.map(|row| parse::<Uuid>(&row, "operation_id"))
+            .transpose()
+    }
+
+    /// Indexed filters are applied in SQL; amount and direction are checked
+    /// on each page, since decimals are stored as text.
+    async fn search_transactions(
+        &self,
+        query: &TransactionSearchDTO,
+        after: Option<SearchCursor>,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, String> {
+        let mut found = Vec::new();
+        let mut position = after;
+        loop {
+            let mut builder = QueryBuilder::<Sqlite>::new(format!(
+                "SELECT {} FROM transactions WHERE 1 = 1",
+                TRANSACTION_COLUMNS
+            ));
+            if let Some(account_id) = query.account_id {
+                builder
+                    .push(" AND account_id = ")
+                    .push_bind(account_id.to_string());
+            }
+            if let Some(reference) = &query.external_reference {
+                builder
+                    .push(" AND external_reference = ")
+                    .push_bind(reference.clone());
+            }
+            if let Some((key, value)) = query.metadata() {
+                builder
+                    .push(
+                        " AND EXISTS (SELECT 1 FROM transaction_metadata \
+                            WHERE transaction_id = transactions.id AND key = ",
+                    )
+                    .push_bind(key.to_string())
+                    .push(" AND value = ")
+                    .push_bind(value.to_string())
+                    .push(")");
+            }
+            if let Some(currency) = &query.currency {
+                builder.push(" AND currency = ").push_bind(currency.clone());
+            }
+            if let Some(from) = &query.from {
+                builder
+                    .push(" AND created_at_in_nanos >= ")
+                    .push_bind(nanos(from));
+            }
+            if let Some(to) = &query.to {
+                builder
+                    .push(" AND created_at_in_nanos < ")
+                    .push_bind(nanos(to));
+            }
+            if let Some(kind) = query.kind {
+                builder.push(" AND kind = ").push_bind(kind.as_str());
+            }
+            if let Some(status) = query.status {
+                builder.push(" AND status = ").push_bind(status.as_str());
+            }
+            if let Some(cursor) = &position {
+                builder
+                    .push(" AND (created_at_in_nanos > ")
+                    .push_bind(cursor.created_at_in_nanos)
+                    .push(" OR (created_at_in_nanos = ")
+                    .push_bind(cursor.created_at_in_nanos)
+                    .push(" AND id > ")
+                    .push_bind(cursor.id.to_string())
+                    .push("))");
+            }
+            builder
+                .push(" ORDER BY created_at_in_nanos, id LIMIT ")
+                .push_bind(SEARCH_PAGE_SIZE as i64);
+
+            let rows = builder
+                .build()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| format!("Failed to search transactions: {}", e))?;
+            let page = rows
+                .iter()
+                .map(get_transaction)
+                .collect::<Result<Vec<_>, _>>()?;
+            position = page.last().map(SearchCursor::of);
+            let exhausted = page.len() < SEARCH_PAGE_SIZE;
+            found.extend(page.into_iter().filter(|tx| query.matches(tx)));
+            if found.len() >= limit || exhausted {
+                found.truncate(limit);
+                return Ok(found);
+            }
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), String> {
+        let pending = pending_migrations(&self.pool).await?;
+        if !pending.is_empty() {
+            return Err(format!("SQLite migrations pending: {:?}", pending));
+        }
+        Ok(())
+    }
+
+    /// Waits for connections in use to be returned, then closes them all.
+    async fn close(&self) -> Result<(), String> {
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base::model::OperationKind;
+
+    use crate::{connect, migrate, SqliteConfig};
+
+    use super::*;
+
+    async fn setup() -> (SqliteStorage, Account) {
+        let pool = connect(SqliteConfig {
+            url: "sqlite::memory:".to_string(),
+        })
+        .await
+        .unwrap();
+        migrate(&pool).await.unwrap();
+        let storage = SqliteStorage::new(pool);
+        let mut account = Account::new(Uuid::new_v4(), &"BRL".to_string());
+        account.details.metadata.insert("team".into(), "ops".into());
+        storage.save_account(account.clone()).await.unwrap();
+        (storage, account)
+    }
+
+    fn deposit(account: &Account, key: &str, amount: i32) -> (Transaction, AccountUpdate) {
+        let mut tx = Transaction::new(
+            account,
+            key,
+            &BigDecimal::from(amount),
+            OperationKind::Deposit,
+        );
+        tx.details.metadata.insert("order".into(), key.into());
+        let update = account.apply(&tx);
+        (tx, update)
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let (storage, _) = setup().await;
+        base::storage::conformance::run(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn test_account_round_trip() {
+        let (storage, account) = setup().await;
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.uuid, account.uuid);
+        assert_eq!(stored.version, account.version);
+        assert_eq!(stored.details, account.details);
+        assert!(storage.get_account(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_read_transactions() {
+        let (storage, account) = setup().await;
+        let (tx, update) = deposit(&account, "idemp_1", 10);
+        storage
+            .save_transactions(vec![tx.clone()], vec![update])
+            .await
+            .unwrap();
+
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+        assert_eq!(stored.version, tx.account_version);
+        let history = storage
+            .get_transactions(account.uuid, account.created_at, Utc::now(), 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, tx.id);
+        assert_eq!(history[0].details, tx.details);
+        let by_id = storage.get_transaction(tx.id).await.unwrap().unwrap();
+        assert_eq!(by_id.kind, OperationKind::Deposit);
+    }
+
+    #[tokio::test]
+    async fn test_stale_version_writes_nothing() {
+        let (storage, account) = setup().await;
+        let (first, update) = deposit(&account, "idemp_1", 10);
+        storage
+            .save_transactions(vec![first], vec![update])
+            .await
+            .unwrap();
+
+        let (stale, update) = deposit(&account, "idemp_2", 10);
+        let result = storage
+            .save_transactions(vec![stale.clone()], vec![update])
+            .await;
+        assert_eq!(result.unwrap_err(), VERSION_CONFLICT);
+        assert!(storage.get_transaction(stale.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replay_returns_original_operation() {
+        let (storage, account) = setup().await;
+        let (tx, update) = deposit(&account, "idemp_1", 10);
+        storage
+            .save_transactions(vec![tx.clone()], vec![update])
+            .await
+            .unwrap();
+
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        let (replay, update) = deposit(&stored, "idemp_1", 10);
+        let result = storage
+            .save_transactions(vec![replay], vec![update])
+            .await
+            .unwrap();
+        assert_eq!(result[0].id, tx.id);
+        let stored = storage.get_account(account.uuid).await.unwrap().unwrap();
+        assert_eq!(stored.balance, BigDecimal::from(10));
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let (storage, mut account) = setup().await;
+        for (i, amount) in [5, 50, 500].into_iter().enumerate() {
+            let (tx, update) = deposit(&account, &format!("idemp_{}", i), amount);
+            storage
+                .save_transactions(vec![tx], vec![update.clone()])
+                .await
+                .unwrap();
+            account = update.account;
+        }
+
+        let query = TransactionSearchDTO {
+            metadata_key: Some("order".to_string()),
+            metadata_value: Some("idemp_1".to_string()),
+            ..TransactionSearchDTO::default()
+        };
+        let found = storage.search_transactions(&query, None, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].amount, BigDecimal::from(50));
+
+        let query = TransactionSearchDTO {
+            account_id: Some(account.uuid),
+            min_amount: Some(BigDecimal::from(10)),
+            ..TransactionSearchDTO::default()
+        };
+        let first = storage.search_transactions(&query, None, 1).await.unwrap();
+        assert_eq!(first[0].amount, BigDecimal::from(50));
+        let rest = storage
+            .search_transactions(&query, Some(SearchCursor::of(&first[0])), 10)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].amount, BigDecimal::from(500));
+    }
+
+    #[tokio::test]
+    async fn test_unmigrated_database_is_not_healthy() {
+        let pool = connect(SqliteConfig {
+            url: "sqlite::memory:".to_string(),
+        })
+        .await
+        .unwrap();
+        let storage = SqliteStorage::new(pool.clone());
+        let error = storage.health_check().await.unwrap_err();
+        assert_eq!(error, "SQLite migrations pending: [1]");
+
+        migrate(&pool).await.unwrap();
+        assert_eq!(storage.health_check().await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_closed_storage_refuses_calls() {
+        let (storage, account) = setup().await;
+        assert_eq!(storage.close().await, Ok(()));
+        assert!(storage.pool.is_closed());
+        assert!(storage.health_check().await.is_err());
+        let (tx, update) = deposit(&account, "idemp_1", 10);
+        assert!(storage
+            .save_transactions(vec![tx], vec![update])
+            .await
+            .is_err());
+    }
+}
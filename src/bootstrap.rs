@@ -1,69 +1,285 @@
-use std::{env, sync::Arc};
+use std::{sync::Arc, time::Duration};
 
 use base::{
-    storage::InMemoryStorage,
+    lock::AccountLocks,
+    shutdown::Workers,
+    storage::{
+        CacheConfig, CachedStorage, InMemoryStorage, MeteredStorage, Storage, TracedStorage,
+        WalConfig, WalStorage,
+    },
     use_case::{
-        CreateAccountUseCase, DepositUseCase, GetAccountByUuidUseCase, TransferUseCase,
-        WithdrawalUseCase,
+        BulkImportUseCase, CreateAccountUseCase, DepositUseCase, GenerateStatementUseCase,
+        GetAccountByUuidUseCase, GetTransactionByIdUseCase, GetTransferUseCase, HealthCheckUseCase,
+        MeteredUseCase, SearchTransactionsUseCase, TransferUseCase, UseCase, WithdrawalUseCase,
+        DEFAULT_HEALTH_CHECK_TIMEOUT, DEFAULT_IMPORT_CONCURRENCY,
     },
 };
-use cassandra_storage::{CassandraConfig, CassandraStorage};
-use dynamo_storage::DynamoStorage;
-use tokio::sync::Mutex;
 
-use crate::handler::AppState;
+use crate::{
+    config::{Backend, Config},
+    handler::AppState,
+};
 
-async fn _get_cassandra_storage() -> Result<Arc<Mutex<CassandraStorage>>, String> {
-    let contact_points = env::var("CASSANDRA_CONTACT_POINTS")
-        .map_err(|_| "CASSANDRA_CONTACT_POINTS must be set".to_string())?;
+#[cfg(feature = "cassandra_storage")]
+async fn get_cassandra_storage(
+    config: &Config,
+) -> Result<Arc<cassandra_storage::CassandraStorage>, String> {
+    use cassandra_storage::{CassandraConfig, CassandraStorage};
 
-    let config = CassandraConfig { contact_points };
-    let session = cassandra_storage::connect(config)
+    let config = CassandraConfig {
+        contact_points: config.cassandra.contact_points.clone().unwrap_or_default(),
+        keyspace: config.cassandra.keyspace.clone(),
+    };
+    let session = cassandra_storage::connect(&config)
         .await
         .map_err(|err| format!("Failed to connect to Cassandra: {}", err))?;
 
-    Ok(Arc::new(Mutex::new(CassandraStorage::new(Arc::new(
-        session,
-    )))))
+    Ok(Arc::new(CassandraStorage::new(
+        Arc::new(session),
+        &config.keyspace,
+    )))
+}
+
+/// Ends operations left pending by a writer that failed or crashed midway.
+#[cfg(feature = "cassandra_storage")]
+fn spawn_cassandra_recovery(
+    storage: &Arc<cassandra_storage::CassandraStorage>,
+    workers: &mut Workers,
+) {
+    let storage = Arc::clone(storage);
+    workers.spawn("cassandra_recovery", |stop| async move {
+        storage
+            .recover_every(cassandra_storage::DEFAULT_RECOVERY_INTERVAL, stop)
+            .await
+    });
 }
 
-async fn _get_dynamo_storage() -> Result<Arc<Mutex<DynamoStorage>>, String> {
-    let client = dynamo_storage::utils::connect()
+/// The table is created, and items written by older versions rewritten, with
+/// `ledgerctl --backend dynamo migrate`.
+#[cfg(feature = "dynamo_storage")]
+async fn get_dynamo_storage(config: &Config) -> Result<Arc<dynamo_storage::DynamoStorage>, String> {
+    use dynamo_storage::{DynamoConfig, DynamoStorage};
+
+    let config = DynamoConfig {
+        endpoint: Some(config.dynamo.endpoint.clone()).filter(|endpoint| !endpoint.is_empty()),
+        region: config.dynamo.region.clone(),
+        table: config.dynamo.table.clone(),
+    };
+    let client = dynamo_storage::utils::connect(&config)
         .await
         .map_err(|err| format!("Failed to connect to DynamoDB: {}", err))?;
 
-    // if first run, create the tables in local dynamo
-    // match utils::create_table(&client).await {
-    //     Ok(_) => println!("Tables created successfully"),
-    //     Err(_) => println!("Error creating tables"),
-    // };
+    Ok(Arc::new(DynamoStorage::new(
+        Arc::new(client),
+        &config.table,
+    )))
+}
+
+#[cfg(feature = "postgres_storage")]
+async fn get_postgres_storage(
+    config: &Config,
+) -> Result<Arc<postgres_storage::PostgresStorage>, String> {
+    let config = postgres_storage::PostgresConfig {
+        url: config.postgres.url.clone().unwrap_or_default(),
+        max_connections: config.postgres.max_connections,
+    };
+    let pool = postgres_storage::connect(config)
+        .await
+        .map_err(|err| format!("Failed to connect to Postgres: {}", err))?;
+    postgres_storage::migrate(&pool).await?;
+
+    Ok(Arc::new(postgres_storage::PostgresStorage::new(pool)))
+}
+
+#[cfg(feature = "sqlite_storage")]
+async fn get_sqlite_storage(config: &Config) -> Result<Arc<sqlite_storage::SqliteStorage>, String> {
+    let url = config.sqlite.url.clone().unwrap_or_default();
+    let pool = sqlite_storage::connect(sqlite_storage::SqliteConfig { url })
+        .await
+        .map_err(|err| format!("Failed to connect to SQLite: {}", err))?;
+    sqlite_storage::migrate(&pool).await?;
+
+    Ok(Arc::new(sqlite_storage::SqliteStorage::new(pool)))
+}
+
+async fn get_wal_storage(config: &Config) -> Result<Arc<WalStorage>, String> {
+    let config = WalConfig {
+        dir: config.wal.dir.clone().unwrap_or_default(),
+        compact_after_bytes: config.wal.compact_after_bytes,
+    };
+
+    Ok(Arc::new(WalStorage::open(config).await?))
+}
+
+fn spawn_wal_compactor(storage: &Arc<WalStorage>, config: &Config, workers: &mut Workers) {
+    let interval = Duration::from_secs(config.wal.compact_interval_secs);
+    if interval.is_zero() {
+        return;
+    }
+    let storage = Arc::clone(storage);
+    workers.spawn("wal_compactor", |stop| async move {
+        storage.compact_every(interval, stop).await
+    });
+}
+
+fn account_cache_config(config: &Config) -> CacheConfig {
+    let settings = &config.account_cache;
+    CacheConfig {
+        capacity: settings.capacity,
+        ttl: Duration::from_millis(settings.ttl_ms),
+        negative_ttl: settings.negative_ttl_ms.map(Duration::from_millis),
+    }
+}
+
+fn metered<I: Send + 'static, O: Send + 'static>(
+    name: &'static str,
+    use_case: Arc<dyn UseCase<I, O>>,
+) -> Arc<dyn UseCase<I, O>> {
+    Arc::new(MeteredUseCase::new(name, use_case))
+}
 
-    Ok(Arc::new(Mutex::new(DynamoStorage::new(Arc::new(client)))))
+/// Builds the storage chosen by `config.storage.backend` and the use cases on
+/// top of it, and returns the storage too so it can be closed on shutdown.
+/// Workers the backend needs, such as the WAL compactor, join `workers`.
+/// Fails when the backend cannot be reached or migrated.
+pub async fn bootstrap(
+    config: &Config,
+    workers: &mut Workers,
+) -> Result<(AppState, Arc<dyn Storage>), String> {
+    let state = match config.storage.backend {
+        Backend::Memory => build_state(Arc::new(InMemoryStorage::new()), config),
+        #[cfg(feature = "cassandra_storage")]
+        Backend::Cassandra => {
+            let storage = get_cassandra_storage(config).await?;
+            spawn_cassandra_recovery(&storage, workers);
+            build_state(storage, config)
+        }
+        #[cfg(feature = "dynamo_storage")]
+        Backend::Dynamo => build_state(get_dynamo_storage(config).await?, config),
+        #[cfg(feature = "postgres_storage")]
+        Backend::Postgres => build_state(get_postgres_storage(config).await?, config),
+        #[cfg(feature = "sqlite_storage")]
+        Backend::Sqlite => build_state(get_sqlite_storage(config).await?, config),
+        Backend::Wal => {
+            let storage = get_wal_storage(config).await?;
+            spawn_wal_compactor(&storage, config, workers);
+            build_state(storage, config)
+        }
+        // `Config::load` already rejects backends that are not compiled in.
+        #[allow(unreachable_patterns)]
+        backend => return Err(backend.not_compiled_in()),
+    };
+    Ok(state)
 }
 
-async fn get_in_memory_storage() -> Arc<Mutex<InMemoryStorage>> {
-    Arc::new(Mutex::new(InMemoryStorage::new()))
+/// Closes the storage built by `bootstrap`, then drops it on a blocking
+/// thread: the Cassandra driver closes its session, synchronously, when the
+/// last handle is freed. Returns whether that was the last handle; any other
+/// keeps the connections open until it is dropped too.
+pub async fn close_storage(storage: Arc<dyn Storage>) -> bool {
+    if let Err(err) = storage.close().await {
+        tracing::warn!("Failed to close the storage: {}", err);
+    }
+    let handles = Arc::strong_count(&storage);
+    if handles > 1 {
+        tracing::warn!(handles, "The storage is still in use after closing it");
+    }
+    let _ = tokio::task::spawn_blocking(move || drop(storage)).await;
+    handles == 1
 }
 
-pub async fn bootstrap() -> AppState {
-    // Uncomment to use dynamo storage
-    // let storage = match get_dynamo_storage().await {
-    //     Ok(storage) => storage,
-    //     Err(err) => panic!("{}", err),
-    // };
-    let storage = get_in_memory_storage().await;
-
-    let create_account_uc = Arc::new(CreateAccountUseCase::new(&storage));
-    let get_account_by_id_uc = Arc::new(GetAccountByUuidUseCase::new(&storage));
-    let deposit_uc = Arc::new(DepositUseCase::new(&storage));
-    let withdrawal_uc = Arc::new(WithdrawalUseCase::new(&storage));
-    let transfer_uc = Arc::new(TransferUseCase::new(&storage));
-
-    AppState::new(
+fn build_state<S: Storage + 'static>(
+    storage: Arc<S>,
+    config: &Config,
+) -> (AppState, Arc<dyn Storage>) {
+    let storage = Arc::new(MeteredStorage::new(
+        &storage,
+        config.storage.backend.as_str(),
+    ));
+    let storage = Arc::new(CachedStorage::new(&storage, account_cache_config(config)));
+    let storage = Arc::new(TracedStorage::new(&storage));
+
+    let locks = Arc::new(AccountLocks::default());
+
+    let create_account_uc = metered(
+        "create_account",
+        Arc::new(CreateAccountUseCase::new(&storage)),
+    );
+    let get_account_by_id_uc = metered(
+        "get_account",
+        Arc::new(GetAccountByUuidUseCase::new(&storage)),
+    );
+    let deposit_uc = metered("deposit", Arc::new(DepositUseCase::new(&storage, &locks)));
+    let withdrawal_uc = metered(
+        "withdrawal",
+        Arc::new(WithdrawalUseCase::new(&storage, &locks)),
+    );
+    let transfer_uc = metered("transfer", Arc::new(TransferUseCase::new(&storage, &locks)));
+    let statement_uc = metered(
+        "generate_statement",
+        Arc::new(GenerateStatementUseCase::new(&storage)),
+    );
+    let import_uc = metered(
+        "bulk_import",
+        Arc::new(BulkImportUseCase::new(
+            create_account_uc.clone(),
+            deposit_uc.clone(),
+            withdrawal_uc.clone(),
+            transfer_uc.clone(),
+            DEFAULT_IMPORT_CONCURRENCY,
+        )),
+    );
+    let search_transactions_uc = metered(
+        "search_transactions",
+        Arc::new(SearchTransactionsUseCase::new(&storage)),
+    );
+    let get_transaction_by_id_uc = metered(
+        "get_transaction",
+        Arc::new(GetTransactionByIdUseCase::new(&storage)),
+    );
+    let get_transfer_uc = metered("get_transfer", Arc::new(GetTransferUseCase::new(&storage)));
+    let health_check_uc = Arc::new(HealthCheckUseCase::new(
+        &storage,
+        DEFAULT_HEALTH_CHECK_TIMEOUT,
+    ));
+
+    let state = AppState::new(
         create_account_uc,
         get_account_by_id_uc,
         deposit_uc,
         withdrawal_uc,
         transfer_uc,
-    )
+        statement_uc,
+        import_uc,
+        search_transactions_uc,
+        get_transaction_by_id_uc,
+        get_transfer_uc,
+        health_check_uc,
+    );
+    (state, storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wal_backend_shuts_down() {
+        let dir = std::env::temp_dir().join(format!("mini_ledger_{}", Uuid::new_v4()));
+        let mut config = Config::default();
+        config.storage.backend = Backend::Wal;
+        config.wal.dir = Some(dir.clone());
+        let mut workers = Workers::new();
+
+        let (state, storage) = bootstrap(&config, &mut workers).await.unwrap();
+        drop(state);
+
+        // The compactor stops on its signal rather than being aborted, and
+        // lets go of the storage, so closing it releases the last handle.
+        assert_eq!(workers.shutdown(Duration::from_secs(1)).await, 0);
+        assert!(close_storage(storage).await);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
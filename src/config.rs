@@ -0,0 +1,748 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use base::storage::{
+    wal::{DEFAULT_COMPACT_AFTER_BYTES, DEFAULT_COMPACT_INTERVAL},
+    CacheConfig,
+};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+use crate::import::ImportArgs;
+
+/// Env var naming the config file when `--config` is not given.
+pub const CONFIG_ENV: &str = "LEDGER_CONFIG";
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_KEYSPACE: &str = "mini_ledger";
+const DEFAULT_DYNAMO_ENDPOINT: &str = "http://localhost:8000";
+const DEFAULT_DYNAMO_REGION: &str = "us-east-1";
+const DEFAULT_DYNAMO_TABLE: &str = "mini_ledger";
+const DEFAULT_POSTGRES_MAX_CONNECTIONS: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Memory,
+    Cassandra,
+    Dynamo,
+    Postgres,
+    Sqlite,
+    Wal,
+}
+
+impl Backend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Backend::Memory => "memory",
+            Backend::Cassandra => "cassandra",
+            Backend::Dynamo => "dynamo",
+            Backend::Postgres => "postgres",
+            Backend::Sqlite => "sqlite",
+            Backend::Wal => "wal",
+        }
+    }
+
+    /// The cargo feature that compiles the backend in, if it needs one.
+    fn feature(&self) -> Option<&'static str> {
+        match self {
+            Backend::Memory | Backend::Wal => None,
+            Backend::Cassandra => Some("cassandra_storage"),
+            Backend::Dynamo => Some("dynamo_storage"),
+            Backend::Postgres => Some("postgres_storage"),
+            Backend::Sqlite => Some("sqlite_storage"),
+        }
+    }
+
+    pub fn is_compiled_in(&self) -> bool {
+        match self {
+            Backend::Memory | Backend::Wal => true,
+            Backend::Cassandra => cfg!(feature = "cassandra_storage"),
+            Backend::Dynamo => cfg!(feature = "dynamo_storage"),
+            Backend::Postgres => cfg!(feature = "postgres_storage"),
+            Backend::Sqlite => cfg!(feature = "sqlite_storage"),
+        }
+    }
+
+    pub fn not_compiled_in(&self) -> String {
+        let compiled_in: Vec<&str> = Backend::value_variants()
+            .iter()
+            .filter(|backend| backend.is_compiled_in())
+            .map(Backend::as_str)
+            .collect();
+        format!(
+            "The {} backend is not compiled in; rebuild with --features {} \
+             (compiled in: {})",
+            self,
+            self.feature().unwrap_or_default(),
+            compiled_in.join(", ")
+        )
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Defaults to one worker per physical core.
+    pub workers: Option<usize>,
+    /// How long in-flight requests, and then background workers, get to
+    /// finish once shutdown starts.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            workers: None,
+            shutdown_timeout_secs: DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: Backend,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CassandraSettings {
+    pub contact_points: Option<String>,
+    pub keyspace: String,
+}
+
+impl Default for CassandraSettings {
+    fn default() -> Self {
+        CassandraSettings {
+            contact_points: None,
+            keyspace: DEFAULT_KEYSPACE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DynamoSettings {
+    /// DynamoDB Local by default. An empty endpoint uses the regional AWS
+    /// endpoint and the default credential chain.
+    pub endpoint: String,
+    pub region: String,
+    pub table: String,
+}
+
+impl Default for DynamoSettings {
+    fn default() -> Self {
+        DynamoSettings {
+            endpoint: DEFAULT_DYNAMO_ENDPOINT.to_string(),
+            region: DEFAULT_DYNAMO_REGION.to_string(),
+            table: DEFAULT_DYNAMO_TABLE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostgresSettings {
+    pub url: Option<String>,
+    pub max_connections: u32,
+}
+
+impl Default for PostgresSettings {
+    fn default() -> Self {
+        PostgresSettings {
+            url: None,
+            max_connections: DEFAULT_POSTGRES_MAX_CONNECTIONS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteSettings {
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalSettings {
+    pub dir: Option<PathBuf>,
+    pub compact_after_bytes: u64,
+    /// Periodic compaction is off while this is 0.
+    pub compact_interval_secs: u64,
+}
+
+impl Default for WalSettings {
+    fn default() -> Self {
+        WalSettings {
+            dir: None,
+            compact_after_bytes: DEFAULT_COMPACT_AFTER_BYTES,
+            compact_interval_secs: DEFAULT_COMPACT_INTERVAL.as_secs(),
+        }
+    }
+}
+
+/// The account cache is off while `capacity` is 0.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountCacheSettings {
+    pub capacity: usize,
+    pub ttl_ms: u64,
+    pub negative_ttl_ms: Option<u64>,
+}
+
+impl Default for AccountCacheSettings {
+    fn default() -> Self {
+        AccountCacheSettings {
+            capacity: 0,
+            ttl_ms: CacheConfig::default().ttl.as_millis() as u64,
+            negative_ttl_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Verbosity is not among these: it is read from `RUST_LOG`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySettings {
+    pub log_format: LogFormat,
+    /// Base URL of an OTLP/HTTP collector; spans are not exported when unset.
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Runtime configuration of the server, layered from defaults, a TOML file
+/// (`--config` or `LEDGER_CONFIG`), env vars and flags, in that order.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub cassandra: CassandraSettings,
+    pub dynamo: DynamoSettings,
+    pub postgres: PostgresSettings,
+    pub sqlite: SqliteSettings,
+    pub wal: WalSettings,
+    pub account_cache: AccountCacheSettings,
+    pub telemetry: TelemetrySettings,
+}
+
+/// Serve the mini ledger API, or run a one-off command against its storage.
+///
+/// Each setting can be given in the config file, as an env var or as a flag;
+/// flags override env vars, which override the file.
+#[derive(Debug, Parser)]
+#[command(name = "mini_ledger")]
+pub struct Cli {
+    /// TOML file with the settings, see mini_ledger.example.toml
+    #[arg(long, env = CONFIG_ENV)]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    overrides: Overrides,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Import operations from a JSONL or CSV file instead of serving
+    Import(ImportArgs),
+}
+
+/// Settings given as env vars or flags, each overriding the config file.
+#[derive(Debug, Args)]
+struct Overrides {
+    #[arg(long, env = "HOST")]
+    host: Option<String>,
+    #[arg(long, env = "PORT")]
+    port: Option<u16>,
+    /// One per physical core when unset
+    #[arg(long, env = "WORKERS")]
+    workers: Option<usize>,
+    /// Time in-flight requests, and then background workers, get to finish
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECS")]
+    shutdown_timeout_secs: Option<u64>,
+    #[arg(long, env = "STORAGE_BACKEND", value_enum)]
+    backend: Option<Backend>,
+    #[arg(long, env = "CASSANDRA_CONTACT_POINTS")]
+    cassandra_contact_points: Option<String>,
+    #[arg(long, env = "CASSANDRA_KEYSPACE")]
+    cassandra_keyspace: Option<String>,
+    /// Empty for the regional AWS endpoint
+    #[arg(long, env = "DYNAMO_ENDPOINT")]
+    dynamo_endpoint: Option<String>,
+    #[arg(long, env = "DYNAMO_REGION")]
+    dynamo_region: Option<String>,
+    #[arg(long, env = "DYNAMO_TABLE")]
+    dynamo_table: Option<String>,
+    #[arg(long, env = "POSTGRES_URL")]
+    postgres_url: Option<String>,
+    #[arg(long, env = "POSTGRES_MAX_CONNECTIONS")]
+    postgres_max_connections: Option<u32>,
+    #[arg(long, env = "SQLITE_URL")]
+    sqlite_url: Option<String>,
+    #[arg(long, env = "WAL_DIR")]
+    wal_dir: Option<PathBuf>,
+    #[arg(long, env = "WAL_COMPACT_AFTER_BYTES")]
+    wal_compact_after_bytes: Option<u64>,
+    /// 0 disables periodic compaction
+    #[arg(long, env = "WAL_COMPACT_INTERVAL_SECS")]
+    wal_compact_interval_secs: Option<u64>,
+    /// 0 disables the cache
+    #[arg(long, env = "ACCOUNT_CACHE_CAPACITY")]
+    account_cache_capacity: Option<usize>,
+    #[arg(long, env = "ACCOUNT_CACHE_TTL_MS")]
+    account_cache_ttl_ms: Option<u64>,
+    /// Missing accounts are not cached when unset
+    #[arg(long, env = "ACCOUNT_CACHE_NEGATIVE_TTL_MS")]
+    account_cache_negative_ttl_ms: Option<u64>,
+    #[arg(long, env = "LOG_FORMAT", value_enum)]
+    log_format: Option<LogFormat>,
+    /// Spans are not exported when unset
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+}
+
+impl Overrides {
+    fn apply(self, config: &mut Config) {
+        fn set<T>(setting: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+        set(&mut config.server.host, self.host);
+        set(&mut config.server.port, self.port);
+        set(&mut config.server.workers, self.workers.map(Some));
+        set(
+            &mut config.server.shutdown_timeout_secs,
+            self.shutdown_timeout_secs,
+        );
+        set(&mut config.storage.backend, self.backend);
+        set(
+            &mut config.cassandra.contact_points,
+            self.cassandra_contact_points.map(Some),
+        );
+        set(&mut config.cassandra.keyspace, self.cassandra_keyspace);
+        set(&mut config.dynamo.endpoint, self.dynamo_endpoint);
+        set(&mut config.dynamo.region, self.dynamo_region);
+        set(&mut config.dynamo.table, self.dynamo_table);
+        set(&mut config.postgres.url, self.postgres_url.map(Some));
+        set(
+            &mut config.postgres.max_connections,
+            self.postgres_max_connections,
+        );
+        set(&mut config.sqlite.url, self.sqlite_url.map(Some));
+        set(&mut config.wal.dir, self.wal_dir.map(Some));
+        set(
+            &mut config.wal.compact_after_bytes,
+            self.wal_compact_after_bytes,
+        );
+        set(
+            &mut config.wal.compact_interval_secs,
+            self.wal_compact_interval_secs,
+        );
+        set(
+            &mut config.account_cache.capacity,
+            self.account_cache_capacity,
+        );
+        set(&mut config.account_cache.ttl_ms, self.account_cache_ttl_ms);
+        set(
+            &mut config.account_cache.negative_ttl_ms,
+            self.account_cache_negative_ttl_ms.map(Some),
+        );
+        set(&mut config.telemetry.log_format, self.log_format);
+        set(
+            &mut config.telemetry.otlp_endpoint,
+            self.otlp_endpoint.map(Some),
+        );
+    }
+}
+
+/// How to set the backend setting `key` outside the file, for error messages,
+/// e.g. `wal.dir (WAL_DIR or --wal-dir)`.
+fn hint(key: &str) -> String {
+    let command = Cli::command();
+    let arg = command
+        .get_arguments()
+        .find(|arg| arg.get_id() == key.replace('.', "_").as_str())
+        .expect("every required key has a flag");
+    format!(
+        "{} ({} or --{})",
+        key,
+        arg.get_env()
+            .and_then(|env| env.to_str())
+            .unwrap_or_default(),
+        arg.get_long().unwrap_or_default()
+    )
+}
+
+impl Config {
+    /// Loads the config for the parsed command line and returns it with the
+    /// subcommand, if any. Fails on unreadable files, unknown keys, and
+    /// settings the chosen backend is missing; flags and env vars that do not
+    /// parse were already rejected by clap.
+    pub fn load(cli: Cli) -> Result<(Config, Option<Command>), String> {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        cli.overrides.apply(&mut config);
+        config.validate()?;
+        Ok((config, cli.command))
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read config file {}: {}", path.display(), err))?;
+        toml::from_str(&contents)
+            .map_err(|err| format!("Invalid config file {}: {}", path.display(), err))
+    }
+
+    /// Checks the server settings and those of the chosen backend only.
+    fn validate(&self) -> Result<(), String> {
+        if self.server.workers == Some(0) {
+            return Err("server.workers must be at least 1".to_string());
+        }
+        if !self.storage.backend.is_compiled_in() {
+            return Err(self.storage.backend.not_compiled_in());
+        }
+        let required = |value: Option<&str>, key: &str| match value {
+            Some(value) if !value.is_empty() => Ok(()),
+            _ => Err(format!(
+                "The {} backend requires {}",
+                self.storage.backend,
+                hint(key)
+            )),
+        };
+        match self.storage.backend {
+            Backend::Memory => {}
+            Backend::Cassandra => {
+                required(
+                    self.cassandra.contact_points.as_deref(),
+                    "cassandra.contact_points",
+                )?;
+                let keyspace = &self.cassandra.keyspace;
+                let valid = keyspace.len() <= 48
+                    && keyspace.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && keyspace
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(format!(
+                        "Invalid cassandra.keyspace {:?}: expected up to 48 letters, digits or \
+                         underscores, starting with a letter",
+                        keyspace
+                    ));
+                }
+            }
+            Backend::Dynamo => {
+                required(Some(&self.dynamo.region), "dynamo.region")?;
+                let table = &self.dynamo.table;
+                let valid = (3..=255).contains(&table.len())
+                    && table
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c));
+                if !valid {
+                    return Err(format!(
+                        "Invalid dynamo.table {:?}: expected 3 to 255 letters, digits, \
+                         underscores, dashes or dots",
+                        table
+                    ));
+                }
+            }
+            Backend::Postgres => {
+                required(self.postgres.url.as_deref(), "postgres.url")?;
+                if self.postgres.max_connections == 0 {
+                    return Err("postgres.max_connections must be at least 1".to_string());
+                }
+            }
+            Backend::Sqlite => required(self.sqlite.url.as_deref(), "sqlite.url")?,
+            Backend::Wal => required(
+                self.wal.dir.as_ref().and_then(|dir| dir.to_str()),
+                "wal.dir",
+            )?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, iter,
+        sync::{Mutex, PoisonError},
+    };
+
+    use clap::error::ErrorKind;
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Env vars are process-wide, so tests setting them take turns.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Parses `cli` with only `vars` set among the env vars clap reads.
+    fn parse(cli: &[&str], vars: &[(&str, &str)]) -> Result<Cli, clap::Error> {
+        let _guard = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        for arg in Cli::command().get_arguments() {
+            if let Some(name) = arg.get_env() {
+                env::remove_var(name);
+            }
+        }
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let cli = Cli::try_parse_from(iter::once("mini_ledger").chain(cli.iter().copied()));
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+        cli
+    }
+
+    fn load(cli: &[&str], vars: &[(&str, &str)]) -> Result<(Config, Option<Command>), String> {
+        Config::load(parse(cli, vars).map_err(|err| err.to_string())?)
+    }
+
+    fn write_config(contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mini_ledger_{}.toml", Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let (config, command) = load(&[], &[]).unwrap();
+        assert_eq!(config, Config::default());
+        assert_eq!(config.server.host, "127.0.0.1");
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.storage.backend, Backend::Memory);
+        assert!(command.is_none());
+    }
+
+    #[test]
+    fn test_flags_override_env_and_env_overrides_file() {
+        let path = write_config(
+            r#"
+            [server]
+            host = "0.0.0.0"
+            port = 9000
+            workers = 2
+
+            [storage]
+            backend = "wal"
+
+            [wal]
+            dir = "/tmp/wal"
+
+            [dynamo]
+            region = "sa-east-1"
+            table = "from_file"
+            "#,
+        );
+        let (config, _) = load(
+            &[
+                "--config",
+                &path,
+                "--port=9002",
+                "--dynamo-table",
+                "from_flag",
+            ],
+            &[
+                ("PORT", "9001"),
+                ("DYNAMO_TABLE", "from_env"),
+                ("WORKERS", "4"),
+            ],
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 9002);
+        assert_eq!(config.server.workers, Some(4));
+        assert_eq!(config.storage.backend, Backend::Wal);
+        assert_eq!(config.dynamo.region, "sa-east-1");
+        assert_eq!(config.dynamo.table, "from_flag");
+        assert_eq!(config.dynamo.endpoint, "http://localhost:8000");
+    }
+
+    #[test]
+    fn test_telemetry_settings() {
+        let path = write_config(
+            "[telemetry]\nlog_format = \"json\"\notlp_endpoint = \"http://file:4318\"\n",
+        );
+        let (config, _) = load(&["--config", &path], &[]).unwrap();
+        assert_eq!(config.telemetry.log_format, LogFormat::Json);
+        assert_eq!(
+            config.telemetry.otlp_endpoint.as_deref(),
+            Some("http://file:4318")
+        );
+
+        let (config, _) = load(
+            &["--config", &path, "--log-format", "text"],
+            &[("OTEL_EXPORTER_OTLP_ENDPOINT", "http://env:4318")],
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.telemetry.log_format, LogFormat::Text);
+        assert_eq!(
+            config.telemetry.otlp_endpoint.as_deref(),
+            Some("http://env:4318")
+        );
+
+        let error = load(&[], &[("LOG_FORMAT", "yaml")]).unwrap_err();
+        assert!(error.contains("yaml"));
+    }
+
+    #[test]
+    fn test_config_file_from_env() {
+        let path = write_config("[storage]\nbackend = \"wal\"\n[wal]\ndir = \"/tmp/wal\"\n");
+        let (config, _) = load(&[], &[(CONFIG_ENV, &path)]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.storage.backend, Backend::Wal);
+        assert_eq!(config.wal.dir, Some(PathBuf::from("/tmp/wal")));
+    }
+
+    #[test]
+    fn test_subcommand_is_returned() {
+        let (config, command) = load(
+            &[
+                "--backend",
+                "memory",
+                "import",
+                "ops.jsonl",
+                "--format",
+                "csv",
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.storage.backend, Backend::Memory);
+        assert!(matches!(command, Some(Command::Import(_))));
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let kind = |cli: &[&str], vars: &[(&str, &str)]| parse(cli, vars).unwrap_err().kind();
+        assert_eq!(kind(&[], &[("PORT", "http")]), ErrorKind::ValueValidation);
+        assert_eq!(kind(&["--backend", "mongo"], &[]), ErrorKind::InvalidValue);
+        assert_eq!(
+            kind(&["--bind", "0.0.0.0"], &[]),
+            ErrorKind::UnknownArgument
+        );
+        assert_eq!(kind(&["--port"], &[]), ErrorKind::InvalidValue);
+        assert_eq!(
+            load(&["--workers", "0"], &[]).unwrap_err(),
+            "server.workers must be at least 1"
+        );
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let error = load(&["--config", "/nonexistent/ledger.toml"], &[]).unwrap_err();
+        assert!(error.starts_with("Failed to read config file /nonexistent/ledger.toml"));
+
+        let path = write_config("[server]\nbind = \"0.0.0.0\"\n");
+        let error = load(&["--config", &path], &[]).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.contains("unknown field `bind`"), "{}", error);
+    }
+
+    #[test]
+    fn test_backend_requirements() {
+        assert_eq!(
+            load(&["--backend", "wal"], &[]).unwrap_err(),
+            "The wal backend requires wal.dir (WAL_DIR or --wal-dir)"
+        );
+        // Settings of other backends are not checked.
+        assert!(load(&["--cassandra-keyspace", "ledger;drop"], &[]).is_ok());
+    }
+
+    #[cfg(feature = "cassandra_storage")]
+    #[test]
+    fn test_cassandra_requirements() {
+        assert_eq!(
+            load(&["--backend", "cassandra"], &[]).unwrap_err(),
+            "The cassandra backend requires cassandra.contact_points \
+             (CASSANDRA_CONTACT_POINTS or --cassandra-contact-points)"
+        );
+        let error = load(
+            &[
+                "--backend",
+                "cassandra",
+                "--cassandra-keyspace",
+                "ledger;drop",
+            ],
+            &[("CASSANDRA_CONTACT_POINTS", "127.0.0.1")],
+        )
+        .unwrap_err();
+        assert!(error.starts_with(r#"Invalid cassandra.keyspace "ledger;drop""#));
+    }
+
+    #[cfg(feature = "dynamo_storage")]
+    #[test]
+    fn test_dynamo_requirements() {
+        let error = load(&["--backend", "dynamo", "--dynamo-table", "ab"], &[]).unwrap_err();
+        assert!(error.starts_with(r#"Invalid dynamo.table "ab""#));
+    }
+
+    #[cfg(feature = "postgres_storage")]
+    #[test]
+    fn test_postgres_requirements() {
+        assert_eq!(
+            load(&["--backend", "postgres"], &[]).unwrap_err(),
+            "The postgres backend requires postgres.url (POSTGRES_URL or --postgres-url)"
+        );
+    }
+
+    #[test]
+    fn test_backend_must_be_compiled_in() {
+        let vars = [
+            ("CASSANDRA_CONTACT_POINTS", "127.0.0.1"),
+            ("POSTGRES_URL", "postgres://localhost/ledger"),
+            ("SQLITE_URL", "sqlite://ledger.db"),
+            ("WAL_DIR", "/tmp/wal"),
+        ];
+        for backend in Backend::value_variants() {
+            let result = load(&["--backend", backend.as_str()], &vars);
+            if backend.is_compiled_in() {
+                assert!(result.is_ok(), "{}: {:?}", backend, result);
+            } else {
+                let error = result.unwrap_err();
+                assert!(error.starts_with(&format!(
+                    "The {} backend is not compiled in; rebuild with --features {}_storage",
+                    backend, backend
+                )));
+                assert!(error.contains("memory"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_example_file() {
+        let config: Config = toml::from_str(include_str!("../mini_ledger.example.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+}
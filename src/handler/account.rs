@@ -1,10 +1,11 @@
 use actix_web::{get, post, web, HttpResponse, Responder};
-use base::dto::account::AccountCreationDTO;
+use base::{dto::account::AccountCreationDTO, model::ACCOUNT_ALREADY_EXISTS};
 use uuid::Uuid;
 
-use super::state::AppState;
+use super::{state::AppState, version::etag};
 
 #[post("/accounts")]
+#[tracing::instrument(skip_all)]
 pub async fn create_account(
     state: web::Data<AppState>,
     account_creation_request: web::Json<AccountCreationDTO>,
@@ -12,11 +13,28 @@ pub async fn create_account(
     let input = account_creation_request.into_inner();
     let created_account_result = state.create_account_uc.execute(input).await;
     created_account_result
-        .map(|created_account| HttpResponse::Created().json(created_account))
-        .unwrap_or_else(|error| HttpResponse::BadRequest().body(error))
+        .map(|result| {
+            // A replay of the request that created the account gets it as is.
+            let mut response = if result.created {
+                HttpResponse::Created()
+            } else {
+                HttpResponse::Ok()
+            };
+            response
+                .insert_header(etag(result.account.version))
+                .json(result.account)
+        })
+        .unwrap_or_else(|error| {
+            if error == ACCOUNT_ALREADY_EXISTS {
+                HttpResponse::Conflict().body(error)
+            } else {
+                HttpResponse::BadRequest().body(error)
+            }
+        })
 }
 
 #[get("/accounts/{param_uuid}")]
+#[tracing::instrument(skip_all)]
 pub async fn get_account_by_id(
     state: web::Data<AppState>,
     param_uuid: web::Path<Uuid>,
@@ -26,6 +44,11 @@ pub async fn get_account_by_id(
         .execute(param_uuid.into_inner())
         .await;
     account_result
-        .map(|account| HttpResponse::Ok().json(account))
+        .map(|account| match &account {
+            Some(found) => HttpResponse::Ok()
+                .insert_header(etag(found.version))
+                .json(account),
+            None => HttpResponse::Ok().json(account),
+        })
         .unwrap_or_else(|error| HttpResponse::NotFound().body(error))
 }
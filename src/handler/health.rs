@@ -0,0 +1,21 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::json;
+
+use super::state::AppState;
+
+/// Liveness: the process is up and serving requests.
+#[get("/health/live")]
+pub async fn get_liveness() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Readiness: the storage is reachable and migrated, so traffic can be sent.
+#[get("/health/ready")]
+#[tracing::instrument(skip_all)]
+pub async fn get_readiness(state: web::Data<AppState>) -> impl Responder {
+    match state.health_check_uc.execute(()).await {
+        Ok(()) => HttpResponse::Ok().json(json!({ "status": "ready" })),
+        Err(error) => HttpResponse::ServiceUnavailable()
+            .json(json!({ "status": "unavailable", "error": error })),
+    }
+}
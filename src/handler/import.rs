@@ -0,0 +1,44 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use base::dto::import::{BulkImportDTO, ImportFormat};
+use futures::StreamExt;
+use serde::Deserialize;
+
+use super::state::AppState;
+
+pub const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub format: ImportFormat,
+    #[serde(default)]
+    pub resume_from: usize,
+}
+
+#[post("/imports")]
+#[tracing::instrument(skip_all)]
+pub async fn create_import(
+    state: web::Data<AppState>,
+    query: web::Query<ImportQuery>,
+    body: String,
+) -> impl Responder {
+    let query = query.into_inner();
+    let input = BulkImportDTO {
+        content: body,
+        format: query.format,
+        resume_from: query.resume_from,
+    };
+    let report = match state.import_uc.execute(input).await {
+        Ok(report) => report,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+
+    let lines = report.map(|result| {
+        serde_json::to_string(&result)
+            .map(|line| web::Bytes::from(line + "\n"))
+            .map_err(actix_web::error::ErrorInternalServerError)
+    });
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines)
+}
@@ -0,0 +1,98 @@
+use std::{sync::LazyLock, time::Instant};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    http::header::ContentType,
+    middleware::Next,
+    Error, HttpResponse, Responder,
+};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec, IntCounterVec,
+    TextEncoder,
+};
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "Requests served, by route and status",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Request latency, by route and status",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+/// Counts the request and records its latency. Routes are labelled by their
+/// pattern, e.g. `/accounts/{param_uuid}`, so ids do not become labels.
+pub async fn track_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let response = next.call(req).await?;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+    Ok(response)
+}
+
+/// Every registered metric in the Prometheus text format.
+#[get("/metrics")]
+pub async fn get_metrics() -> impl Responder {
+    let mut body = vec![];
+    match TextEncoder::new().encode(&prometheus::gather(), &mut body) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(body),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{middleware::from_fn, test, web, App};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_counts_requests_by_route() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(track_request))
+                .service(get_metrics)
+                .route("/ping/{id}", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        for id in ["a", "b"] {
+            let req = test::TestRequest::get().uri(&format!("/ping/{}", id));
+            test::call_service(&app, req.to_request()).await;
+        }
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let line = body
+            .lines()
+            .find(|line| line.starts_with("http_requests_total") && line.contains("/ping/{id}"))
+            .unwrap();
+        assert!(line.contains(r#"status="200""#));
+        assert!(line.ends_with(" 2"));
+    }
+}
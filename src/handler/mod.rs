@@ -1,5 +1,11 @@
 pub mod account;
+pub mod health;
+pub mod import;
+pub mod metrics;
+pub mod request_id;
 pub mod state;
+pub mod statement;
 pub mod transaction;
+pub mod version;
 
 pub use state::AppState;
@@ -0,0 +1,92 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    Error,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Takes the caller's `X-Request-Id`, or generates one when it is missing or
+/// unusable, and echoes it on the response.
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Runs the request inside an `http_request` span carrying its request id,
+/// so every use case and storage span below it can be correlated, and logs
+/// the outcome once the response is ready.
+pub async fn trace_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = request_id(&req);
+    let span = tracing::info_span!(
+        "http_request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+    let start = Instant::now();
+    let mut response = next.call(req).instrument(span.clone()).await?;
+
+    tracing::info!(
+        parent: &span,
+        status = response.status().as_u16(),
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "request completed"
+    );
+    let value = HeaderValue::from_str(&request_id).expect("request ids are visible ASCII");
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{middleware::from_fn, test, web, App, HttpResponse};
+
+    use super::*;
+
+    async fn call(header: Option<&str>) -> String {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(trace_request))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let mut req = test::TestRequest::get().uri("/");
+        if let Some(header) = header {
+            req = req.insert_header((REQUEST_ID_HEADER, header));
+        }
+        let response = test::call_service(&app, req.to_request()).await;
+        let id = response.headers().get(REQUEST_ID_HEADER).unwrap();
+        id.to_str().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_propagates_request_id() {
+        assert_eq!(call(Some("abc-123")).await, "abc-123");
+    }
+
+    #[actix_web::test]
+    async fn test_generates_missing_request_id() {
+        let generated = call(None).await;
+        assert!(Uuid::parse_str(&generated).is_ok());
+        assert_ne!(call(None).await, generated);
+
+        let too_long = "x".repeat(MAX_REQUEST_ID_LEN + 1);
+        assert_ne!(call(Some(&too_long)).await, too_long);
+    }
+}
@@ -2,30 +2,46 @@ use std::sync::Arc;
 
 use base::{
     dto::{
-        account::AccountCreationDTO,
+        account::{AccountCreationDTO, AccountCreationResultDTO},
+        import::BulkImportDTO,
+        search::TransactionSearchDTO,
+        statement::StatementRequestDTO,
         transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
     },
-    model::{Account, Transaction},
+    model::{Account, ImportReport, Statement, Transaction, TransactionPage, Transfer},
     use_case::UseCase,
 };
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub create_account_uc: Arc<dyn UseCase<AccountCreationDTO, Account>>,
+    pub create_account_uc: Arc<dyn UseCase<AccountCreationDTO, AccountCreationResultDTO>>,
     pub get_account_by_id_uc: Arc<dyn UseCase<Uuid, Option<Account>>>,
     pub deposit_uc: Arc<dyn UseCase<DepositTransactionDTO, Vec<Transaction>>>,
     pub withdrawal_uc: Arc<dyn UseCase<WithdrawalTransactionDTO, Vec<Transaction>>>,
     pub transfer_uc: Arc<dyn UseCase<TransferTransactionDTO, Vec<Transaction>>>,
+    pub statement_uc: Arc<dyn UseCase<StatementRequestDTO, Statement>>,
+    pub import_uc: Arc<dyn UseCase<BulkImportDTO, ImportReport>>,
+    pub search_transactions_uc: Arc<dyn UseCase<TransactionSearchDTO, TransactionPage>>,
+    pub get_transaction_by_id_uc: Arc<dyn UseCase<Uuid, Option<Transaction>>>,
+    pub get_transfer_uc: Arc<dyn UseCase<Uuid, Option<Transfer>>>,
+    pub health_check_uc: Arc<dyn UseCase<(), ()>>,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        create_account_uc: Arc<dyn UseCase<AccountCreationDTO, Account>>,
+        create_account_uc: Arc<dyn UseCase<AccountCreationDTO, AccountCreationResultDTO>>,
         get_account_by_id_uc: Arc<dyn UseCase<Uuid, Option<Account>>>,
         deposit_uc: Arc<dyn UseCase<DepositTransactionDTO, Vec<Transaction>>>,
         withdrawal_uc: Arc<dyn UseCase<WithdrawalTransactionDTO, Vec<Transaction>>>,
         transfer_uc: Arc<dyn UseCase<TransferTransactionDTO, Vec<Transaction>>>,
+        statement_uc: Arc<dyn UseCase<StatementRequestDTO, Statement>>,
+        import_uc: Arc<dyn UseCase<BulkImportDTO, ImportReport>>,
+        search_transactions_uc: Arc<dyn UseCase<TransactionSearchDTO, TransactionPage>>,
+        get_transaction_by_id_uc: Arc<dyn UseCase<Uuid, Option<Transaction>>>,
+        get_transfer_uc: Arc<dyn UseCase<Uuid, Option<Transfer>>>,
+        health_check_uc: Arc<dyn UseCase<(), ()>>,
     ) -> Self {
         AppState {
             create_account_uc,
@@ -33,6 +49,12 @@ impl AppState {
             deposit_uc,
             withdrawal_uc,
             transfer_uc,
+            statement_uc,
+            import_uc,
+            search_transactions_uc,
+            get_transaction_by_id_uc,
+            get_transfer_uc,
+            health_check_uc,
         }
     }
 }
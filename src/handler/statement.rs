@@ -0,0 +1,225 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use base::{
+    dto::statement::StatementRequestDTO,
+    model::{StatementEntry, StatementHeader, StatementLine, StatementSummary},
+};
+use chrono::{DateTime, Utc};
+use futures::{future, stream, StreamExt};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::state::AppState;
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub struct StatementQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    #[serde(default)]
+    pub format: StatementFormat,
+}
+
+const CSV_COLUMNS: [&str; 6] = [
+    "created_at",
+    "transaction_id",
+    "idempotency_key",
+    "entry",
+    "amount",
+    "balance",
+];
+
+fn csv_rows<R: AsRef<[u8]>>(rows: &[[R; 6]]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.write_record(row).map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+/// Rows after the transactions are dated at the end of the period.
+fn csv_entry(
+    header: &StatementHeader,
+    entry: &str,
+    amount: String,
+    balance: String,
+) -> [String; 6] {
+    [
+        header.to.to_rfc3339(),
+        String::new(),
+        String::new(),
+        entry.to_string(),
+        amount,
+        balance,
+    ]
+}
+
+fn csv_head(header: &StatementHeader) -> Result<String, String> {
+    let opening = [
+        header.from.to_rfc3339(),
+        String::new(),
+        String::new(),
+        "opening_balance".to_string(),
+        String::new(),
+        header.opening_balance.to_string(),
+    ];
+    Ok(csv_rows(&[CSV_COLUMNS])? + &csv_rows(&[opening])?)
+}
+
+fn csv_line(line: &StatementLine) -> Result<String, String> {
+    let tx = &line.transaction;
+    csv_rows(&[[
+        tx.created_at.to_rfc3339(),
+        tx.id.to_string(),
+        tx.idempotency_key.clone(),
+        "transaction".to_string(),
+        tx.amount.to_string(),
+        line.running_balance.to_string(),
+    ]])
+}
+
+fn csv_tail(header: &StatementHeader, summary: &StatementSummary) -> Result<String, String> {
+    csv_rows(&[
+        csv_entry(
+            header,
+            "total_in",
+            summary.total_in.to_string(),
+            String::new(),
+        ),
+        csv_entry(
+            header,
+            "total_out",
+            summary.total_out.to_string(),
+            String::new(),
+        ),
+        csv_entry(
+            header,
+            "closing_balance",
+            String::new(),
+            summary.closing_balance.to_string(),
+        ),
+    ])
+}
+
+/// Takes the place of the summary rows, so a statement that failed part way
+/// never ends with a closing balance.
+fn csv_error(header: &StatementHeader) -> String {
+    csv_rows(&[csv_entry(header, "error", String::new(), String::new())]).unwrap_or_default()
+}
+
+/// The JSON document is written incrementally: the header object is left open
+/// so the transactions array and the summary fields can be appended to it.
+fn json_head(header: &StatementHeader) -> Result<String, serde_json::Error> {
+    let mut head = serde_json::to_string(header)?;
+    head.pop();
+    Ok(head + r#","transactions":["#)
+}
+
+fn json_line(line: &StatementLine, first: bool) -> Result<String, serde_json::Error> {
+    let separator = if first { "" } else { "," };
+    Ok(format!("{}{}", separator, serde_json::to_string(line)?))
+}
+
+fn json_tail(summary: &StatementSummary) -> Result<String, serde_json::Error> {
+    let summary = serde_json::to_string(summary)?;
+    Ok(format!("],{}", &summary[1..]))
+}
+
+/// Closes the document with an `error` field in place of the summary.
+fn json_error(error: &str) -> String {
+    format!("],\"error\":{}}}", serde_json::Value::from(error))
+}
+
+/// Ends a body whose status was already sent, so the failure is logged here.
+fn trailer(format: StatementFormat, header: &StatementHeader, error: &str) -> String {
+    tracing::error!(%error, "statement failed after the response started");
+    match format {
+        StatementFormat::Json => json_error(error),
+        StatementFormat::Csv => csv_error(header),
+    }
+}
+
+#[get("/accounts/{param_uuid}/statement")]
+#[tracing::instrument(skip_all)]
+pub async fn get_account_statement(
+    state: web::Data<AppState>,
+    param_uuid: web::Path<Uuid>,
+    query: web::Query<StatementQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let input = StatementRequestDTO {
+        account_id: param_uuid.into_inner(),
+        from: query.from,
+        to: query.to,
+    };
+    let statement = match state.statement_uc.execute(input).await {
+        Ok(statement) => statement,
+        Err(error) if error == "Account not found" => return HttpResponse::NotFound().body(error),
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+
+    let header = statement.header;
+    let format = query.format;
+    let head = match format {
+        StatementFormat::Json => json_head(&header).map_err(|e| e.to_string()),
+        StatementFormat::Csv => csv_head(&header),
+    };
+    let head = match head {
+        Ok(head) => head,
+        Err(error) => return HttpResponse::InternalServerError().body(error),
+    };
+    // Reading the first page fails before the status is sent; later failures
+    // can only end the body with an error trailer.
+    let mut entries = statement.entries;
+    let first_entry = match entries.next().await {
+        Some(Err(error)) => return HttpResponse::InternalServerError().body(error),
+        first_entry => first_entry,
+    };
+
+    let mut first = true;
+    let tail_header = header.clone();
+    let body = stream::iter(first_entry)
+        .chain(entries)
+        .map(move |entry| match (format, entry?) {
+            (StatementFormat::Json, StatementEntry::Line(line)) => {
+                let chunk = json_line(&line, first);
+                first = false;
+                chunk.map_err(|e| e.to_string())
+            }
+            (StatementFormat::Json, StatementEntry::Summary(summary)) => {
+                json_tail(&summary).map_err(|e| e.to_string())
+            }
+            (StatementFormat::Csv, StatementEntry::Line(line)) => csv_line(&line),
+            (StatementFormat::Csv, StatementEntry::Summary(summary)) => {
+                csv_tail(&tail_header, &summary)
+            }
+        });
+    let chunks =
+        stream::once(async move { Ok(head) })
+            .chain(body)
+            .scan(false, move |failed, chunk| {
+                if *failed {
+                    return future::ready(None);
+                }
+                let chunk = chunk.unwrap_or_else(|error| {
+                    *failed = true;
+                    trailer(format, &header, &error)
+                });
+                future::ready(Some(Ok::<_, actix_web::Error>(web::Bytes::from(chunk))))
+            });
+
+    let content_type = match format {
+        StatementFormat::Json => "application/json",
+        StatementFormat::Csv => "text/csv",
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .streaming(chunks)
+}
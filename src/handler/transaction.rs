@@ -1,45 +1,131 @@
-use actix_web::{post, web, HttpResponse, Responder};
-use base::dto::transaction::{
-    DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO,
+use actix_web::{get, http::header::IfMatch, post, web, HttpResponse, Responder};
+use base::{
+    dto::{
+        search::TransactionSearchDTO,
+        transaction::{DepositTransactionDTO, TransferTransactionDTO, WithdrawalTransactionDTO},
+    },
+    model::Transaction,
 };
+use uuid::Uuid;
 
-use super::state::AppState;
+use super::{
+    state::AppState,
+    version::{etag, expected_version, transaction_error},
+};
 
 #[post("/deposits")]
+#[tracing::instrument(skip_all)]
 pub async fn create_deposit(
     state: web::Data<AppState>,
     deposit_request: web::Json<DepositTransactionDTO>,
+    if_match: Option<web::Header<IfMatch>>,
 ) -> impl Responder {
-    let result = state.deposit_uc.execute(deposit_request.into_inner()).await;
-    result
-        .map(|txs| HttpResponse::Created().json(txs))
-        .unwrap_or_else(|error| HttpResponse::InternalServerError().body(error))
+    let mut input = deposit_request.into_inner();
+    if let Err(error) = input.details.validate() {
+        return HttpResponse::BadRequest().body(error);
+    }
+    input.expected_version = match expected_version(if_match) {
+        Ok(version) => version,
+        Err(error) => return HttpResponse::from_error(error),
+    };
+    let result = state.deposit_uc.execute(input).await;
+    result.map(created).unwrap_or_else(transaction_error)
 }
 
 #[post("/withdrawals")]
+#[tracing::instrument(skip_all)]
 pub async fn create_withdrawal(
     state: web::Data<AppState>,
     withdrawal_request: web::Json<WithdrawalTransactionDTO>,
+    if_match: Option<web::Header<IfMatch>>,
 ) -> impl Responder {
-    let result = state
-        .withdrawal_uc
-        .execute(withdrawal_request.into_inner())
-        .await;
-    result
-        .map(|txs| HttpResponse::Created().json(txs))
-        .unwrap_or_else(|error| HttpResponse::InternalServerError().body(error))
+    let mut input = withdrawal_request.into_inner();
+    if let Err(error) = input.details.validate() {
+        return HttpResponse::BadRequest().body(error);
+    }
+    input.expected_version = match expected_version(if_match) {
+        Ok(version) => version,
+        Err(error) => return HttpResponse::from_error(error),
+    };
+    let result = state.withdrawal_uc.execute(input).await;
+    result.map(created).unwrap_or_else(transaction_error)
 }
 
+/// `If-Match` and the returned `ETag` refer to the source account.
 #[post("/transfers")]
+#[tracing::instrument(skip_all)]
 pub async fn create_transfer(
     state: web::Data<AppState>,
     transfer_request: web::Json<TransferTransactionDTO>,
+    if_match: Option<web::Header<IfMatch>>,
+) -> impl Responder {
+    let mut input = transfer_request.into_inner();
+    if let Err(error) = input.details.validate() {
+        return HttpResponse::BadRequest().body(error);
+    }
+    input.expected_version = match expected_version(if_match) {
+        Ok(version) => version,
+        Err(error) => return HttpResponse::from_error(error),
+    };
+    let result = state.transfer_uc.execute(input).await;
+    result.map(created).unwrap_or_else(transaction_error)
+}
+
+/// The first transaction is on the account the operation was addressed to,
+/// so its version becomes the `ETag`.
+fn created(txs: Vec<Transaction>) -> HttpResponse {
+    let mut response = HttpResponse::Created();
+    if let Some(tx) = txs.first() {
+        response.insert_header(etag(tx.account_version));
+    }
+    response.json(txs)
+}
+
+#[get("/transactions")]
+#[tracing::instrument(skip_all)]
+pub async fn search_transactions(
+    state: web::Data<AppState>,
+    query: web::Query<TransactionSearchDTO>,
 ) -> impl Responder {
     let result = state
-        .transfer_uc
-        .execute(transfer_request.into_inner())
+        .search_transactions_uc
+        .execute(query.into_inner())
         .await;
     result
-        .map(|txs| HttpResponse::Created().json(txs))
-        .unwrap_or_else(|error| HttpResponse::InternalServerError().body(error))
+        .map(|page| HttpResponse::Ok().json(page))
+        .unwrap_or_else(|error| HttpResponse::BadRequest().body(error))
+}
+
+#[get("/transactions/{transaction_id}")]
+#[tracing::instrument(skip_all)]
+pub async fn get_transaction_by_id(
+    state: web::Data<AppState>,
+    transaction_id: web::Path<Uuid>,
+) -> impl Responder {
+    let result = state
+        .get_transaction_by_id_uc
+        .execute(transaction_id.into_inner())
+        .await;
+    match result {
+        Ok(Some(transaction)) => HttpResponse::Ok().json(transaction),
+        Ok(None) => HttpResponse::NotFound().body("Transaction not found"),
+        Err(error) => HttpResponse::InternalServerError().body(error),
+    }
+}
+
+#[get("/transfers/{transfer_id}")]
+#[tracing::instrument(skip_all)]
+pub async fn get_transfer_by_id(
+    state: web::Data<AppState>,
+    transfer_id: web::Path<Uuid>,
+) -> impl Responder {
+    let result = state
+        .get_transfer_uc
+        .execute(transfer_id.into_inner())
+        .await;
+    match result {
+        Ok(Some(transfer)) => HttpResponse::Ok().json(transfer),
+        Ok(None) => HttpResponse::NotFound().body("Transfer not found"),
+        Err(error) => HttpResponse::InternalServerError().body(error),
+    }
 }
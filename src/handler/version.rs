@@ -0,0 +1,85 @@
+use actix_web::{
+    error::{ErrorBadRequest, ErrorPreconditionFailed},
+    http::header::{ETag, EntityTag, IfMatch},
+    web, Error, HttpResponse,
+};
+use base::{
+    model::ACCOUNT_VERSION_MISMATCH,
+    storage::{IDEMPOTENCY_KEY_REUSED, VERSION_CONFLICT},
+};
+use uuid::Uuid;
+
+/// Account versions are exposed as strong entity tags.
+pub fn etag(version: Uuid) -> ETag {
+    ETag(EntityTag::new_strong(version.to_string()))
+}
+
+/// Reads the account version a request is conditional on. `If-Match: *` is
+/// the same as no condition, since the account must exist anyway.
+pub fn expected_version(if_match: Option<web::Header<IfMatch>>) -> Result<Option<Uuid>, Error> {
+    let tags = match if_match.map(web::Header::into_inner) {
+        None | Some(IfMatch::Any) => return Ok(None),
+        Some(IfMatch::Items(tags)) => tags,
+    };
+    // A missing header parses as an empty list.
+    match tags.as_slice() {
+        [] => Ok(None),
+        [tag] if !tag.weak => Uuid::parse_str(tag.tag())
+            .map(Some)
+            .map_err(|_| ErrorPreconditionFailed(ACCOUNT_VERSION_MISMATCH)),
+        [_] => Err(ErrorPreconditionFailed(ACCOUNT_VERSION_MISMATCH)),
+        _ => Err(ErrorBadRequest("If-Match must name a single version")),
+    }
+}
+
+/// Invalid details are rejected by the handlers before the use case runs,
+/// so what reaches here are the use case's and the storage's own errors.
+pub fn transaction_error(error: String) -> HttpResponse {
+    match error.as_str() {
+        ACCOUNT_VERSION_MISMATCH => HttpResponse::PreconditionFailed().body(error),
+        // Still contended after every retry; the client may try again.
+        VERSION_CONFLICT => HttpResponse::Conflict().body(error),
+        // Retrying will not help: the key belongs to a different operation.
+        IDEMPOTENCY_KEY_REUSED | "Insufficient balance" => {
+            HttpResponse::UnprocessableEntity().body(error)
+        }
+        _ if error.ends_with("not found") => HttpResponse::NotFound().body(error),
+        _ => HttpResponse::InternalServerError().body(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    fn status(error: &str) -> StatusCode {
+        transaction_error(error.to_string()).status()
+    }
+
+    #[test]
+    fn test_transaction_error_status() {
+        assert_eq!(
+            status(ACCOUNT_VERSION_MISMATCH),
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(status(VERSION_CONFLICT), StatusCode::CONFLICT);
+        assert_eq!(
+            status(IDEMPOTENCY_KEY_REUSED),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            status("Insufficient balance"),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            status("Destination account not found"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("Storage unavailable"),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use base::{
+    dto::import::{BulkImportDTO, ImportFormat},
+    model::ImportStatus,
+};
+use clap::Args;
+use futures::StreamExt;
+
+use crate::handler::AppState;
+
+/// Arguments of `mini_ledger import`.
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    file: PathBuf,
+    /// Inferred from the file extension when omitted
+    #[arg(long, value_parser = ["jsonl", "csv"])]
+    format: Option<String>,
+    /// Line to resume from, as reported by an earlier run
+    #[arg(long, default_value_t = 0)]
+    resume_from: usize,
+    /// File for the per-line results instead of stdout
+    #[arg(long)]
+    report: Option<PathBuf>,
+}
+
+impl ImportArgs {
+    fn format(&self) -> ImportFormat {
+        let csv = match self.format.as_deref() {
+            Some(format) => format == "csv",
+            None => self.file.extension().is_some_and(|ext| ext == "csv"),
+        };
+        if csv {
+            ImportFormat::Csv
+        } else {
+            ImportFormat::Jsonl
+        }
+    }
+}
+
+/// Runs `mini_ledger import`, writing one JSON result per input line to the
+/// report file (or stdout) and failing if any line could not be applied.
+pub async fn run(state: &AppState, args: ImportArgs) -> io::Result<()> {
+    let content = std::fs::read_to_string(&args.file)?;
+    let mut output: Box<dyn Write> = match &args.report {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    let mut report = state
+        .import_uc
+        .execute(BulkImportDTO {
+            content,
+            format: args.format(),
+            resume_from: args.resume_from,
+        })
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let (mut total, mut failed) = (0, 0);
+    while let Some(result) = report.next().await {
+        total += 1;
+        if result.status == ImportStatus::Error {
+            failed += 1;
+        }
+        writeln!(output, "{}", serde_json::to_string(&result)?)?;
+    }
+    output.flush()?;
+
+    eprintln!("imported {} lines, {} failed", total - failed, failed);
+    if failed > 0 {
+        return Err(io::Error::other(format!("{} lines failed", failed)));
+    }
+    Ok(())
+}
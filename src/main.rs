@@ -1,25 +1,94 @@
-use actix_web::{web, App, HttpServer};
-use bootstrap::bootstrap;
-use std::env;
+use actix_web::{middleware::from_fn, web, App, HttpServer};
+use base::{shutdown::Workers, storage::Storage};
+use bootstrap::{bootstrap, close_storage};
+use clap::Parser;
+use config::{Cli, Command, Config};
+use std::{process, sync::Arc, time::Duration};
+use telemetry::{Telemetry, TelemetryConfig};
 
 mod bootstrap;
+mod config;
 pub mod handler;
+mod import;
+mod telemetry;
+
+/// Runs once no more requests are served: background workers get `deadline`
+/// to stop, then the storage is closed and dropped, and the remaining spans
+/// are exported.
+async fn shut_down(
+    workers: Workers,
+    storage: Arc<dyn Storage>,
+    telemetry: Telemetry,
+    deadline: Duration,
+) {
+    workers.shutdown(deadline).await;
+    close_storage(storage).await;
+    tracing::info!("Shutdown complete");
+    telemetry.shutdown();
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let state = bootstrap().await;
+    let (config, command) = Config::load(Cli::parse()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2)
+    });
+    let telemetry = telemetry::init(TelemetryConfig::from_settings(&config.telemetry))
+        .map_err(std::io::Error::other)?;
+    // Background workers, such as the WAL compactor, are spawned by
+    // `bootstrap`, each handed a `StopSignal`.
+    let mut workers = Workers::new();
+    let (state, storage) = match bootstrap(&config, &mut workers).await {
+        Ok(bootstrapped) => bootstrapped,
+        Err(err) => {
+            tracing::error!("{}", err);
+            telemetry.shutdown();
+            process::exit(1)
+        }
+    };
+
+    let deadline = Duration::from_secs(config.server.shutdown_timeout_secs);
+
+    if let Some(Command::Import(args)) = command {
+        let result = import::run(&state, args).await;
+        drop(state);
+        shut_down(workers, storage, telemetry, deadline).await;
+        return result;
+    }
 
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(handler::metrics::track_request))
+            .wrap(from_fn(handler::request_id::trace_request))
             .app_data(web::Data::new(state.clone()))
+            .app_data(web::PayloadConfig::new(handler::import::MAX_IMPORT_SIZE))
+            .service(handler::metrics::get_metrics)
+            .service(handler::health::get_liveness)
+            .service(handler::health::get_readiness)
             .service(handler::account::create_account)
             .service(handler::account::get_account_by_id)
+            .service(handler::statement::get_account_statement)
             .service(handler::transaction::create_deposit)
             .service(handler::transaction::create_withdrawal)
             .service(handler::transaction::create_transfer)
-    })
-    .bind(format!("127.0.0.1:{port}"))?
-    .run()
-    .await
+            .service(handler::transaction::search_transactions)
+            .service(handler::transaction::get_transaction_by_id)
+            .service(handler::transaction::get_transfer_by_id)
+            .service(handler::import::create_import)
+    });
+    let server = match config.server.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    // On SIGTERM or SIGINT the server stops accepting connections and waits
+    // up to the deadline for in-flight requests, and their use cases, to end.
+    let result = match server
+        .shutdown_timeout(deadline.as_secs())
+        .bind((config.server.host.as_str(), config.server.port))
+    {
+        Ok(server) => server.run().await,
+        Err(err) => Err(err),
+    };
+    shut_down(workers, storage, telemetry, deadline).await;
+    result
 }
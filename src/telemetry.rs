@@ -0,0 +1,159 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing_subscriber::{
+    fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+use crate::config::{LogFormat, TelemetrySettings};
+
+const SERVICE_NAME: &str = "mini_ledger";
+
+pub struct TelemetryConfig {
+    /// One JSON object per line instead of human readable text.
+    pub json: bool,
+    /// Base URL of an OTLP/HTTP collector; spans are exported to its
+    /// `/v1/traces` when set.
+    pub otlp_endpoint: Option<String>,
+}
+
+impl TelemetryConfig {
+    /// Verbosity is not configured here but read from `RUST_LOG`,
+    /// defaulting to `info`.
+    pub fn from_settings(settings: &TelemetrySettings) -> Self {
+        TelemetryConfig {
+            json: settings.log_format == LogFormat::Json,
+            otlp_endpoint: settings
+                .otlp_endpoint
+                .clone()
+                .filter(|endpoint| !endpoint.is_empty()),
+        }
+    }
+}
+
+/// Keeps the span exporter alive; `shutdown` flushes the spans it still holds.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", err);
+            }
+        }
+    }
+}
+
+fn otlp_provider(endpoint: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .map_err(|err| format!("Failed to build the OTLP exporter: {}", err))?;
+    let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Installs the global subscriber. Spans are logged when they close, with
+/// how long they took.
+pub fn init(config: TelemetryConfig) -> Result<Telemetry, String> {
+    let fmt = tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE);
+    let fmt = if config.json {
+        fmt.json().with_current_span(true).boxed()
+    } else {
+        fmt.boxed()
+    };
+    let provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(otlp_provider)
+        .transpose()?;
+    let otlp = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(fmt)
+        .with(otlp)
+        .try_init()
+        .map_err(|err| format!("Failed to install the tracing subscriber: {}", err))?;
+    Ok(Telemetry { provider })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+
+    /// Accepts one request and answers it like an OTLP collector, sending
+    /// back its request line and body size.
+    fn collector_stub() -> (String, mpsc::Receiver<(String, usize)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            let (head_len, content_length) = loop {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    break (end + 4, length);
+                }
+            };
+            while request.len() < head_len + content_length {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            let request_line = String::from_utf8_lossy(&request)
+                .lines()
+                .next()
+                .unwrap()
+                .to_string();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            sender.send((request_line, content_length)).unwrap();
+        });
+        (endpoint, receiver)
+    }
+
+    #[test]
+    fn test_exports_spans_to_otlp_collector() {
+        let (endpoint, received) = collector_stub();
+        let provider = otlp_provider(&endpoint).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("http_request", request_id = "abc-123").in_scope(|| {});
+        });
+        provider.force_flush().unwrap();
+
+        let (request_line, body_len) = received.recv().unwrap();
+        assert_eq!(request_line, "POST /v1/traces HTTP/1.1");
+        assert!(body_len > 0);
+        provider.shutdown().unwrap();
+    }
+}